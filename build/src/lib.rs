@@ -9,10 +9,24 @@ pub use dune::Dune;
 use syn::MetaList;
 use syn::__private::ToTokens;
 
+struct Module {
+    name: String,
+    type_name: String,
+    functions: Vec<String>,
+}
+
+struct Exception {
+    name: String,
+    sig: Option<String>,
+}
+
 struct Source {
     path: PathBuf,
+    exceptions: Vec<Exception>,
     functions: Vec<String>,
     types: Vec<String>,
+    type_names: Vec<String>,
+    modules: Vec<Module>,
 }
 
 pub struct Sigs {
@@ -21,21 +35,178 @@ pub struct Sigs {
     source: Vec<Source>,
 }
 
+impl Exception {
+    fn declaration(&self) -> String {
+        match &self.sig {
+            Some(sig) => format!("exception {} of {sig}", self.name),
+            None => format!("exception {}", self.name),
+        }
+    }
+}
+
+// Get the major version of the OCaml compiler, this uses the same environment variables as
+// ocaml-sys
+fn ocaml_major_version() -> Option<u32> {
+    let version = match std::env::var("OCAML_VERSION") {
+        Ok(version) => version,
+        Err(_) => {
+            let ocamlopt = std::env::var("OCAMLOPT").unwrap_or_else(|_| "ocamlopt".to_string());
+            let output = std::process::Command::new(ocamlopt)
+                .arg("-version")
+                .output()
+                .ok()?;
+            String::from_utf8(output.stdout).ok()?
+        }
+    };
+    version.trim().split('.').next()?.parse().ok()
+}
+
 fn strip_quotes(s: &str) -> &str {
     s.trim_start_matches('"').trim_end_matches('"')
 }
 
 fn snake_case(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
     let mut dest = String::new();
-    for c in s.chars() {
-        if !dest.is_empty() && c.is_uppercase() {
-            dest.push('_');
+    for (i, c) in chars.iter().enumerate() {
+        // Runs of uppercase letters are kept together, `HTTPServer` becomes `http_server`
+        if i > 0 && c.is_uppercase() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|x| x.is_lowercase());
+            if !prev.is_uppercase() || next_lower {
+                dest.push('_');
+            }
         }
         dest.push(c.to_ascii_lowercase());
     }
     dest
 }
 
+// Rename functions that would conflict with OCaml keywords
+fn ocaml_ident(s: &str) -> String {
+    const KEYWORDS: &[&str] = &[
+        "and",
+        "as",
+        "assert",
+        "begin",
+        "class",
+        "constraint",
+        "do",
+        "done",
+        "downto",
+        "else",
+        "end",
+        "exception",
+        "external",
+        "false",
+        "for",
+        "fun",
+        "function",
+        "functor",
+        "if",
+        "in",
+        "include",
+        "inherit",
+        "initializer",
+        "lazy",
+        "let",
+        "match",
+        "method",
+        "module",
+        "mutable",
+        "new",
+        "nonrec",
+        "object",
+        "of",
+        "open",
+        "or",
+        "private",
+        "rec",
+        "sig",
+        "struct",
+        "then",
+        "to",
+        "true",
+        "try",
+        "type",
+        "val",
+        "virtual",
+        "when",
+        "while",
+        "with",
+    ];
+    if KEYWORDS.contains(&s) {
+        format!("{s}_")
+    } else {
+        s.to_string()
+    }
+}
+
+fn is_methods_attr(attr: &syn::Attribute) -> bool {
+    let attr_name = attr
+        .path()
+        .segments
+        .iter()
+        .map(|x| x.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::");
+    attr_name == "methods" || attr_name == "ocaml::methods"
+}
+
+// Check for `#[derive(IntoException)]` or `#[derive(ocaml::IntoException)]`
+fn is_into_exception(attrs: &[syn::Attribute]) -> bool {
+    let mut found = false;
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("derive")) {
+        let _ = attr.parse_nested_meta(|meta| {
+            if meta
+                .path
+                .segments
+                .last()
+                .map(|x| x.ident == "IntoException")
+                == Some(true)
+            {
+                found = true;
+            }
+            Ok(())
+        });
+    }
+    found
+}
+
+// Get the exception declared by an `IntoException` variant
+fn exception(variant: &syn::Variant) -> Exception {
+    let mut exn = Exception {
+        name: variant.ident.to_string(),
+        sig: None,
+    };
+
+    for attr in variant
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("exception"))
+    {
+        attr.parse_nested_meta(|meta| {
+            let value: syn::LitStr = meta.value()?.parse()?;
+            if meta.path.is_ident("name") {
+                exn.name = value.value();
+            } else if meta.path.is_ident("sig") {
+                exn.sig = Some(value.value());
+            }
+            Ok(())
+        })
+        .unwrap_or_else(|e| panic!("Invalid exception attribute: {e}"));
+    }
+
+    if exn.sig.is_none() && !variant.fields.is_empty() {
+        panic!(
+            "Missing exception signature for {}, use #[exception(sig = \"...\")]",
+            variant.ident
+        );
+    }
+
+    exn
+}
+
 fn handle(attrs: Vec<syn::Attribute>, mut f: impl FnMut(&str)) {
     for attr in attrs {
         let attr_name = attr
@@ -104,8 +275,11 @@ impl Sigs {
             let path = file.path();
             let mut src = Source {
                 path: path.clone(),
+                exceptions: Vec::new(),
                 functions: Vec::new(),
                 types: Vec::new(),
+                type_names: Vec::new(),
+                modules: Vec::new(),
             };
             let s = std::fs::read_to_string(&path)?;
             let t: syn::File = syn::parse_str(&s)
@@ -135,9 +309,13 @@ impl Sigs {
                                 format!("type {name} = {ty}")
                             };
                             src.types.push(def);
+                            src.type_names.push(name.clone());
                         });
                     }
                     syn::Item::Enum(item) => {
+                        if is_into_exception(&item.attrs) {
+                            src.exceptions.extend(item.variants.iter().map(exception));
+                        }
                         let name = snake_case(&item.ident.to_string());
                         handle(item.attrs, |ty| {
                             let def = if ty.is_empty() {
@@ -146,17 +324,62 @@ impl Sigs {
                                 format!("type {name} = {ty}")
                             };
                             src.types.push(def);
+                            src.type_names.push(name.clone());
                         });
                     }
                     syn::Item::Type(item) => {
                         let name = snake_case(&item.ident.to_string());
-                        handle(item.attrs, |_ty| src.types.push(format!("type {name}")));
+                        handle(item.attrs, |_ty| {
+                            src.types.push(format!("type {name}"));
+                            src.type_names.push(name.clone());
+                        });
+                    }
+                    syn::Item::Impl(item) if item.attrs.iter().any(is_methods_attr) => {
+                        let name = match item.self_ty.as_ref() {
+                            syn::Type::Path(p) => p.path.segments.last().unwrap().ident.to_string(),
+                            _ => continue,
+                        };
+                        let type_name = snake_case(&name);
+                        let mut module = Module {
+                            name,
+                            type_name: type_name.clone(),
+                            functions: Vec::new(),
+                        };
+                        for item in item.items {
+                            let item_fn = match item {
+                                syn::ImplItem::Fn(item_fn) => item_fn,
+                                _ => continue,
+                            };
+                            if !matches!(item_fn.vis, syn::Visibility::Public(_)) {
+                                continue;
+                            }
+                            let method = item_fn.sig.ident.to_string();
+                            let name = format!("{type_name}_{method}");
+                            let method = ocaml_ident(&method);
+                            handle(item_fn.attrs, |ty| {
+                                let def = if item_fn.sig.inputs.len() > 5 {
+                                    format!(
+                                        "external {method}: {ty} = \"{name}_bytecode\" \"{name}\""
+                                    )
+                                } else {
+                                    format!("external {method}: {ty} = \"{name}\"")
+                                };
+                                module.functions.push(def);
+                            });
+                        }
+                        if !module.functions.is_empty() {
+                            src.modules.push(module);
+                        }
                     }
                     _ => (),
                 }
             }
 
-            if !src.functions.is_empty() || !src.types.is_empty() {
+            if !src.functions.is_empty()
+                || !src.types.is_empty()
+                || !src.modules.is_empty()
+                || !src.exceptions.is_empty()
+            {
                 self.source.push(src);
             }
         }
@@ -164,12 +387,78 @@ impl Sigs {
         Ok(())
     }
 
+    // Modules use the type generated by `ocaml::sig` when there is one
+    fn module_type(&self, type_name: &str) -> String {
+        let exists = self
+            .source
+            .iter()
+            .any(|src| src.type_names.iter().any(|x| x == type_name));
+        if exists {
+            format!("type t = {type_name}")
+        } else {
+            "type t".to_string()
+        }
+    }
+
     fn generate_ml(&mut self) -> Result<(), std::io::Error> {
         let mut f = std::fs::File::create(&self.output).unwrap();
 
         writeln!(f, "(* Generated by ocaml-rs *)\n")?;
         writeln!(f, "open! Bigarray")?;
 
+        // Runtime helpers used by ocaml-rs, these are always included so they're available to any
+        // crate that depends on ocaml-rs, not just the one generating the bindings
+
+        // Used by ocaml-rs to create OCaml closures from Rust functions
+        writeln!(
+            f,
+            "\nexternal ocaml_rs_closure_call: 'a -> 'b -> 'c = \"ocaml_rs_closure_call\""
+        )?;
+        writeln!(
+            f,
+            "let () = Callback.register \"ocaml_rs_closure\" ocaml_rs_closure_call"
+        )?;
+        for n in 2..=6 {
+            let args = (0..n).map(|i| format!("x{i}")).collect::<Vec<_>>();
+            writeln!(
+                f,
+                "let () = Callback.register \"ocaml_rs_closure{n}\" (fun env {} -> ocaml_rs_closure_call env ({}))",
+                args.join(" "),
+                args.join(", ")
+            )?;
+        }
+
+        // Used by `Seq::from_iter` to memoize each node
+        writeln!(
+            f,
+            "\nlet () = Callback.register \"ocaml_rs_seq_memo\" (fun f -> let l = lazy (f ()) in fun () -> Lazy.force l)"
+        )?;
+
+        // Used by `InChannel` and `OutChannel` to catch I/O exceptions
+        writeln!(f, "\nlet () = Callback.register \"ocaml_rs_input\" input")?;
+        writeln!(
+            f,
+            "let () = Callback.register \"ocaml_rs_output\" output_substring"
+        )?;
+        writeln!(f, "let () = Callback.register \"ocaml_rs_flush\" flush")?;
+
+        // Raised for Rust panics when using `PanicPolicy::Exception`
+        writeln!(f, "\nexception Rust_panic of string * string")?;
+        writeln!(
+            f,
+            "let () = Callback.register_exception \"Rust_panic\" (Rust_panic (\"\", \"\"))"
+        )?;
+
+        // Used by `ocaml::domain::spawn` and `Domain::join`, the `Domain` module is only
+        // available in OCaml 5
+        if ocaml_major_version().is_some_and(|v| v >= 5) {
+            writeln!(
+                f,
+                "\nlet () = Callback.register \"Domain.spawn\" Domain.spawn"
+            )?;
+            writeln!(f, "let () = Callback.register \"Domain.join\" Domain.join")?;
+        }
+
         for src in &self.source {
             writeln!(
                 f,
@@ -181,9 +470,27 @@ impl Sigs {
                 writeln!(f, "{t}")?;
             }
 
+            for exn in &src.exceptions {
+                writeln!(f, "{}", exn.declaration())?;
+                writeln!(
+                    f,
+                    "let () = Callback.register_exception \"{0}\" (Obj.magic [%extension_constructor {0}] : exn)",
+                    exn.name
+                )?;
+            }
+
             for func in &src.functions {
                 writeln!(f, "{func}")?;
             }
+
+            for m in &src.modules {
+                writeln!(f, "\nmodule {} = struct", m.name)?;
+                writeln!(f, "  {}", self.module_type(&m.type_name))?;
+                for func in &m.functions {
+                    writeln!(f, "  {func}")?;
+                }
+                writeln!(f, "end")?;
+            }
         }
 
         Ok(())
@@ -196,6 +503,8 @@ impl Sigs {
         writeln!(f, "(* Generated by ocaml-rs *)\n")?;
         writeln!(f, "open! Bigarray")?;
 
+        writeln!(f, "\nexception Rust_panic of string * string")?;
+
         for src in &self.source {
             writeln!(
                 f,
@@ -207,9 +516,22 @@ impl Sigs {
                 writeln!(f, "{t}")?;
             }
 
+            for exn in &src.exceptions {
+                writeln!(f, "{}", exn.declaration())?;
+            }
+
             for func in &src.functions {
                 writeln!(f, "{func}")?;
             }
+
+            for m in &src.modules {
+                writeln!(f, "\nmodule {} : sig", m.name)?;
+                writeln!(f, "  {}", self.module_type(&m.type_name))?;
+                for func in &m.functions {
+                    writeln!(f, "  {func}")?;
+                }
+                writeln!(f, "end")?;
+            }
         }
 
         Ok(())
@@ -2,7 +2,7 @@
 extern crate proc_macro;
 
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{quote, ToTokens};
 
 fn check_func(item_fn: &mut syn::ItemFn) {
     if item_fn.sig.asyncness.is_some() {
@@ -136,6 +136,35 @@ pub fn ocaml_sig(attribute: TokenStream, item: TokenStream) -> TokenStream {
     item
 }
 
+#[derive(Default)]
+struct FuncAttrs {
+    gc_name: Option<syn::Ident>,
+    checked: bool,
+    release_lock: bool,
+}
+
+// Parse the arguments passed to `ocaml::func`
+fn func_attrs(attribute: TokenStream) -> FuncAttrs {
+    use syn::parse::Parser;
+
+    let mut acc = FuncAttrs::default();
+    let idents = syn::punctuated::Punctuated::<syn::Ident, syn::Token![,]>::parse_terminated
+        .parse(attribute)
+        .expect("invalid arguments to ocaml::func");
+    for ident in idents {
+        if ident == "checked" {
+            acc.checked = true;
+        } else if ident == "release_lock" {
+            acc.release_lock = true;
+        } else if acc.gc_name.is_none() {
+            acc.gc_name = Some(ident);
+        } else {
+            panic!("invalid argument to ocaml::func: {ident}");
+        }
+    }
+    acc
+}
+
 /// `func` is used export Rust functions to OCaml, performing the necessary wrapping/unwrapping
 /// automatically.
 ///
@@ -144,6 +173,12 @@ pub fn ocaml_sig(attribute: TokenStream, item: TokenStream) -> TokenStream {
 /// - Defines a bytecode function automatically for functions that take more than 5 arguments. The
 ///   bytecode function for `my_func` would be `my_func_bytecode`
 /// - Allows for an optional ident argument specifying the name of the `gc` handle parameter
+/// - `#[ocaml::func(checked)]` converts arguments using `TryFromValue`, raising `Invalid_argument`
+///   when an argument doesn't have the expected shape
+/// - `#[ocaml::func(release_lock)]` releases the runtime lock while the function body runs,
+///   arguments are converted before the lock is released and the return value is converted after
+///   it has been reacquired. All argument and return types must implement `ocaml::Owned`, errors
+///   can be returned using `ocaml::OwnedError`
 #[proc_macro_attribute]
 pub fn ocaml_func(attribute: TokenStream, item: TokenStream) -> TokenStream {
     let mut item_fn: syn::ItemFn = syn::parse(item).unwrap();
@@ -155,7 +190,11 @@ pub fn ocaml_func(attribute: TokenStream, item: TokenStream) -> TokenStream {
     let mut gc_name = syn::Ident::new("gc", name.span());
     let mut use_gc = quote!({let _ = &#gc_name;});
 
-    if let Ok(ident) = syn::parse::<syn::Ident>(attribute) {
+    let func_attrs = func_attrs(attribute);
+    if let Some(ident) = func_attrs.gc_name {
+        if func_attrs.release_lock {
+            panic!("{name}: release_lock functions cannot access the runtime handle");
+        }
         gc_name = ident;
         use_gc = quote!();
     }
@@ -199,16 +238,39 @@ pub fn ocaml_func(attribute: TokenStream, item: TokenStream) -> TokenStream {
         })
         .collect();
 
-    let convert_params: Vec<_> = args
-        .iter()
-        .filter_map(|arg| match arg {
-            Some(ident) => {
-                let ident = ident.ident.clone();
-                Some(quote! { let #ident = ocaml::FromValue::from_value(unsafe { ocaml::Value::new(#ident).root() }); })
-            }
-            None => None,
-        })
-        .collect();
+    let convert_params: Vec<_> = if func_attrs.checked {
+        // Convert all arguments before raising, so nothing is leaked when `Invalid_argument` is
+        // raised
+        let fn_name = name.to_string();
+        let checked: Vec<_> = param_names
+            .iter()
+            .map(|ident| {
+                let arg_name = ident.to_string();
+                quote! {
+                    ocaml::TryFromValue::try_from_value(unsafe { ocaml::Value::new(#ident).root() })
+                        .map_err(|e| format!("{}: argument `{}`: {}", #fn_name, #arg_name, e))?
+                }
+            })
+            .collect();
+        let names = param_names.iter();
+        vec![quote! {
+            #[allow(clippy::redundant_closure_call)]
+            let (#(#names,)*) = match (|| -> Result<_, String> { Ok((#(#checked,)*)) })() {
+                Ok(x) => x,
+                Err(e) => ocaml::Error::raise_invalid_argument(e),
+            };
+        }]
+    } else {
+        args.iter()
+            .filter_map(|arg| match arg {
+                Some(ident) => {
+                    let ident = ident.ident.clone();
+                    Some(quote! { let #ident = ocaml::FromValue::from_value(unsafe { ocaml::Value::new(#ident).root() }); })
+                }
+                None => None,
+            })
+            .collect()
+    };
 
     if ocaml_args.is_empty() {
         ocaml_args.push(quote! { _: ocaml::Raw});
@@ -216,7 +278,15 @@ pub fn ocaml_func(attribute: TokenStream, item: TokenStream) -> TokenStream {
 
     let body = &item_fn.block;
 
-    let inner = if returns {
+    let inner = if func_attrs.release_lock {
+        let output = &item_fn.sig.output;
+        quote! {
+            #[inline(always)]
+            #constness #unsafety fn inner(#(#rust_args),*) #output {
+                #body
+            }
+        }
+    } else if returns {
         quote! {
             #[inline(always)]
             #constness #unsafety fn inner(#gc_name: &ocaml::Runtime, #(#rust_args),*) -> #rust_return_type {
@@ -234,6 +304,27 @@ pub fn ocaml_func(attribute: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
+    let call_inner = if func_attrs.release_lock {
+        // Make sure nothing that references the OCaml heap is used without the runtime lock
+        let types = item_fn
+            .sig
+            .inputs
+            .iter()
+            .filter_map(|arg| match arg {
+                syn::FnArg::Typed(t) => Some(&t.ty),
+                _ => None,
+            })
+            .chain(rust_return_type);
+        quote! {
+            let _ = &#gc_name;
+            #(ocaml::runtime::assert_owned::<#types>();)*
+            #[allow(unused_unsafe)]
+            let res = unsafe { ocaml::runtime::release_lock(move || inner(#param_names)) };
+        }
+    } else {
+        quote!(let res = inner(#gc_name, #param_names);)
+    };
+
     let where_clause = &item_fn.sig.generics.where_clause;
     let attr: Vec<_> = item_fn.attrs.iter().collect();
 
@@ -247,7 +338,7 @@ pub fn ocaml_func(attribute: TokenStream, item: TokenStream) -> TokenStream {
 
             ocaml::body!(#gc_name: {
                 #(#convert_params);*
-                let res = inner(#gc_name, #param_names);
+                #call_inner
                 #[allow(unused_unsafe)]
                 let mut gc_ = unsafe { ocaml::Runtime::recover_handle() };
                 unsafe { ocaml::ToValue::to_value(&res, &gc_).raw() }
@@ -283,6 +374,152 @@ pub fn ocaml_func(attribute: TokenStream, item: TokenStream) -> TokenStream {
     gen.into()
 }
 
+// Replace `Self` with the concrete type, since methods are exported as free functions
+fn replace_self(tokens: proc_macro2::TokenStream, ty: &syn::Type) -> proc_macro2::TokenStream {
+    tokens
+        .into_iter()
+        .flat_map(|tt| match tt {
+            proc_macro2::TokenTree::Ident(ident) if ident == "Self" => quote!(#ty),
+            proc_macro2::TokenTree::Group(group) => {
+                let mut g =
+                    proc_macro2::Group::new(group.delimiter(), replace_self(group.stream(), ty));
+                g.set_span(group.span());
+                proc_macro2::TokenTree::Group(g).into()
+            }
+            tt => tt.into(),
+        })
+        .collect()
+}
+
+fn is_sig_attr(attr: &syn::Attribute) -> bool {
+    let s = attr
+        .path()
+        .segments
+        .iter()
+        .map(|x| x.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::");
+    s == "ocaml::sig" || s == "sig"
+}
+
+/// `methods` exports each `pub fn` in an `impl` block to OCaml using `ocaml::func`, the function
+/// for `Type::method` is named `type_method` and `&self`/`&mut self` are passed as the custom
+/// value. `#[ocaml::sig]` can be used on each method to add it to the module generated by
+/// `ocaml-build`
+///
+/// - `#[ocaml::methods(checked)]` converts all arguments using `TryFromValue`, like
+///   `#[ocaml::func(checked)]`
+#[proc_macro_attribute]
+pub fn ocaml_methods(attribute: TokenStream, item: TokenStream) -> TokenStream {
+    let mut item_impl: syn::ItemImpl = syn::parse(item).unwrap();
+
+    let func_attrs = func_attrs(attribute);
+    if func_attrs.gc_name.is_some() || func_attrs.release_lock {
+        panic!("ocaml::methods only accepts the `checked` argument");
+    }
+    let func_attr = if func_attrs.checked {
+        quote!(#[ocaml::func(checked)])
+    } else {
+        quote!(#[ocaml::func])
+    };
+
+    if item_impl.trait_.is_some() {
+        panic!("ocaml::methods cannot be used on trait implementations");
+    }
+
+    if !item_impl.generics.params.is_empty() {
+        panic!("ocaml::methods may not contain generics");
+    }
+
+    let self_ty = item_impl.self_ty.as_ref().clone();
+    let type_name = match &self_ty {
+        syn::Type::Path(p) => p.path.segments.last().unwrap().ident.to_string(),
+        _ => panic!("ocaml::methods must be used on a named type"),
+    };
+
+    let mut funcs = Vec::new();
+    for item in item_impl.items.iter_mut() {
+        let method = match item {
+            syn::ImplItem::Fn(method) => method,
+            _ => continue,
+        };
+
+        method.attrs.retain(|attr| !is_sig_attr(attr));
+
+        if !matches!(method.vis, syn::Visibility::Public(_)) {
+            continue;
+        }
+
+        if !method.sig.generics.params.is_empty() {
+            panic!("OCaml functions may not contain generics");
+        }
+
+        let method_name = &method.sig.ident;
+        let name = syn::Ident::new(
+            &format!("{}_{method_name}", snake_case(&type_name)),
+            method_name.span(),
+        );
+
+        let mut params = Vec::new();
+        let mut call_args = Vec::new();
+        for (i, arg) in method.sig.inputs.iter().enumerate() {
+            match arg {
+                syn::FnArg::Receiver(r) => {
+                    if r.reference.is_none() {
+                        panic!("{type_name}::{method_name}: self must be passed by reference");
+                    }
+                    let mutability = &r.mutability;
+                    params.push(quote!(self_: &#mutability #self_ty));
+                    call_args.push(quote!(self_));
+                }
+                syn::FnArg::Typed(t) => {
+                    let ident = match t.pat.as_ref() {
+                        syn::Pat::Ident(ident) => ident.ident.clone(),
+                        _ => syn::Ident::new(&format!("arg{i}"), method_name.span()),
+                    };
+                    let ty = replace_self(t.ty.to_token_stream(), &self_ty);
+                    params.push(quote!(#ident: #ty));
+                    call_args.push(quote!(#ident));
+                }
+            }
+        }
+
+        let output = replace_self(method.sig.output.to_token_stream(), &self_ty);
+        let unsafety = &method.sig.unsafety;
+
+        funcs.push(quote! {
+            #func_attr
+            pub #unsafety fn #name(#(#params),*) #output {
+                <#self_ty>::#method_name(#(#call_args),*)
+            }
+        });
+    }
+
+    let gen = quote! {
+        #item_impl
+
+        #(#funcs)*
+    };
+    gen.into()
+}
+
+fn snake_case(s: &str) -> String {
+    let chars: Vec<char> = s.chars().collect();
+    let mut dest = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        // Runs of uppercase letters are kept together, `HTTPServer` becomes `http_server`
+        if i > 0 && c.is_uppercase() {
+            let prev = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|x| x.is_lowercase());
+            if !prev.is_uppercase() || next_lower {
+                dest.push('_');
+            }
+        }
+        dest.push(c.to_ascii_lowercase());
+    }
+    dest
+}
+
 /// `native_func` is used export Rust functions to OCaml, it has much lower overhead than `func`
 /// and expects all arguments and return type to to be `Value`.
 ///
@@ -485,7 +722,7 @@ fn ocaml_bytecode_func_impl(
                 Some(ident) => Some(quote! {
                     #[allow(clippy::not_unsafe_ptr_arg_deref)]
                     let #ident = ocaml::FromValue::from_value(unsafe {
-                        Value::new(core::ptr::read(__ocaml_argv.add(__ocaml_arg_index as usize))).root()
+                        ocaml::Value::new(core::ptr::read(__ocaml_argv.add(__ocaml_arg_index as usize))).root()
                     });
                     __ocaml_arg_index += 1 ;
                 }),
@@ -500,7 +737,8 @@ fn ocaml_bytecode_func_impl(
             pub #constness unsafe extern "C" fn #name(__ocaml_argv: *mut ocaml::Raw, __ocaml_argc: i32) -> ocaml::Raw #where_clause {
                 assert!(#len <= __ocaml_argc as usize, "len: {}, argc: {}", #len, __ocaml_argc);
 
-                let #gc_name = unsafe { ocaml::Runtime::recover_handle() };
+                // Panics are handled the same way as `func` and `native_func`
+                let #gc_name = &ocaml::Runtime::init();
 
                 #inner
 
@@ -527,8 +765,9 @@ fn ocaml_bytecode_func_impl(
                 #attr
             )*
             pub #constness #unsafety extern "C" fn #name(#(#ocaml_args),*) -> ocaml::Raw #where_clause {
+                // Panics are handled the same way as `func` and `native_func`
                 #[allow(unused_variables)]
-                let #gc_name = unsafe { ocaml::Runtime::recover_handle() };
+                let #gc_name = &ocaml::Runtime::init();
 
                 #inner
 
@@ -759,6 +998,196 @@ pub fn derive_from_value(item: TokenStream) -> TokenStream {
     }
 }
 
+/// Derive `ocaml::TryFromValue`
+#[proc_macro_derive(TryFromValue, attributes(float_array, unboxed))]
+pub fn derive_try_from_value(item: TokenStream) -> TokenStream {
+    if let Ok(item_struct) = syn::parse::<syn::ItemStruct>(item.clone()) {
+        let attrs = attrs(&item_struct.attrs);
+        let g = item_struct.generics;
+        let name = item_struct.ident;
+        let expected = name.to_string();
+
+        // Tuple structs have unnamed fields
+        let tuple_struct = item_struct.fields.is_empty()
+            || item_struct.fields.iter().take(1).all(|x| x.ident.is_none());
+
+        // This is true when all struct fields are `float`s
+        let is_double_array_struct =
+            attrs.float_array || is_double_array_struct(&item_struct.fields);
+
+        if attrs.unboxed && item_struct.fields.len() > 1 {
+            panic!("cannot unbox structs with more than 1 field")
+        }
+
+        let fields = item_struct.fields.iter().enumerate().map(|(index, field)| {
+            let segment = match &field.ident {
+                Some(name) => {
+                    let name = name.to_string();
+                    quote!(ocaml::PathSegment::Field(#name))
+                }
+                None => quote!(ocaml::PathSegment::Index(#index)),
+            };
+            let x = if is_double_array_struct {
+                let ty = &field.ty;
+                quote!(value.double_field(#index) as #ty)
+            } else if attrs.unboxed {
+                quote!(ocaml::TryFromValue::try_from_value(value.clone())?)
+            } else {
+                quote!(ocaml::TryFromValue::try_from_value(value.field(#index))
+                    .map_err(|e| e.at(#segment))?)
+            };
+            match &field.ident {
+                Some(name) => quote!(#name: #x),
+                None => x,
+            }
+        });
+
+        let inner = if tuple_struct {
+            quote!(Self(#(#fields),*))
+        } else {
+            quote!(Self{#(#fields),*})
+        };
+
+        let n = item_struct.fields.len();
+        let check = if attrs.unboxed {
+            quote!()
+        } else if is_double_array_struct {
+            quote!(ocaml::ConversionError::expect_block(&value, #expected, ocaml::Tag::DOUBLE_ARRAY, Some(#n))?;)
+        } else {
+            quote!(ocaml::ConversionError::expect_block(&value, #expected, ocaml::Tag(0), Some(#n))?;)
+        };
+
+        let (g_impl, g_ty, g_wh) = g.split_for_impl();
+
+        // Generate TryFromValue for structs
+        quote! {
+            unsafe impl #g_impl ocaml::TryFromValue for #name #g_ty #g_wh {
+                fn try_from_value(value: ocaml::Value) -> Result<Self, ocaml::ConversionError> {
+                    unsafe {
+                        #check
+                        Ok(#inner)
+                    }
+                }
+            }
+        }
+        .into()
+    } else if let Ok(item_enum) = syn::parse::<syn::ItemEnum>(item) {
+        let g = item_enum.generics;
+        let name = item_enum.ident;
+        let expected = format!("variant of {name}");
+        let attrs = attrs(&item_enum.attrs);
+        let mut unit_tag = 0u8;
+        let mut non_unit_tag = 0u8;
+        if attrs.unboxed && item_enum.variants.len() > 1 {
+            panic!("cannot unbox enums with more than 1 variant")
+        }
+        let variants = item_enum.variants.iter().map(|variant| {
+            let arity = variant.fields.len();
+            let is_block = arity != 0;
+            let tag_ref = if arity > 0 {
+                &mut non_unit_tag
+            } else {
+                &mut unit_tag
+            };
+
+            // Get current tag index and increment the tag for next time
+            let tag = *tag_ref;
+            *tag_ref += 1;
+
+            let v_name = &variant.ident;
+            let v_name_str = v_name.to_string();
+
+            // Tuple enums have unnamed fields
+            let tuple_enum = variant.fields.is_empty()
+                || variant.fields.iter().take(1).all(|x| x.ident.is_none());
+
+            // Handle enums with no fields first
+            if arity == 0 {
+                return quote! {
+                    (#is_block, #tag) => Ok(#name::#v_name)
+                };
+            }
+
+            let fields = variant.fields.iter().enumerate().map(|(index, field)| {
+                let segment = match &field.ident {
+                    Some(name) => {
+                        let name = name.to_string();
+                        quote!(ocaml::PathSegment::Field(#name))
+                    }
+                    None => quote!(ocaml::PathSegment::Index(#index)),
+                };
+                let x = if attrs.unboxed {
+                    quote!(ocaml::TryFromValue::try_from_value(value.clone())
+                        .map_err(|e| e.at(ocaml::PathSegment::Variant(#v_name_str)))?)
+                } else {
+                    quote!(ocaml::TryFromValue::try_from_value(value.field(#index))
+                        .map_err(|e| e.at(#segment).at(ocaml::PathSegment::Variant(#v_name_str)))?)
+                };
+                match &field.ident {
+                    Some(name) => quote!(#name: #x),
+                    None => x,
+                }
+            });
+            let inner = if tuple_enum {
+                quote!(#name::#v_name(#(#fields),*))
+            } else {
+                quote!(#name::#v_name{#(#fields),*})
+            };
+
+            let check = if attrs.unboxed {
+                quote!()
+            } else {
+                quote!(ocaml::ConversionError::expect_block(&value, #expected, ocaml::Tag(#tag), Some(#arity))?;)
+            };
+
+            // Generate match case
+            quote! {
+                (#is_block, #tag) => {
+                    #check
+                    Ok(#inner)
+                }
+            }
+        });
+
+        let (g_impl, g_ty, g_wh) = g.split_for_impl();
+
+        let select = if attrs.unboxed {
+            quote!((true, 0u8))
+        } else {
+            quote! {
+                let is_block = value.is_block();
+                let tag = if !is_block {
+                    match u8::try_from(value.int_val()) {
+                        Ok(tag) => tag,
+                        Err(_) => return Err(ocaml::ConversionError::new(#expected, &value)),
+                    }
+                } else {
+                    value.tag().0
+                };
+                (is_block, tag)
+            }
+        };
+
+        // Generate TryFromValue for enums
+        quote! {
+            unsafe impl #g_impl ocaml::TryFromValue for #name #g_ty #g_wh {
+                fn try_from_value(value: ocaml::Value) -> Result<Self, ocaml::ConversionError> {
+                    unsafe {
+                        #[allow(unreachable_patterns)]
+                        match { #select } {
+                            #(#variants),*,
+                            _ => Err(ocaml::ConversionError::new(#expected, &value)),
+                        }
+                    }
+                }
+           }
+        }
+        .into()
+    } else {
+        panic!("invalid type for TryFromValue");
+    }
+}
+
 /// Derive `ocaml::ToValue`
 #[proc_macro_derive(ToValue, attributes(float_array, unboxed))]
 pub fn derive_to_value(item: TokenStream) -> TokenStream {
@@ -955,3 +1384,242 @@ pub fn derive_to_value(item: TokenStream) -> TokenStream {
         panic!("invalid type for ToValue");
     }
 }
+
+// Parse `#[exception(...)]` attributes, returning the exception name. `sig` is only used by
+// `ocaml-build`
+fn exception_name(variant: &syn::Variant) -> String {
+    let mut name = None;
+    for attr in variant
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("exception"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                let s: syn::LitStr = meta.value()?.parse()?;
+                name = Some(s.value());
+            } else if meta.path.is_ident("sig") {
+                let _: syn::LitStr = meta.value()?.parse()?;
+            } else {
+                return Err(meta.error("unsupported exception attribute"));
+            }
+            Ok(())
+        })
+        .expect("invalid exception attribute");
+    }
+
+    name.unwrap_or_else(|| variant.ident.to_string())
+}
+
+/// Derive `ocaml::IntoException`
+///
+/// Each variant is mapped to an OCaml exception, the following options can be passed using
+/// `#[exception(...)]` on each variant:
+///
+/// - `name = "..."`: the name used to register the exception, defaults to the variant name
+/// - `sig = "..."`: the OCaml type of the exception arguments, used by `ocaml-build`
+#[proc_macro_derive(IntoException, attributes(exception))]
+pub fn derive_into_exception(item: TokenStream) -> TokenStream {
+    let item_enum = match syn::parse::<syn::ItemEnum>(item) {
+        Ok(x) => x,
+        Err(_) => panic!("invalid type for IntoException, only enums are supported"),
+    };
+
+    let name = &item_enum.ident;
+    let variants = item_enum.variants.iter().map(|variant| {
+        let v_name = &variant.ident;
+        let exn = exception_name(variant);
+        let fields: Vec<_> = variant
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(index, field)| match &field.ident {
+                Some(name) => name.clone(),
+                None => syn::Ident::new(&format!("x{index}"), proc_macro2::Span::call_site()),
+            })
+            .collect();
+
+        let pattern = match &variant.fields {
+            syn::Fields::Named(_) => quote!(#name::#v_name { #(#fields),* }),
+            syn::Fields::Unnamed(_) => quote!(#name::#v_name(#(#fields),*)),
+            syn::Fields::Unit => quote!(#name::#v_name),
+        };
+
+        quote! {
+            #pattern => ocaml::Error::exception_with_args(
+                rt,
+                #exn,
+                &[#(ocaml::ToValue::to_value(&#fields, rt)),*],
+            )
+        }
+    });
+
+    let (g_impl, g_ty, g_wh) = item_enum.generics.split_for_impl();
+
+    quote! {
+        impl #g_impl ocaml::IntoException for #name #g_ty #g_wh {
+            fn into_exception(self, rt: &ocaml::Runtime) -> ocaml::Error {
+                match self {
+                    #(#variants),*
+                }
+            }
+        }
+    }
+    .into()
+}
+
+struct CustomAttrs {
+    name: Option<syn::LitStr>,
+    used: Option<syn::Expr>,
+    max: Option<syn::Expr>,
+    mem_size: Option<syn::Expr>,
+    fixed_length: bool,
+    serialize: bool,
+    compare: bool,
+    hash: bool,
+}
+
+// Parse `#[custom(...)]` attributes
+fn custom_attrs(attrs: &[syn::Attribute]) -> CustomAttrs {
+    let mut acc = CustomAttrs {
+        name: None,
+        used: None,
+        max: None,
+        mem_size: None,
+        fixed_length: false,
+        serialize: false,
+        compare: true,
+        hash: true,
+    };
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("custom")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                acc.name = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("used") {
+                acc.used = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("max") {
+                acc.max = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("mem_size") {
+                acc.mem_size = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("fixed_length") {
+                acc.fixed_length = true;
+            } else if meta.path.is_ident("serialize") {
+                acc.serialize = true;
+            } else if meta.path.is_ident("no_compare") {
+                acc.compare = false;
+            } else if meta.path.is_ident("no_hash") {
+                acc.hash = false;
+            } else {
+                return Err(meta.error("unsupported custom attribute"));
+            }
+            Ok(())
+        })
+        .expect("invalid custom attribute");
+    }
+
+    if acc.fixed_length && !acc.serialize {
+        panic!("fixed_length can only be used with serialize");
+    }
+
+    acc
+}
+
+/// Derive `ocaml::Custom`
+///
+/// `compare` uses `Ord` and `hash` uses `Hash`, the finalizer drops the Rust value. The
+/// following options can be passed using `#[custom(...)]`:
+///
+/// - `name = "..."`: custom type identifier, defaults to `rust.TypeName`
+/// - `used = ...` and `max = ...`: `Custom::USED` and `Custom::MAX`
+/// - `mem_size = ...`: a function `fn(&Self) -> usize` used to implement `Custom::mem_size`
+/// - `serialize`: use `ocaml::CustomSerialize` to implement `serialize` and `deserialize`
+/// - `fixed_length`: mark serialized values as having a fixed length, requires `serialize`
+/// - `no_compare` and `no_hash`: skip `compare` or `hash` for types that don't implement
+///   `Ord` or `Hash`
+#[proc_macro_derive(Custom, attributes(custom))]
+pub fn derive_custom(item: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(item as syn::DeriveInput);
+    let attrs = custom_attrs(&input.attrs);
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let name = match attrs.name {
+        Some(name) => name.value(),
+        None => format!("rust.{ident}"),
+    };
+
+    let used = attrs.used.map(|used| quote!(const USED: usize = #used;));
+    let max = attrs.max.map(|max| quote!(const MAX: usize = #max;));
+    let mem_size = attrs.mem_size.map(|f| {
+        quote! {
+            fn mem_size(&self) -> usize {
+                (#f)(self)
+            }
+        }
+    });
+
+    let compare = if attrs.compare {
+        quote!(compare: Some(ocaml::custom::compare::<Self>),)
+    } else {
+        quote!()
+    };
+
+    let hash = if attrs.hash {
+        quote!(hash: Some(ocaml::custom::hash::<Self>),)
+    } else {
+        quote!()
+    };
+
+    let serialize = if attrs.serialize {
+        quote! {
+            serialize: Some(ocaml::custom::serialize::<Self>),
+            deserialize: Some(ocaml::custom::deserialize::<Self>),
+        }
+    } else {
+        quote!()
+    };
+
+    let (fixed_length_const, fixed_length) = if attrs.fixed_length {
+        let len = quote! {
+            ocaml::sys::custom_fixed_length {
+                bsize_32: core::mem::size_of::<Self>() as ocaml::Int,
+                bsize_64: core::mem::size_of::<Self>() as ocaml::Int,
+            }
+        };
+        (
+            quote!(const FIXED_LENGTH: Option<ocaml::sys::custom_fixed_length> = Some(#len);),
+            quote!(fixed_length: &#len as *const ocaml::sys::custom_fixed_length,),
+        )
+    } else {
+        (quote!(), quote!())
+    };
+
+    let gen = quote! {
+        impl #impl_generics ocaml::Custom for #ident #ty_generics #where_clause {
+            const NAME: &'static str = concat!(#name, "\0");
+
+            #fixed_length_const
+            #used
+            #max
+
+            const OPS: ocaml::custom::CustomOps = ocaml::custom::CustomOps {
+                identifier: Self::NAME.as_ptr() as *const ocaml::sys::Char,
+                finalize: Some(Self::finalize),
+                #compare
+                #hash
+                #serialize
+                #fixed_length
+                ..ocaml::custom::DEFAULT_CUSTOM_OPS
+            };
+
+            #mem_size
+
+            fn registered() -> Option<&'static core::sync::atomic::AtomicBool> {
+                static REGISTERED: core::sync::atomic::AtomicBool =
+                    core::sync::atomic::AtomicBool::new(false);
+                Some(&REGISTERED)
+            }
+        }
+    };
+    gen.into()
+}
@@ -0,0 +1,183 @@
+//! Wrappers for OCaml `in_channel` and `out_channel`
+//!
+//! The channel primitives raise OCaml exceptions on I/O errors, so they're called through
+//! `input`, `output_substring` and `flush`, which are registered as the `ocaml_rs_input`,
+//! `ocaml_rs_output` and `ocaml_rs_flush` named values by the bindings generated by `ocaml-build`.
+//! Exceptions are returned as `std::io::Error` instead of unwinding through Rust code.
+//!
+//! The `Read`, `BufRead` and `Write` implementations call into the OCaml runtime, so they must
+//! only be used while holding the runtime lock - not inside `Runtime::without_lock`, from
+//! `#[ocaml::func(release_lock)]` functions or from threads that haven't acquired the lock using
+//! `Runtime::with_lock`. The channel functions release the lock themselves while blocking.
+
+use crate::{sys, FromValue, Runtime, ToValue, TryFromValue, Value};
+
+// Maximum number of bytes transferred by a single call into the OCaml runtime
+const CHUNK_SIZE: usize = 65536;
+
+// Call the channel function registered as `name`
+unsafe fn call(name: &str, args: &[Value]) -> std::io::Result<Value> {
+    let f = match Value::named(name) {
+        Some(f) => f,
+        None => {
+            return Err(std::io::Error::other(format!(
+                "{name} has not been registered, it is included in the bindings generated by ocaml-build"
+            )))
+        }
+    };
+    let args: Vec<_> = args.iter().map(|x| x.raw()).collect();
+    f.call_n(args).map_err(|e| match e.caml_error() {
+        Some(crate::CamlError::EndOfFile) => {
+            std::io::Error::from(std::io::ErrorKind::UnexpectedEof)
+        }
+        _ => std::io::Error::other(e.to_string()),
+    })
+}
+
+/// `InChannel` wraps an OCaml `in_channel`, implementing `std::io::Read` and `std::io::BufRead`
+///
+/// Reads go through the channel's own buffer and lock, the same as `input` in OCaml. When using
+/// `BufRead`, data that has been buffered but not consumed is lost if the `InChannel` is dropped.
+/// Exceptions raised by `input`, for example `Sys_error`, are returned as `std::io::Error`. The
+/// runtime lock must be held when reading.
+pub struct InChannel {
+    channel: Value,
+    scratch: Option<Value>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+unsafe impl ToValue for InChannel {
+    fn to_value(&self, _rt: &Runtime) -> Value {
+        self.channel.clone()
+    }
+}
+
+unsafe impl FromValue for InChannel {
+    fn from_value(value: Value) -> Self {
+        InChannel {
+            channel: value,
+            scratch: None,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+unsafe impl TryFromValue for InChannel {
+    fn try_from_value(value: Value) -> Result<Self, crate::ConversionError> {
+        unsafe { crate::ConversionError::expect_custom(&value, "in_channel", "_chan")? };
+        Ok(InChannel::from_value(value))
+    }
+}
+
+impl InChannel {
+    // Read at most `dest.len()` bytes directly from the channel
+    fn input(&mut self, dest: &mut [u8]) -> std::io::Result<usize> {
+        let len = dest.len().min(CHUNK_SIZE);
+        unsafe {
+            let scratch = self
+                .scratch
+                .get_or_insert_with(|| Value::new(sys::caml_alloc_string(CHUNK_SIZE)));
+            let n = call(
+                "ocaml_rs_input",
+                &[
+                    self.channel.clone(),
+                    scratch.clone(),
+                    Value::int(0),
+                    Value::int(len as crate::Int),
+                ],
+            )?
+            .int_val() as usize;
+            dest[..n].copy_from_slice(&scratch.bytes_val()[..n]);
+            Ok(n)
+        }
+    }
+}
+
+impl std::io::Read for InChannel {
+    fn read(&mut self, dest: &mut [u8]) -> std::io::Result<usize> {
+        // Avoid reading more than requested from the channel when nothing is buffered
+        if self.pos >= self.buf.len() {
+            return self.input(dest);
+        }
+
+        let n = {
+            let data = std::io::BufRead::fill_buf(self)?;
+            let n = data.len().min(dest.len());
+            dest[..n].copy_from_slice(&data[..n]);
+            n
+        };
+        std::io::BufRead::consume(self, n);
+        Ok(n)
+    }
+}
+
+impl std::io::BufRead for InChannel {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.pos >= self.buf.len() {
+            let mut buf = std::mem::take(&mut self.buf);
+            buf.resize(CHUNK_SIZE, 0);
+            let n = self.input(&mut buf)?;
+            buf.truncate(n);
+            self.buf = buf;
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+/// `OutChannel` wraps an OCaml `out_channel`, implementing `std::io::Write`
+///
+/// Writes go through the channel's own buffer and lock, the same as `output_bytes` in OCaml.
+/// Exceptions raised by `output_substring` and `flush` are returned as `std::io::Error`. The
+/// runtime lock must be held when writing or flushing.
+pub struct OutChannel(Value);
+
+unsafe impl ToValue for OutChannel {
+    fn to_value(&self, _rt: &Runtime) -> Value {
+        self.0.clone()
+    }
+}
+
+unsafe impl FromValue for OutChannel {
+    fn from_value(value: Value) -> Self {
+        OutChannel(value)
+    }
+}
+
+unsafe impl TryFromValue for OutChannel {
+    fn try_from_value(value: Value) -> Result<Self, crate::ConversionError> {
+        unsafe { crate::ConversionError::expect_custom(&value, "out_channel", "_chan")? };
+        Ok(OutChannel(value))
+    }
+}
+
+impl std::io::Write for OutChannel {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let data = &data[..data.len().min(CHUNK_SIZE)];
+        unsafe {
+            call(
+                "ocaml_rs_output",
+                &[
+                    self.0.clone(),
+                    Value::bytes(data),
+                    Value::int(0),
+                    Value::int(data.len() as crate::Int),
+                ],
+            )?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        unsafe {
+            call("ocaml_rs_flush", core::slice::from_ref(&self.0))?;
+        }
+        Ok(())
+    }
+}
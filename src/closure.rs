@@ -0,0 +1,114 @@
+//! OCaml closures backed by Rust functions
+//!
+//! OCaml closures can't be allocated from Rust directly, instead the bindings generated by
+//! `ocaml-build` register `ocaml_rs_closure_call` as the `ocaml_rs_closure` named value.
+//! Partially applying it to a custom block containing a Rust function creates a real OCaml
+//! closure, the Rust function is dropped when the closure is collected. Closures that take more
+//! than one argument use `ocaml_rs_closure2` to `ocaml_rs_closure6`, which pass their arguments
+//! to `ocaml_rs_closure_call` as a tuple. These are always included in the generated bindings,
+//! since they may be needed by dependencies of the crate generating them.
+
+use crate::{Error, FromValue, Pointer, Runtime, ToValue, Value};
+
+// `Fn` rather than `FnMut` because the closure can be re-entered from OCaml
+type Function = Box<dyn Fn(&Runtime, Value) -> Value>;
+
+pub(crate) struct Closure(Function);
+
+crate::custom!(Closure);
+
+/// Allocate an OCaml closure that calls `f` when applied to an argument
+pub(crate) fn alloc(
+    rt: &Runtime,
+    f: impl Fn(&Runtime, Value) -> Value + 'static,
+) -> Result<Value, Error> {
+    alloc_n(rt, 1, f)
+}
+
+/// Allocate an OCaml closure that takes `n` arguments, `f` is called with a tuple of the arguments
+/// when `n` is greater than 1
+fn alloc_n(
+    rt: &Runtime,
+    n: usize,
+    f: impl Fn(&Runtime, Value) -> Value + 'static,
+) -> Result<Value, Error> {
+    let name = if n == 1 {
+        "ocaml_rs_closure".to_string()
+    } else {
+        format!("ocaml_rs_closure{n}")
+    };
+
+    unsafe {
+        let call = match Value::named(&name) {
+            Some(x) => x,
+            None => {
+                return Err(Error::Message(
+                    "ocaml_rs_closure has not been registered, it is included in the bindings generated by ocaml-build",
+                ))
+            }
+        };
+        let env = Pointer::alloc_custom(Closure(Box::new(f)));
+        call.call1(rt, env.0)
+    }
+}
+
+/// Rust functions that can be converted to OCaml closures using `Value::closure`
+///
+/// This is implemented for functions that take a `&Runtime` followed by up to 6 arguments that
+/// implement `FromValue` and return a value that implements `ToValue`. `Args` is a tuple of the
+/// argument types, a function without any arguments becomes a closure that takes `unit`
+pub trait IntoClosure<Args> {
+    /// Allocate an OCaml closure that calls `self`
+    fn into_closure(self, rt: &Runtime) -> Result<Value, Error>;
+}
+
+impl<Func, R: ToValue> IntoClosure<()> for Func
+where
+    Func: Fn(&Runtime) -> R + 'static,
+{
+    fn into_closure(self, rt: &Runtime) -> Result<Value, Error> {
+        alloc(rt, move |rt, _| self(rt).to_value(rt))
+    }
+}
+
+impl<Func, A: FromValue, R: ToValue> IntoClosure<(A,)> for Func
+where
+    Func: Fn(&Runtime, A) -> R + 'static,
+{
+    fn into_closure(self, rt: &Runtime) -> Result<Value, Error> {
+        alloc(rt, move |rt, a| self(rt, A::from_value(a)).to_value(rt))
+    }
+}
+
+macro_rules! into_closure_impl {
+    ($n:expr; $($t:ident: $i:tt),*) => {
+        impl<Func, $($t: FromValue,)* R: ToValue> IntoClosure<($($t,)*)> for Func
+        where
+            Func: Fn(&Runtime, $($t),*) -> R + 'static,
+        {
+            fn into_closure(self, rt: &Runtime) -> Result<Value, Error> {
+                alloc_n(rt, $n, move |rt, args| unsafe {
+                    self(rt, $($t::from_value(args.field($i))),*).to_value(rt)
+                })
+            }
+        }
+    };
+}
+
+into_closure_impl!(2; A: 0, B: 1);
+into_closure_impl!(3; A: 0, B: 1, C: 2);
+into_closure_impl!(4; A: 0, B: 1, C: 2, D: 3);
+into_closure_impl!(5; A: 0, B: 1, C: 2, D: 3, E: 4);
+into_closure_impl!(6; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+
+#[doc(hidden)]
+#[no_mangle]
+pub unsafe extern "C" fn ocaml_rs_closure_call(
+    env: crate::sys::Value,
+    arg: crate::sys::Value,
+) -> crate::sys::Value {
+    let rt = Runtime::init();
+    let env = Pointer::<Closure>::from_value(Value::new(env));
+    let res = (env.as_ref().0)(&rt, Value::new(arg));
+    res.raw().0
+}
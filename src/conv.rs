@@ -2,10 +2,47 @@ use core::convert::TryInto;
 
 use crate::{
     sys,
-    value::{FromValue, ToValue, Value},
+    value::{FromValue, Owned, ToValue, Value},
     Raw, Runtime, Tag,
 };
 
+macro_rules! owned {
+    ($($t:ty),*) => {
+        $(unsafe impl Owned for $t {})*
+    }
+}
+
+owned!(
+    (),
+    bool,
+    i8,
+    u8,
+    i16,
+    u16,
+    i32,
+    u32,
+    i64,
+    u64,
+    isize,
+    usize,
+    f32,
+    f64,
+    String
+);
+
+unsafe impl<T: Owned> Owned for Option<T> {}
+unsafe impl<T: Owned, E: Owned> Owned for Result<T, E> {}
+unsafe impl<const N: usize> Owned for [u8; N] {}
+
+unsafe impl<T: Owned> Owned for Box<T> {}
+unsafe impl<T: Owned> Owned for Vec<T> {}
+
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T: Owned> Owned for std::collections::LinkedList<T> {}
+
+#[cfg(not(feature = "no-std"))]
+unsafe impl<K: Owned, V: Owned> Owned for std::collections::BTreeMap<K, V> {}
+
 unsafe impl<T: ToValue> ToValue for &T {
     fn to_value(&self, rt: &Runtime) -> Value {
         ToValue::to_value(*self, rt)
@@ -113,6 +150,8 @@ impl Incr {
 
 macro_rules! tuple_impl {
     ($($t:ident: $n:tt),*) => {
+        unsafe impl<$($t: Owned),*> Owned for ($($t,)*) {}
+
         unsafe impl<$($t: FromValue),*> FromValue for ($($t,)*) {
             fn from_value(v: Value) -> ($($t,)*) {
                 let mut i = Incr(0);
@@ -459,3 +498,326 @@ unsafe impl<T: ToValue> ToValue for std::collections::LinkedList<T> {
         list.to_value(rt)
     }
 }
+
+#[cfg(not(feature = "no-std"))]
+mod checked {
+    use crate::{
+        error::{ConversionError, PathSegment},
+        sys,
+        value::{TryFromValue, Value},
+        Raw, Tag,
+    };
+
+    macro_rules! try_value_i {
+        ($t:ty) => {
+            unsafe impl TryFromValue for $t {
+                fn try_from_value(v: Value) -> Result<$t, ConversionError> {
+                    let expected = concat!("int (", stringify!($t), ")");
+                    let i = unsafe { ConversionError::expect_immediate(&v, expected)? };
+                    <$t>::try_from(i).map_err(|_| ConversionError::new(expected, &v))
+                }
+            }
+        };
+        ($($t:ty),*) => {
+            $(try_value_i!($t);)*
+        }
+    }
+
+    try_value_i!(i8, u8, i16, u16, crate::Int, crate::Uint);
+
+    macro_rules! try_value_custom {
+        ($t:ty, $expected:expr, $identifier:expr, $get:ident) => {
+            unsafe impl TryFromValue for $t {
+                fn try_from_value(v: Value) -> Result<$t, ConversionError> {
+                    unsafe {
+                        ConversionError::expect_custom(&v, $expected, $identifier)?;
+                        Ok(v.$get() as $t)
+                    }
+                }
+            }
+        };
+    }
+
+    try_value_custom!(i32, "int32", "_i", int32_val);
+    try_value_custom!(u32, "int32", "_i", int32_val);
+    try_value_custom!(i64, "int64", "_j", int64_val);
+    try_value_custom!(u64, "int64", "_j", int64_val);
+
+    unsafe impl TryFromValue for f64 {
+        fn try_from_value(v: Value) -> Result<f64, ConversionError> {
+            unsafe {
+                ConversionError::expect_block(&v, "float", Tag::DOUBLE, None)?;
+                Ok(v.double_val())
+            }
+        }
+    }
+
+    unsafe impl TryFromValue for f32 {
+        fn try_from_value(v: Value) -> Result<f32, ConversionError> {
+            f64::try_from_value(v).map(|x| x as f32)
+        }
+    }
+
+    unsafe impl TryFromValue for bool {
+        fn try_from_value(v: Value) -> Result<bool, ConversionError> {
+            match unsafe { ConversionError::expect_immediate(&v, "bool")? } {
+                0 => Ok(false),
+                1 => Ok(true),
+                _ => Err(ConversionError::new("bool", &v)),
+            }
+        }
+    }
+
+    unsafe impl TryFromValue for () {
+        fn try_from_value(v: Value) -> Result<(), ConversionError> {
+            match unsafe { ConversionError::expect_immediate(&v, "unit")? } {
+                0 => Ok(()),
+                _ => Err(ConversionError::new("unit", &v)),
+            }
+        }
+    }
+
+    unsafe fn bytes<'a>(v: &Value, expected: &str) -> Result<&'a mut [u8], ConversionError> {
+        ConversionError::expect_block(v, expected, Tag::STRING, None)?;
+        let len = sys::caml_string_length(v.raw().0);
+        let ptr = sys::string_val(v.raw().0);
+        Ok(::core::slice::from_raw_parts_mut(ptr, len))
+    }
+
+    unsafe impl<'a> TryFromValue for &'a [u8] {
+        fn try_from_value(v: Value) -> Result<&'a [u8], ConversionError> {
+            unsafe { bytes(&v, "bytes").map(|x| &*x) }
+        }
+    }
+
+    unsafe impl<'a> TryFromValue for &'a mut [u8] {
+        fn try_from_value(v: Value) -> Result<&'a mut [u8], ConversionError> {
+            unsafe { bytes(&v, "bytes") }
+        }
+    }
+
+    unsafe impl<const N: usize> TryFromValue for [u8; N] {
+        fn try_from_value(v: Value) -> Result<Self, ConversionError> {
+            let s = unsafe { bytes(&v, "bytes")? };
+            (&*s)
+                .try_into()
+                .map_err(|_| ConversionError::new(format!("bytes of length {N}"), &v))
+        }
+    }
+
+    unsafe impl<'a> TryFromValue for &'a mut str {
+        fn try_from_value(v: Value) -> Result<&'a mut str, ConversionError> {
+            let s = unsafe { bytes(&v, "string")? };
+            ::core::str::from_utf8_mut(s).map_err(|_| ConversionError::new("UTF-8 string", &v))
+        }
+    }
+
+    unsafe impl<'a> TryFromValue for &'a str {
+        fn try_from_value(v: Value) -> Result<&'a str, ConversionError> {
+            <&mut str>::try_from_value(v).map(|x| &*x)
+        }
+    }
+
+    unsafe impl TryFromValue for String {
+        fn try_from_value(v: Value) -> Result<String, ConversionError> {
+            <&str>::try_from_value(v).map(String::from)
+        }
+    }
+
+    unsafe impl<T: TryFromValue> TryFromValue for Option<T> {
+        fn try_from_value(v: Value) -> Result<Option<T>, ConversionError> {
+            unsafe {
+                if v.is_long() {
+                    return match v.int_val() {
+                        0 => Ok(None),
+                        _ => Err(ConversionError::new("option", &v)),
+                    };
+                }
+
+                ConversionError::expect_block(&v, "option", Tag(0), Some(1))?;
+                T::try_from_value(v.field(0))
+                    .map(Some)
+                    .map_err(|e| e.at(PathSegment::Variant("Some")))
+            }
+        }
+    }
+
+    unsafe impl<A: TryFromValue, B: TryFromValue> TryFromValue for Result<A, B> {
+        fn try_from_value(v: Value) -> Result<Result<A, B>, ConversionError> {
+            unsafe {
+                if v.is_block() && v.tag() == Tag(1) {
+                    ConversionError::expect_block(&v, "result", Tag(1), Some(1))?;
+                    return B::try_from_value(v.field(0))
+                        .map(Err)
+                        .map_err(|e| e.at(PathSegment::Variant("Error")));
+                }
+
+                ConversionError::expect_block(&v, "result", Tag(0), Some(1))?;
+                A::try_from_value(v.field(0))
+                    .map(Ok)
+                    .map_err(|e| e.at(PathSegment::Variant("Ok")))
+            }
+        }
+    }
+
+    unsafe impl<T: TryFromValue> TryFromValue for Result<T, crate::Error> {
+        fn try_from_value(v: Value) -> Result<Result<T, crate::Error>, ConversionError> {
+            unsafe {
+                if v.is_exception_result() {
+                    return Ok(Err(crate::CamlError::Exception(v).into()));
+                }
+            }
+
+            T::try_from_value(v).map(Ok)
+        }
+    }
+
+    unsafe impl<V: TryFromValue> TryFromValue for Box<V> {
+        fn try_from_value(v: Value) -> Result<Box<V>, ConversionError> {
+            V::try_from_value(v).map(Box::new)
+        }
+    }
+
+    unsafe impl<V: TryFromValue> TryFromValue for Vec<V> {
+        fn try_from_value(v: Value) -> Result<Vec<V>, ConversionError> {
+            unsafe {
+                if v.is_block() && v.tag() == Tag::DOUBLE_ARRAY && sys::FLAT_FLOAT_ARRAY {
+                    let len = v.array_length();
+                    let mut dst = Vec::with_capacity(len);
+                    let mut tmp = Value::double(0.0);
+                    for i in 0..len {
+                        tmp.store_double_val(v.double_field(i));
+                        let x = V::try_from_value(Value::new(tmp.raw().0))
+                            .map_err(|e| e.at(PathSegment::Index(i)))?;
+                        dst.push(x);
+                    }
+                    return Ok(dst);
+                }
+
+                ConversionError::expect_block(&v, "array", Tag(0), None)?;
+                let len = v.array_length();
+                let mut dst = Vec::with_capacity(len);
+                for i in 0..len {
+                    let x =
+                        V::try_from_value(v.field(i)).map_err(|e| e.at(PathSegment::Index(i)))?;
+                    dst.push(x);
+                }
+                Ok(dst)
+            }
+        }
+    }
+
+    unsafe impl<'a> TryFromValue for &'a [Raw] {
+        fn try_from_value(v: Value) -> Result<&'a [Raw], ConversionError> {
+            unsafe {
+                ConversionError::expect_block(&v, "array", Tag(0), None)?;
+            }
+            Ok(crate::FromValue::from_value(v))
+        }
+    }
+
+    unsafe impl<'a> TryFromValue for &'a mut [Raw] {
+        fn try_from_value(v: Value) -> Result<&'a mut [Raw], ConversionError> {
+            unsafe {
+                ConversionError::expect_block(&v, "array", Tag(0), None)?;
+            }
+            Ok(crate::FromValue::from_value(v))
+        }
+    }
+
+    /// Iterate over the items of an OCaml list, checking each cell
+    unsafe fn list_items(
+        v: Value,
+        mut f: impl FnMut(usize, Value) -> Result<(), ConversionError>,
+    ) -> Result<(), ConversionError> {
+        let mut tmp = v;
+        let mut index = 0;
+        while tmp.raw().0 != sys::EMPTY_LIST {
+            ConversionError::expect_block(&tmp, "list", Tag(0), Some(2))
+                .map_err(|e| e.at(PathSegment::Index(index)))?;
+            f(index, tmp.field(0)).map_err(|e| e.at(PathSegment::Index(index)))?;
+            tmp = tmp.field(1);
+            index += 1;
+        }
+
+        if tmp.is_long() && tmp.int_val() != 0 {
+            return Err(ConversionError::new("list", &tmp).at(PathSegment::Index(index)));
+        }
+
+        Ok(())
+    }
+
+    unsafe impl<K: Ord + TryFromValue, V: TryFromValue> TryFromValue
+        for std::collections::BTreeMap<K, V>
+    {
+        fn try_from_value(v: Value) -> Result<Self, ConversionError> {
+            let mut dest = std::collections::BTreeMap::new();
+            unsafe {
+                list_items(v, |_, item| {
+                    let (k, v) = TryFromValue::try_from_value(item)?;
+                    dest.insert(k, v);
+                    Ok(())
+                })?;
+            }
+            Ok(dest)
+        }
+    }
+
+    unsafe impl<T: TryFromValue> TryFromValue for std::collections::LinkedList<T> {
+        fn try_from_value(v: Value) -> Result<Self, ConversionError> {
+            let mut dest = std::collections::LinkedList::new();
+            unsafe {
+                list_items(v, |_, item| {
+                    dest.push_back(T::try_from_value(item)?);
+                    Ok(())
+                })?;
+            }
+            Ok(dest)
+        }
+    }
+
+    macro_rules! try_tuple_impl {
+        ($n_fields:expr; $($t:ident: $n:tt),*) => {
+            unsafe impl<$($t: TryFromValue),*> TryFromValue for ($($t,)*) {
+                fn try_from_value(v: Value) -> Result<($($t,)*), ConversionError> {
+                    unsafe {
+                        ConversionError::expect_block(
+                            &v,
+                            concat!("tuple of size ", stringify!($n_fields)),
+                            Tag(0),
+                            Some($n_fields),
+                        )?;
+                        Ok((
+                            $(
+                                $t::try_from_value(v.field($n))
+                                    .map_err(|e| e.at(PathSegment::Index($n)))?,
+                            )*
+                        ))
+                    }
+                }
+            }
+        };
+    }
+
+    try_tuple_impl!(1; A: 0);
+    try_tuple_impl!(2; A: 0, B: 1);
+    try_tuple_impl!(3; A: 0, B: 1, C: 2);
+    try_tuple_impl!(4; A: 0, B: 1, C: 2, D: 3);
+    try_tuple_impl!(5; A: 0, B: 1, C: 2, D: 3, E: 4);
+    try_tuple_impl!(6; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+    try_tuple_impl!(7; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+    try_tuple_impl!(8; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+    try_tuple_impl!(9; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8);
+    try_tuple_impl!(10; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9);
+    try_tuple_impl!(11; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10);
+    try_tuple_impl!(12; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11);
+    try_tuple_impl!(13; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12);
+    try_tuple_impl!(14; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13);
+    try_tuple_impl!(15; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14);
+    try_tuple_impl!(16; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14, P: 15);
+    try_tuple_impl!(17; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14, P: 15, Q: 16);
+    try_tuple_impl!(18; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14, P: 15, Q: 16, R: 17);
+    try_tuple_impl!(19; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14, P: 15, Q: 16, R: 17, S: 18);
+    try_tuple_impl!(20; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14, P: 15, Q: 16, R: 17, S: 18, T: 19);
+    try_tuple_impl!(21; A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11, M: 12, N: 13, O: 14, P: 15, Q: 16, R: 17, S: 18, T: 19, U: 20);
+}
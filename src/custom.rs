@@ -73,6 +73,13 @@ pub trait Custom: Sized {
     /// related to this custom type
     const MAX: usize = 1;
 
+    /// Size in bytes of any memory held by the value outside of the OCaml heap, when this is
+    /// non-zero values are allocated using `caml_alloc_custom_mem` instead of `USED` and `MAX` so
+    /// the GC is able to account for large Rust allocations
+    fn mem_size(&self) -> usize {
+        0
+    }
+
     /// Automatically calls `Pointer::drop_in_place`
     unsafe extern "C" fn finalize(v: Raw) {
         let p = v.as_pointer::<Self>();
@@ -83,6 +90,13 @@ pub trait Custom: Sized {
     fn ops() -> &'static CustomOps {
         &Self::OPS
     }
+
+    /// Flag used by `register` to avoid looking up the custom operations on every allocation,
+    /// this is implemented by `custom!` and `derive(Custom)`
+    #[doc(hidden)]
+    fn registered() -> Option<&'static core::sync::atomic::AtomicBool> {
+        None
+    }
 }
 
 /// Create a custom OCaml type from an existing Rust type
@@ -144,6 +158,9 @@ pub trait Custom: Sized {
 /// }
 /// ```
 ///
+/// Types implementing `CustomSerialize` can use `Type: CustomSerialize` to fill in the
+/// `serialize` and `deserialize` operations, see `CustomSerialize` for an example.
+///
 /// Additionally, `custom` can be used inside the `impl` block:
 ///
 /// ```rust
@@ -170,6 +187,16 @@ pub trait Custom: Sized {
 /// ```
 #[macro_export]
 macro_rules! custom {
+    ($name:ident $(<$($t:tt),*>)? : CustomSerialize $({$($k:ident : $v:expr),* $(,)? })?) => {
+        impl $(<$($t),*>)? $crate::Custom for $name $(<$($t),*>)? {
+            $crate::custom! {
+                name: concat!("rust.", stringify!($name)),
+                serialize: $crate::custom::serialize::<Self>,
+                deserialize: $crate::custom::deserialize::<Self>
+                $(, $($k: $v),*)?
+            }
+        }
+    };
     ($name:ident $(<$($t:tt),*>)? $({$($k:ident : $v:expr),* $(,)? })?) => {
         impl $(<$($t),*>)? $crate::Custom for $name $(<$($t),*>)? {
             $crate::custom! {
@@ -189,6 +216,12 @@ macro_rules! custom {
                 .. $crate::custom::DEFAULT_CUSTOM_OPS
             }
         };
+
+        fn registered() -> Option<&'static core::sync::atomic::AtomicBool> {
+            static REGISTERED: core::sync::atomic::AtomicBool =
+                core::sync::atomic::AtomicBool::new(false);
+            Some(&REGISTERED)
+        }
     };
 }
 
@@ -206,6 +239,298 @@ unsafe impl<T: Custom> FromValue for &mut T {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T: Custom> TryFromValue for &T {
+    fn try_from_value(v: Value) -> Result<Self, ConversionError> {
+        let mut ptr = Pointer::<T>::try_from_custom(v)?;
+        unsafe { Ok(&*ptr.as_mut_ptr()) }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T: Custom> TryFromValue for &mut T {
+    fn try_from_value(v: Value) -> Result<Self, ConversionError> {
+        let mut ptr = Pointer::<T>::try_from_custom(v)?;
+        unsafe { Ok(&mut *ptr.as_mut_ptr()) }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl<T: Custom> Pointer<T> {
+    /// Convert from `Value`, checking that the value is a custom block created for `T`
+    pub fn try_from_custom(v: Value) -> Result<Pointer<T>, ConversionError> {
+        let name = T::NAME.trim_end_matches('\0');
+        unsafe { ConversionError::expect_custom(&v, name, name)? };
+        Ok(Pointer::from_value(v))
+    }
+}
+
+/// `CustomSerialize` allows custom types to be serialized using the OCaml `Marshal` module
+///
+/// The `serialize` and `deserialize` implementations should read and write the same data in the
+/// same order. Use `ocaml::custom!(Type: CustomSerialize)` to create the `Custom` implementation,
+/// the custom operations are registered with the OCaml runtime automatically when the first value
+/// is allocated. If a value may be unmarshalled before any have been allocated, for example when
+/// reading a file written by another process, `ocaml::custom::register` must be called first,
+/// otherwise `Marshal` fails with `Failure "input_value: unknown custom block identifier"`
+///
+/// ```rust
+/// use ocaml::custom::{CustomSerialize, Deserializer, Serializer};
+///
+/// struct Point {
+///     x: f64,
+///     y: f64,
+/// }
+///
+/// impl CustomSerialize for Point {
+///     fn serialize(&self, s: &mut Serializer) {
+///         s.write_f64(self.x);
+///         s.write_f64(self.y);
+///     }
+///
+///     fn deserialize(d: &mut Deserializer) -> Result<Self, String> {
+///         Ok(Point {
+///             x: d.read_f64(),
+///             y: d.read_f64(),
+///         })
+///     }
+/// }
+///
+/// ocaml::custom!(Point: CustomSerialize);
+/// ```
+pub trait CustomSerialize: Custom {
+    /// Write the value using the provided `Serializer`
+    fn serialize(&self, s: &mut Serializer);
+
+    /// Read a value using the provided `Deserializer`, returning an error will cause
+    /// deserialization to fail with a `Failure` exception
+    fn deserialize(d: &mut Deserializer) -> Result<Self, String>;
+}
+
+/// Used to write serialized data from `CustomSerialize::serialize`
+pub struct Serializer(core::marker::PhantomData<*mut u8>);
+
+impl Serializer {
+    /// Write a `u8`
+    pub fn write_u8(&mut self, x: u8) {
+        unsafe { sys::caml_serialize_int_1(x as i32) }
+    }
+
+    /// Write an `i8`
+    pub fn write_i8(&mut self, x: i8) {
+        unsafe { sys::caml_serialize_int_1(x as i32) }
+    }
+
+    /// Write a `u16`
+    pub fn write_u16(&mut self, x: u16) {
+        unsafe { sys::caml_serialize_int_2(x as i32) }
+    }
+
+    /// Write an `i16`
+    pub fn write_i16(&mut self, x: i16) {
+        unsafe { sys::caml_serialize_int_2(x as i32) }
+    }
+
+    /// Write a `u32`
+    pub fn write_u32(&mut self, x: u32) {
+        unsafe { sys::caml_serialize_int_4(x as i32) }
+    }
+
+    /// Write an `i32`
+    pub fn write_i32(&mut self, x: i32) {
+        unsafe { sys::caml_serialize_int_4(x) }
+    }
+
+    /// Write a `u64`
+    pub fn write_u64(&mut self, x: u64) {
+        unsafe { sys::caml_serialize_int_8(x as i64) }
+    }
+
+    /// Write an `i64`
+    pub fn write_i64(&mut self, x: i64) {
+        unsafe { sys::caml_serialize_int_8(x) }
+    }
+
+    /// Write an `f32`
+    pub fn write_f32(&mut self, x: f32) {
+        unsafe { sys::caml_serialize_float_4(x) }
+    }
+
+    /// Write an `f64`
+    pub fn write_f64(&mut self, x: f64) {
+        unsafe { sys::caml_serialize_float_8(x) }
+    }
+
+    /// Write a byte slice, the length is not included
+    pub fn write_bytes(&mut self, x: &[u8]) {
+        unsafe { sys::caml_serialize_block_1(x.as_ptr() as *const _, x.len() as Int) }
+    }
+}
+
+/// Used to read serialized data in `CustomSerialize::deserialize`
+pub struct Deserializer(core::marker::PhantomData<*mut u8>);
+
+impl Deserializer {
+    /// Read a `u8`
+    pub fn read_u8(&mut self) -> u8 {
+        unsafe { sys::caml_deserialize_uint_1() as u8 }
+    }
+
+    /// Read an `i8`
+    pub fn read_i8(&mut self) -> i8 {
+        unsafe { sys::caml_deserialize_sint_1() as i8 }
+    }
+
+    /// Read a `u16`
+    pub fn read_u16(&mut self) -> u16 {
+        unsafe { sys::caml_deserialize_uint_2() as u16 }
+    }
+
+    /// Read an `i16`
+    pub fn read_i16(&mut self) -> i16 {
+        unsafe { sys::caml_deserialize_sint_2() as i16 }
+    }
+
+    /// Read a `u32`
+    pub fn read_u32(&mut self) -> u32 {
+        unsafe { sys::caml_deserialize_uint_4() }
+    }
+
+    /// Read an `i32`
+    pub fn read_i32(&mut self) -> i32 {
+        unsafe { sys::caml_deserialize_sint_4() }
+    }
+
+    /// Read a `u64`
+    pub fn read_u64(&mut self) -> u64 {
+        unsafe { sys::caml_deserialize_uint_8() }
+    }
+
+    /// Read an `i64`
+    pub fn read_i64(&mut self) -> i64 {
+        unsafe { sys::caml_deserialize_sint_8() }
+    }
+
+    /// Read an `f32`
+    pub fn read_f32(&mut self) -> f32 {
+        unsafe { sys::caml_deserialize_float_4() }
+    }
+
+    /// Read an `f64`
+    pub fn read_f64(&mut self) -> f64 {
+        unsafe { sys::caml_deserialize_float_8() }
+    }
+
+    /// Fill `dest` with the next `dest.len()` bytes
+    pub fn read_bytes(&mut self, dest: &mut [u8]) {
+        unsafe { sys::caml_deserialize_block_1(dest.as_mut_ptr() as *mut _, dest.len() as Int) }
+    }
+}
+
+/// `serialize` operation for types implementing `CustomSerialize`
+pub unsafe extern "C" fn serialize<T: CustomSerialize>(
+    v: Raw,
+    bsize_32: *mut Uint,
+    bsize_64: *mut Uint,
+) {
+    let ptr = v.as_pointer::<T>();
+    ptr.as_ref()
+        .serialize(&mut Serializer(core::marker::PhantomData));
+    *bsize_32 = core::mem::size_of::<T>();
+    *bsize_64 = core::mem::size_of::<T>();
+}
+
+/// `deserialize` operation for types implementing `CustomSerialize`
+pub unsafe extern "C" fn deserialize<T: CustomSerialize>(dst: *mut core::ffi::c_void) -> Uint {
+    match T::deserialize(&mut Deserializer(core::marker::PhantomData)) {
+        Ok(x) => {
+            core::ptr::write_unaligned(dst as *mut T, x);
+            core::mem::size_of::<T>()
+        }
+        Err(e) => {
+            // `caml_deserialize_error` raises without returning, so anything allocated here would
+            // be leaked. The message is copied into a buffer on the stack instead, which is fine
+            // since the runtime copies it before raising. Messages are truncated and interior NUL
+            // bytes are removed.
+            let mut buf = [0u8; 256];
+            let msg = e.bytes().filter(|b| *b != 0).take(buf.len() - 1);
+            for (dst, b) in buf.iter_mut().zip(msg) {
+                *dst = b;
+            }
+            drop(e);
+            sys::caml_deserialize_error(buf.as_ptr() as *const sys::Char)
+        }
+    }
+}
+
+/// `compare` operation for types implementing `Ord`, a total order is required since the result
+/// is used by `Set`, `Map` and sorting on the OCaml side
+pub unsafe extern "C" fn compare<T: Custom + Ord>(a: Raw, b: Raw) -> i32 {
+    let a = a.as_pointer::<T>();
+    let b = b.as_pointer::<T>();
+    match a.as_ref().cmp(b.as_ref()) {
+        core::cmp::Ordering::Less => -1,
+        core::cmp::Ordering::Equal => 0,
+        core::cmp::Ordering::Greater => 1,
+    }
+}
+
+/// `hash` operation for types implementing `Hash`
+pub unsafe extern "C" fn hash<T: Custom + core::hash::Hash>(v: Raw) -> Int {
+    use core::hash::Hasher;
+
+    // FNV-1a is used because OCaml hashes need to be stable across runs
+    struct Fnv(u64);
+
+    impl Hasher for Fnv {
+        fn write(&mut self, bytes: &[u8]) {
+            for b in bytes {
+                self.0 ^= *b as u64;
+                self.0 = self.0.wrapping_mul(0x100000001b3);
+            }
+        }
+
+        fn finish(&self) -> u64 {
+            self.0
+        }
+    }
+
+    let mut hasher = Fnv(0xcbf29ce484222325);
+    v.as_pointer::<T>().as_ref().hash(&mut hasher);
+    hasher.finish() as Int
+}
+
+/// Register the custom operations for `T` with the OCaml runtime, this is required before values
+/// of type `T` can be deserialized
+///
+/// This is called automatically when allocating a custom value with a `deserialize` operation, so
+/// it only needs to be called explicitly when OCaml may unmarshal a value of type `T` before one
+/// has been allocated, typically from an initialization function. It does nothing if the
+/// operations have already been registered
+pub fn register<T: Custom>() {
+    use core::sync::atomic::Ordering;
+
+    let registered = T::registered();
+    if registered.is_some_and(|x| x.load(Ordering::Acquire)) {
+        return;
+    }
+
+    let ops = T::ops();
+    if ops.identifier.is_null() {
+        return;
+    }
+
+    unsafe {
+        if sys::caml_find_custom_operations(ops.identifier).is_null() {
+            sys::caml_register_custom_operations(ops as *const _ as *mut sys::custom_operations);
+        }
+    }
+
+    if let Some(x) = registered {
+        x.store(true, Ordering::Release);
+    }
+}
+
 /// Derives `Custom` with the given finalizer for a type
 ///
 /// ```rust,no_run
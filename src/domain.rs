@@ -0,0 +1,81 @@
+//! Support for OCaml 5 domains
+//!
+//! `spawn` and `parallel` use the OCaml `Domain` module, which has no C API. `Domain.spawn` and
+//! `Domain.join` are registered by the bindings generated by `ocaml-build` when using OCaml 5,
+//! otherwise they need to be registered from OCaml first:
+//!
+//! ```ocaml
+//! let () =
+//!   Callback.register "Domain.spawn" Domain.spawn;
+//!   Callback.register "Domain.join" Domain.join
+//! ```
+
+use crate::{Error, Runtime, Value};
+
+/// Get the ID of the domain the current thread is running in, or `None` if the thread isn't
+/// attached to a domain. When using OCaml 4 this is always `Some(0)` once the runtime has been
+/// initialized
+pub fn id() -> Option<usize> {
+    unsafe {
+        if crate::sys::caml_sys_get_domain_state().is_null() {
+            return None;
+        }
+
+        Some(crate::sys::caml_sys_domain_id() as usize)
+    }
+}
+
+unsafe fn named(name: &'static str) -> Result<Value, Error> {
+    match Value::named(name) {
+        Some(f) => Ok(f),
+        None => Err(Error::Message(
+            "Domain.spawn and Domain.join have not been registered using Callback.register",
+        )),
+    }
+}
+
+/// A handle to a domain created using `spawn`
+pub struct Domain(Value);
+
+impl Domain {
+    /// Wait for the domain to finish, returning the result of the closure or the exception it
+    /// raised
+    pub fn join(self, rt: &Runtime) -> Result<Value, Error> {
+        unsafe {
+            let join = named("Domain.join")?;
+            join.call1(rt, &self.0)
+        }
+    }
+}
+
+/// Run an OCaml closure with the type `unit -> 'a` in a new domain
+pub fn spawn(rt: &Runtime, f: &Value) -> Result<Domain, Error> {
+    unsafe {
+        let spawn = named("Domain.spawn")?;
+        let domain = spawn.call1(rt, f)?;
+        Ok(Domain(domain))
+    }
+}
+
+/// Run each closure in a separate domain, returning the results once all of the domains have
+/// finished
+pub fn parallel(rt: &Runtime, fs: &[Value]) -> Result<Vec<Value>, Error> {
+    let mut domains = Vec::with_capacity(fs.len());
+    let mut err = None;
+    for f in fs {
+        match spawn(rt, f) {
+            Ok(d) => domains.push(d),
+            Err(e) => {
+                err = Some(e);
+                break;
+            }
+        }
+    }
+
+    // Join every domain that was started, even when one of them failed
+    let results: Vec<_> = domains.into_iter().map(|d| d.join(rt)).collect();
+    if let Some(e) = err {
+        return Err(e);
+    }
+    results.into_iter().collect()
+}
@@ -40,7 +40,64 @@ pub enum CamlError {
     WithArg(Value, Value),
 }
 
+// Returns the constructor of an exception value, this is the exception itself for exceptions
+// without arguments
+unsafe fn exception_constructor(exn: &Value) -> Option<Value> {
+    if !exn.is_block() {
+        return None;
+    }
+
+    if exn.tag() == crate::Tag::OBJECT {
+        return Some(exn.clone());
+    }
+
+    if exn.tag() == crate::Tag(0) && crate::sys::wosize_val(exn.raw().0) > 0 {
+        let constructor = exn.field(0);
+        if constructor.is_block() && constructor.tag() == crate::Tag::OBJECT {
+            return Some(constructor);
+        }
+    }
+
+    None
+}
+
+impl CamlError {
+    /// Convert an exception raised by OCaml into a `CamlError`, the predefined exceptions are
+    /// mapped to the matching variants. `Failure` and `Invalid_argument` become `WithArg` since
+    /// their messages aren't `'static`
+    pub(crate) unsafe fn from_exception(exn: Value) -> CamlError {
+        let constructor = match exception_constructor(&exn) {
+            Some(c) => c,
+            None => return CamlError::Exception(exn),
+        };
+
+        let nargs = if exn.tag() == crate::Tag::OBJECT {
+            0
+        } else {
+            crate::sys::wosize_val(exn.raw().0) - 1
+        };
+
+        match (constructor.field(0).bytes_val(), nargs) {
+            (b"Not_found", 0) => CamlError::NotFound,
+            (b"Out_of_memory", 0) => CamlError::OutOfMemory,
+            (b"Stack_overflow", 0) => CamlError::StackOverflow,
+            (b"End_of_file", 0) => CamlError::EndOfFile,
+            (b"Division_by_zero", 0) => CamlError::ZeroDivide,
+            (b"Sys_blocked_io", 0) => CamlError::SysBlockedIo,
+            (b"Sys_error", 1) => CamlError::SysError(exn.field(1)),
+            (b"Failure", 1) | (b"Invalid_argument", 1) => {
+                CamlError::WithArg(constructor, exn.field(1))
+            }
+            _ => CamlError::Exception(exn),
+        }
+    }
+}
+
 /// Error returned by `ocaml-rs` functions
+///
+/// `Error` doesn't implement `std::error::Error`, since that would conflict with the blanket
+/// `From` implementation used to convert other errors using `?`. It can be converted into a
+/// `Box<dyn std::error::Error>` instead
 #[derive(Debug)]
 pub enum Error {
     /// A value cannot be called using callback functions
@@ -58,8 +115,201 @@ pub enum Error {
 
     /// OCaml exceptions
     Caml(CamlError),
+
+    /// OCaml exception along with the backtrace recorded when it was raised, `Value::call*`
+    /// returns this instead of `Error::Caml` when backtraces are enabled, see `Backtrace`
+    #[cfg(not(feature = "no-std"))]
+    CamlBacktrace(CamlError, Backtrace),
+}
+
+/// Error returned by `#[ocaml::func(release_lock)]` functions
+///
+/// Unlike `Error`, this never references the OCaml heap, so it can be created while the runtime
+/// lock is released. It is raised as an OCaml exception once the lock has been reacquired
+#[cfg(not(feature = "no-std"))]
+#[derive(Debug)]
+pub enum OwnedError {
+    /// Not_found
+    NotFound,
+
+    /// Failure
+    Failure(String),
+
+    /// Invalid_argument
+    InvalidArgument(String),
+
+    /// General error, raised as `Failure`
+    Error(Box<dyn std::error::Error + Send>),
+}
+
+// `Value` isn't `Send`, so the boxed error can't contain any OCaml values
+#[cfg(not(feature = "no-std"))]
+unsafe impl crate::Owned for OwnedError {}
+
+#[cfg(not(feature = "no-std"))]
+impl<T: 'static + std::error::Error + Send> From<T> for OwnedError {
+    fn from(x: T) -> OwnedError {
+        OwnedError::Error(Box::new(x))
+    }
+}
+
+/// A single step into an OCaml value, used by `ConversionError` to locate the value that
+/// failed to convert
+#[cfg(not(feature = "no-std"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// Tuple field, array element or list element
+    Index(usize),
+
+    /// Named record field
+    Field(&'static str),
+
+    /// Variant constructor
+    Variant(&'static str),
+}
+
+/// Error returned by `TryFromValue` when an OCaml value doesn't have the expected shape
+#[cfg(not(feature = "no-std"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConversionError {
+    /// Description of the expected value
+    pub expected: String,
+
+    /// Description of the value that was found
+    pub found: String,
+
+    /// Path to the value that failed to convert, starting from the outermost value
+    pub path: Vec<PathSegment>,
+}
+
+#[cfg(not(feature = "no-std"))]
+impl ConversionError {
+    /// Create a new error for `value`, which was expected to be `expected`
+    pub fn new(expected: impl Into<String>, value: &Value) -> ConversionError {
+        ConversionError {
+            expected: expected.into(),
+            found: unsafe { describe_value(value) },
+            path: Vec::new(),
+        }
+    }
+
+    /// Prepend `segment` to the error path, this is used when converting nested values
+    #[must_use]
+    pub fn at(mut self, segment: PathSegment) -> ConversionError {
+        self.path.insert(0, segment);
+        self
+    }
+
+    #[doc(hidden)]
+    pub unsafe fn expect_immediate(value: &Value, expected: &str) -> Result<isize, Self> {
+        if value.is_block() {
+            return Err(ConversionError::new(expected, value));
+        }
+
+        Ok(value.int_val())
+    }
+
+    #[doc(hidden)]
+    pub unsafe fn expect_block(
+        value: &Value,
+        expected: &str,
+        tag: crate::Tag,
+        size: Option<usize>,
+    ) -> Result<(), Self> {
+        if !value.is_block() || value.tag() != tag {
+            return Err(ConversionError::new(expected, value));
+        }
+
+        if let Some(size) = size {
+            let len = if tag == crate::Tag::DOUBLE_ARRAY {
+                value.array_length()
+            } else {
+                crate::sys::wosize_val(value.raw().0)
+            };
+
+            if len != size {
+                return Err(ConversionError::new(expected, value));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[doc(hidden)]
+    pub unsafe fn expect_custom(
+        value: &Value,
+        expected: &str,
+        identifier: &str,
+    ) -> Result<(), Self> {
+        if !value.is_block() || value.tag() != crate::Tag::CUSTOM {
+            return Err(ConversionError::new(expected, value));
+        }
+
+        if custom_identifier(value).map(|x| x.to_bytes()) != Some(identifier.as_bytes()) {
+            return Err(ConversionError::new(expected, value));
+        }
+
+        Ok(())
+    }
 }
 
+/// Returns the identifier of a custom block's `custom_operations`
+#[cfg(not(feature = "no-std"))]
+pub(crate) unsafe fn custom_identifier(value: &Value) -> Option<&core::ffi::CStr> {
+    let ops = *(value.raw().0 as *const *const crate::sys::custom_operations);
+    if ops.is_null() || (*ops).identifier.is_null() {
+        return None;
+    }
+
+    Some(core::ffi::CStr::from_ptr((*ops).identifier))
+}
+
+#[cfg(not(feature = "no-std"))]
+unsafe fn describe_value(value: &Value) -> String {
+    if value.is_long() {
+        return format!("int {}", value.int_val());
+    }
+
+    match value.tag() {
+        crate::Tag::STRING => "string".to_string(),
+        crate::Tag::DOUBLE => "float".to_string(),
+        crate::Tag::DOUBLE_ARRAY => "float array".to_string(),
+        crate::Tag::CLOSURE | crate::Tag::INFIX => "closure".to_string(),
+        crate::Tag::CUSTOM => match custom_identifier(value) {
+            Some(id) => format!("custom block {}", id.to_string_lossy()),
+            None => "custom block".to_string(),
+        },
+        tag => format!(
+            "block with tag {} and size {}",
+            tag.0,
+            crate::sys::wosize_val(value.raw().0)
+        ),
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl core::fmt::Display for ConversionError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "expected {}, found {}", self.expected, self.found)?;
+        if self.path.is_empty() {
+            return Ok(());
+        }
+
+        write!(f, " at ")?;
+        for segment in &self.path {
+            match segment {
+                PathSegment::Index(i) => write!(f, "[{i}]")?,
+                PathSegment::Field(name) => write!(f, ".{name}")?,
+                PathSegment::Variant(name) => write!(f, "({name})")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl std::error::Error for ConversionError {}
+
 #[cfg(not(feature = "no-std"))]
 impl<T: 'static + std::error::Error> From<T> for Error {
     fn from(x: T) -> Error {
@@ -67,6 +317,289 @@ impl<T: 'static + std::error::Error> From<T> for Error {
     }
 }
 
+/// Formatting `Exception`, `WithArg` and `SysError` reads the exception from the OCaml heap, so
+/// the runtime lock must be held. Errors shouldn't be formatted inside of `Runtime::without_lock`
+/// or a `release_lock` function
+#[cfg(not(feature = "no-std"))]
+impl core::fmt::Display for CamlError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CamlError::NotFound => write!(f, "Not_found"),
+            CamlError::Failure(s) => write!(f, "Failure({s:?})"),
+            CamlError::InvalidArgument(s) => write!(f, "Invalid_argument({s:?})"),
+            CamlError::OutOfMemory => write!(f, "Out_of_memory"),
+            CamlError::StackOverflow => write!(f, "Stack_overflow"),
+            CamlError::SysError(s) => unsafe {
+                write!(f, "Sys_error(")?;
+                fmt_exception_arg(f, s)?;
+                write!(f, ")")
+            },
+            CamlError::EndOfFile => write!(f, "End_of_file"),
+            CamlError::ZeroDivide => write!(f, "Division_by_zero"),
+            CamlError::ArrayBoundError => write!(f, "Invalid_argument(\"index out of bounds\")"),
+            CamlError::SysBlockedIo => write!(f, "Sys_blocked_io"),
+            CamlError::Exception(e) => match unsafe { e.exception_to_string() } {
+                Ok(s) => write!(f, "{s}"),
+                Err(_) => write!(f, "<exception>"),
+            },
+            CamlError::WithArg(exn, arg) => unsafe {
+                // `exn` is the exception constructor, the first field of which is its name
+                if exn.is_block() && exn.tag() == crate::Tag::OBJECT {
+                    write!(f, "{}", exn.field(0).string_val())?;
+                } else {
+                    write!(f, "<exception>")?;
+                }
+                write!(f, "(")?;
+                fmt_exception_arg(f, arg)?;
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+// Formats an exception argument the same way as `Printexc.to_string` for unregistered printers
+#[cfg(not(feature = "no-std"))]
+unsafe fn fmt_exception_arg(f: &mut core::fmt::Formatter<'_>, v: &Value) -> core::fmt::Result {
+    if v.is_long() {
+        return write!(f, "{}", v.int_val());
+    }
+
+    match v.tag() {
+        crate::Tag::STRING => write!(f, "{:?}", v.string_val()),
+        crate::Tag::DOUBLE => write!(f, "{}", v.double_val()),
+        _ => write!(f, "_"),
+    }
+}
+
+/// OCaml exceptions are read from the OCaml heap, see the `Display` implementation for `CamlError`
+#[cfg(not(feature = "no-std"))]
+impl core::fmt::Display for Error {
+    // The alternate format, `{:#}`, includes the OCaml backtrace when one was recorded
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::NotCallable => write!(f, "value is not callable")?,
+            Error::NotDoubleArray => write!(f, "invalid double array")?,
+            Error::Message(s) => write!(f, "{s}")?,
+            Error::Error(e) => write!(f, "{e}")?,
+            Error::Caml(e) | Error::CamlBacktrace(e, _) => write!(f, "{e}")?,
+        }
+
+        if f.alternate() {
+            if let Some(bt) = self.backtrace() {
+                write!(f, "\n{bt}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// `Error` can't implement `std::error::Error` because of the blanket `From` implementation above,
+// so conversion to `Box<dyn std::error::Error>` goes through a wrapper instead
+#[cfg(not(feature = "no-std"))]
+struct StdError(Error);
+
+#[cfg(not(feature = "no-std"))]
+impl core::fmt::Debug for StdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl core::fmt::Display for StdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl std::error::Error for StdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.0 {
+            Error::Error(e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl From<Error> for Box<dyn std::error::Error> {
+    fn from(x: Error) -> Box<dyn std::error::Error> {
+        match x {
+            Error::Error(e) => e,
+            x => Box::new(StdError(x)),
+        }
+    }
+}
+
+/// Source location of a backtrace frame, see `Printexc.location`
+#[cfg(not(feature = "no-std"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Source file name
+    pub filename: String,
+
+    /// Line number
+    pub line: usize,
+
+    /// First character of the location on `line`
+    pub start_char: usize,
+
+    /// Last character of the location on `line`
+    pub end_char: usize,
+
+    /// True when the frame was inlined by the compiler
+    pub is_inline: bool,
+
+    /// Name of the enclosing definition, not available before OCaml 4.11
+    pub defname: Option<String>,
+}
+
+/// A single frame of an OCaml backtrace, see `Printexc.backtrace_slot`
+#[cfg(not(feature = "no-std"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BacktraceFrame {
+    /// True when the frame is a `raise` rather than a function call
+    pub is_raise: bool,
+
+    /// Source location, `None` when there is no debug information for the frame
+    pub location: Option<Location>,
+}
+
+/// OCaml backtrace for an exception
+///
+/// Backtraces are only recorded when enabled using `Printexc.record_backtrace` or
+/// `OCAMLRUNPARAM=b`, and require the OCaml code to be compiled with `-g`. `Display` matches the
+/// output of `Printexc.print_raw_backtrace`
+#[cfg(not(feature = "no-std"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backtrace {
+    /// Frames, starting from the point where the exception was raised
+    pub frames: Vec<BacktraceFrame>,
+}
+
+#[cfg(not(feature = "no-std"))]
+impl Backtrace {
+    /// Get the backtrace of the most recently raised exception on the current domain, returns
+    /// `None` if backtraces aren't being recorded or no debug information is available
+    pub fn capture(_rt: &Runtime) -> Option<Backtrace> {
+        unsafe {
+            if crate::sys::caml_backtrace_status(crate::sys::UNIT) != crate::sys::TRUE
+                || crate::sys::caml_debug_info_available() == 0
+            {
+                return None;
+            }
+
+            let raw = Value::new(crate::sys::caml_get_exception_raw_backtrace(
+                crate::sys::UNIT,
+            ));
+            let slots = Value::new(crate::sys::caml_convert_raw_backtrace(raw.raw().0));
+            let frames = (0..crate::sys::wosize_val(slots.raw().0))
+                .map(|i| BacktraceFrame::from_value(&slots.field(i)))
+                .collect();
+            Some(Backtrace { frames })
+        }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl BacktraceFrame {
+    unsafe fn from_value(slot: &Value) -> BacktraceFrame {
+        let is_raise = slot.field(0).int_val() != 0;
+        if slot.tag() != crate::Tag(0) {
+            return BacktraceFrame {
+                is_raise,
+                location: None,
+            };
+        }
+
+        // OCaml 5.2 added `end_offset` and `end_lnum`, `defname` was added in 4.11
+        let size = crate::sys::wosize_val(slot.raw().0);
+        let (end_char, is_inline) = if size >= 9 { (6, 7) } else { (4, 5) };
+        let defname = if size >= 7 {
+            Some(slot.field(size - 1).string_val().to_owned())
+        } else {
+            None
+        };
+
+        BacktraceFrame {
+            is_raise,
+            location: Some(Location {
+                filename: slot.field(1).string_val().to_owned(),
+                line: slot.field(2).int_val() as usize,
+                start_char: slot.field(3).int_val() as usize,
+                end_char: slot.field(end_char).int_val() as usize,
+                is_inline: slot.field(is_inline).int_val() != 0,
+                defname,
+            }),
+        }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl core::fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            let info = match (frame.is_raise, i) {
+                (true, 0) => "Raised at",
+                (true, _) => "Re-raised at",
+                (false, 0) => "Raised by primitive operation at",
+                (false, _) => "Called from",
+            };
+
+            match &frame.location {
+                // Unknown locations are only printed for function calls
+                None if frame.is_raise => continue,
+                None => writeln!(f, "{info} unknown location")?,
+                Some(loc) => {
+                    write!(f, "{info} ")?;
+                    if let Some(defname) = &loc.defname {
+                        write!(f, "{defname} in ")?;
+                    }
+                    writeln!(
+                        f,
+                        "file {:?}{}, line {}, characters {}-{}",
+                        loc.filename,
+                        if loc.is_inline { " (inlined)" } else { "" },
+                        loc.line,
+                        loc.start_char,
+                        loc.end_char
+                    )?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Convert a Rust error into an OCaml exception
+///
+/// This can be derived for enums using `#[derive(ocaml::IntoException)]`, each variant is mapped
+/// to an exception registered using `Callback.register_exception` and the variant's fields are
+/// converted to the exception arguments using `ToValue`. The exception name defaults to the name
+/// of the variant and can be set using `#[exception(name = "...")]`. `ocaml-build` generates the
+/// matching `exception` declarations, the OCaml type of the arguments is set using
+/// `#[exception(sig = "...")]`:
+///
+/// ```rust,ignore
+/// #[derive(ocaml::IntoException)]
+/// pub enum MyError {
+///     // exception Not_ready
+///     #[exception(name = "Not_ready")]
+///     NotReady,
+///
+///     // exception My_error of int * string
+///     #[exception(name = "My_error", sig = "int * string")]
+///     Code(ocaml::Int, String),
+/// }
+/// ```
+pub trait IntoException {
+    /// Convert `self` into an `Error` that raises the matching OCaml exception when returned to
+    /// OCaml
+    fn into_exception(self, rt: &Runtime) -> Error;
+}
+
 impl From<CamlError> for Error {
     fn from(x: CamlError) -> Error {
         Error::Caml(x)
@@ -108,6 +641,28 @@ impl Error {
         Err(CamlError::WithArg(value, arg).into())
     }
 
+    /// Create an exception that has been registered using `Callback.register_exception` with
+    /// any number of arguments
+    pub fn exception_with_args<S: AsRef<str>>(rt: &Runtime, exc: S, args: &[Value]) -> Error {
+        let value = match unsafe { Value::named(exc.as_ref()) } {
+            Some(v) => v,
+            None => return Error::Message("Value has not been registered with the OCaml runtime"),
+        };
+
+        if args.is_empty() {
+            return CamlError::Exception(value).into();
+        }
+
+        unsafe {
+            let mut exn = Value::alloc(args.len() + 1, crate::Tag(0));
+            exn.store_field(rt, 0, value);
+            for (i, arg) in args.iter().enumerate() {
+                exn.store_field(rt, i + 1, arg);
+            }
+            CamlError::Exception(exn).into()
+        }
+    }
+
     /// Raise `Not_found`
     pub fn not_found() -> Result<(), Error> {
         Err(CamlError::NotFound.into())
@@ -140,6 +695,23 @@ impl Error {
         loop {}
     }
 
+    #[doc(hidden)]
+    pub fn raise_invalid_argument(s: impl AsRef<str>) -> ! {
+        unsafe {
+            let value = {
+                let s = s.as_ref();
+                let value = crate::sys::caml_alloc_string(s.len());
+                let ptr = crate::sys::string_val(value);
+                core::ptr::copy_nonoverlapping(s.as_ptr(), ptr, s.len());
+                value
+            };
+            drop(s);
+            crate::sys::caml_invalid_argument_value(value);
+        }
+        #[allow(clippy::empty_loop)]
+        loop {}
+    }
+
     #[doc(hidden)]
     pub fn raise_value(v: Value, x: Value) -> ! {
         unsafe {
@@ -149,6 +721,97 @@ impl Error {
         loop {}
     }
 
+    /// Get the OCaml exception, for `Error::Caml` and `Error::CamlBacktrace`
+    pub fn caml_error(&self) -> Option<&CamlError> {
+        match self {
+            Error::Caml(e) => Some(e),
+            #[cfg(not(feature = "no-std"))]
+            Error::CamlBacktrace(e, _) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Get the OCaml backtrace for an exception returned by one of the `Value::call*` functions,
+    /// this is only available when backtraces are enabled, see `Backtrace`
+    #[cfg(not(feature = "no-std"))]
+    pub fn backtrace(&self) -> Option<&Backtrace> {
+        match self {
+            Error::CamlBacktrace(_, bt) => Some(bt),
+            _ => None,
+        }
+    }
+
+    /// Get the name of the OCaml exception, for example `Not_found` or `Module.My_error`. This
+    /// returns `None` for errors that aren't OCaml exceptions
+    #[cfg(not(feature = "no-std"))]
+    pub fn exception_name(&self) -> Option<String> {
+        let name = match self.caml_error()? {
+            CamlError::NotFound => "Not_found",
+            CamlError::Failure(_) => "Failure",
+            CamlError::InvalidArgument(_) => "Invalid_argument",
+            CamlError::OutOfMemory => "Out_of_memory",
+            CamlError::StackOverflow => "Stack_overflow",
+            CamlError::SysError(_) => "Sys_error",
+            CamlError::EndOfFile => "End_of_file",
+            CamlError::ZeroDivide => "Division_by_zero",
+            CamlError::ArrayBoundError => "Invalid_argument",
+            CamlError::SysBlockedIo => "Sys_blocked_io",
+            CamlError::Exception(e) | CamlError::WithArg(e, _) => {
+                return unsafe {
+                    exception_constructor(e)
+                        .map(|c| String::from_utf8_lossy(c.field(0).bytes_val()).into_owned())
+                };
+            }
+        };
+        Some(name.to_string())
+    }
+
+    /// Returns true when the error is the OCaml exception `name`. Exceptions defined in wrapped
+    /// libraries have the library prefixed to their names, so `name` may also match the end of
+    /// the full name: `Foo.My_error` matches `Mylib.Foo.My_error`
+    #[cfg(not(feature = "no-std"))]
+    pub fn is_exception(&self, name: &str) -> bool {
+        match self.exception_name() {
+            Some(n) => n
+                .strip_suffix(name)
+                .is_some_and(|prefix| prefix.is_empty() || prefix.ends_with('.')),
+            None => false,
+        }
+    }
+
+    /// Decode the arguments of an OCaml exception, exceptions with multiple arguments are
+    /// decoded as a tuple and exceptions without arguments as `()`. This returns `None` for
+    /// errors that aren't OCaml exceptions
+    #[cfg(not(feature = "no-std"))]
+    pub fn exception_payload<T: FromValue>(&self, rt: &Runtime) -> Option<T> {
+        let err = self.caml_error()?;
+
+        let payload = unsafe {
+            match err {
+                CamlError::Failure(s) | CamlError::InvalidArgument(s) => s.to_value(rt),
+                CamlError::ArrayBoundError => "index out of bounds".to_value(rt),
+                CamlError::SysError(arg) | CamlError::WithArg(_, arg) => arg.clone(),
+                CamlError::Exception(e) => {
+                    exception_constructor(e)?;
+                    match crate::sys::wosize_val(e.raw().0) {
+                        _ if e.tag() == crate::Tag::OBJECT => Value::unit(),
+                        2 => e.field(1),
+                        n => {
+                            let mut tuple = Value::alloc_tuple(n - 1);
+                            for i in 1..n {
+                                tuple.store_field(rt, i - 1, e.field(i));
+                            }
+                            tuple
+                        }
+                    }
+                }
+                _ => Value::unit(),
+            }
+        };
+
+        Some(T::from_value(payload))
+    }
+
     /// Get named error registered using `Callback.register_exception`
     pub fn named<S: AsRef<str>>(s: S) -> Option<Value> {
         unsafe { Value::named(s.as_ref()) }
@@ -166,58 +829,45 @@ unsafe impl<T: ToValue, E: ToValue> ToValue for Result<T, E> {
     }
 }
 
+// Raise the OCaml exception for `err`
+unsafe fn raise_caml_error(err: &CamlError) -> ! {
+    match err {
+        CamlError::Exception(e) => crate::sys::caml_raise(e.raw().0),
+        CamlError::NotFound => crate::sys::caml_raise_not_found(),
+        CamlError::ArrayBoundError => crate::sys::caml_array_bound_error(),
+        CamlError::OutOfMemory => crate::sys::caml_raise_out_of_memory(),
+        CamlError::EndOfFile => crate::sys::caml_raise_end_of_file(),
+        CamlError::StackOverflow => crate::sys::caml_raise_stack_overflow(),
+        CamlError::ZeroDivide => crate::sys::caml_raise_zero_divide(),
+        CamlError::SysBlockedIo => crate::sys::caml_raise_sys_blocked_io(),
+        CamlError::InvalidArgument(s) => {
+            let s = crate::util::CString::new(*s).expect("Invalid C string");
+            crate::sys::caml_invalid_argument(s.as_ptr() as *const ocaml_sys::Char)
+        }
+        CamlError::WithArg(a, b) => crate::sys::caml_raise_with_arg(a.raw().0, b.raw().0),
+        CamlError::SysError(s) => crate::sys::caml_raise_sys_error(s.raw().0),
+        CamlError::Failure(s) => {
+            let s = crate::util::CString::new(*s).expect("Invalid C string");
+            crate::sys::caml_failwith(s.as_ptr() as *const ocaml_sys::Char)
+        }
+    }
+
+    unreachable!()
+}
+
 unsafe impl<T: ToValue> ToValue for Result<T, Error> {
     fn to_value(&self, rt: &Runtime) -> Value {
         match self {
             Ok(x) => return x.to_value(rt),
-            Err(Error::Caml(CamlError::Exception(e))) => unsafe {
-                crate::sys::caml_raise(e.raw().0);
-            },
-            Err(Error::Caml(CamlError::NotFound)) => unsafe {
-                crate::sys::caml_raise_not_found();
-            },
-            Err(Error::Caml(CamlError::ArrayBoundError)) => unsafe {
-                crate::sys::caml_array_bound_error();
-            },
-            Err(Error::Caml(CamlError::OutOfMemory)) => unsafe {
-                crate::sys::caml_raise_out_of_memory();
-            },
-            Err(Error::Caml(CamlError::EndOfFile)) => unsafe {
-                crate::sys::caml_raise_end_of_file()
-            },
-            Err(Error::Caml(CamlError::StackOverflow)) => unsafe {
-                crate::sys::caml_raise_stack_overflow()
-            },
-            Err(Error::Caml(CamlError::ZeroDivide)) => unsafe {
-                crate::sys::caml_raise_zero_divide()
-            },
-            Err(Error::Caml(CamlError::SysBlockedIo)) => unsafe {
-                crate::sys::caml_raise_sys_blocked_io()
-            },
-            Err(Error::Caml(CamlError::InvalidArgument(s))) => {
-                unsafe {
-                    let s = crate::util::CString::new(*s).expect("Invalid C string");
-                    crate::sys::caml_invalid_argument(s.as_ptr() as *const ocaml_sys::Char)
-                };
-            }
-            Err(Error::Caml(CamlError::WithArg(a, b))) => unsafe {
-                crate::sys::caml_raise_with_arg(a.raw().0, b.raw().0)
-            },
-            Err(Error::Caml(CamlError::SysError(s))) => {
-                unsafe { crate::sys::caml_raise_sys_error(s.raw().0) };
-            }
+            Err(Error::Caml(e)) => unsafe { raise_caml_error(e) },
+            #[cfg(not(feature = "no-std"))]
+            Err(Error::CamlBacktrace(e, _)) => unsafe { raise_caml_error(e) },
             Err(Error::Message(s)) => {
                 unsafe {
                     let s = crate::util::CString::new(*s).expect("Invalid C string");
                     crate::sys::caml_failwith(s.as_ptr() as *const ocaml_sys::Char)
                 };
             }
-            Err(Error::Caml(CamlError::Failure(s))) => {
-                unsafe {
-                    let s = crate::util::CString::new(*s).expect("Invalid C string");
-                    crate::sys::caml_failwith(s.as_ptr() as *const ocaml_sys::Char)
-                };
-            }
             #[cfg(not(feature = "no-std"))]
             Err(Error::Error(e)) => {
                 let s = format!("{e:?}\0");
@@ -237,6 +887,31 @@ unsafe impl<T: ToValue> ToValue for Result<T, Error> {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T: ToValue> ToValue for Result<T, OwnedError> {
+    fn to_value(&self, rt: &Runtime) -> Value {
+        match self {
+            Ok(x) => return x.to_value(rt),
+            Err(OwnedError::NotFound) => unsafe { crate::sys::caml_raise_not_found() },
+            // The messages are unrooted before raising, nothing is allocated in between
+            Err(OwnedError::Failure(s)) => unsafe {
+                let s = s.to_value(rt).raw().0;
+                crate::sys::caml_failwith_value(s)
+            },
+            Err(OwnedError::InvalidArgument(s)) => unsafe {
+                let s = s.to_value(rt).raw().0;
+                crate::sys::caml_invalid_argument_value(s)
+            },
+            Err(OwnedError::Error(e)) => unsafe {
+                let s = format!("{e:?}").to_value(rt).raw().0;
+                crate::sys::caml_failwith_value(s)
+            },
+        };
+
+        unreachable!()
+    }
+}
+
 unsafe impl<T: FromValue> FromValue for Result<T, crate::Error> {
     fn from_value(value: Value) -> Result<T, crate::Error> {
         unsafe {
@@ -124,13 +124,17 @@
 #[cfg(all(feature = "link", feature = "no-std"))]
 std::compile_error!("Cannot use link and no-std features");
 
+#[cfg(all(feature = "serde", feature = "no-std"))]
+std::compile_error!("Cannot use serde and no-std features");
+
 /// The `sys` module contains the low-level implementation of the OCaml runtime
 pub use ocaml_sys as sys;
 
 #[cfg(feature = "derive")]
 pub use ocaml_derive::{
-    ocaml_bytecode_func as bytecode_func, ocaml_func as func, ocaml_native_func as native_func,
-    ocaml_sig as sig, FromValue, ToValue,
+    ocaml_bytecode_func as bytecode_func, ocaml_func as func, ocaml_methods as methods,
+    ocaml_native_func as native_func, ocaml_sig as sig, Custom, FromValue, IntoException, ToValue,
+    TryFromValue,
 };
 
 #[macro_use]
@@ -144,6 +148,12 @@ mod types;
 mod util;
 mod value;
 
+#[cfg(not(feature = "no-std"))]
+mod channel;
+
+#[cfg(not(feature = "no-std"))]
+mod closure;
+
 /// Rooted values
 pub mod root;
 
@@ -153,14 +163,39 @@ pub mod runtime;
 /// Custom types, used for allocating Rust values owned by the OCaml garbage collector
 pub mod custom;
 
-pub use crate::custom::Custom;
-pub use crate::error::{CamlError, Error};
+#[cfg(not(feature = "no-std"))]
+pub mod domain;
+
+#[cfg(not(feature = "no-std"))]
+pub mod marshal;
+
+#[cfg(not(feature = "no-std"))]
+pub mod memprof;
+
+#[cfg(not(feature = "no-std"))]
+pub mod runtime_events;
+
+#[cfg(feature = "serde")]
+pub mod serde;
+
+#[cfg(not(feature = "no-std"))]
+pub use crate::channel::{InChannel, OutChannel};
+#[cfg(not(feature = "no-std"))]
+pub use crate::closure::IntoClosure;
+pub use crate::custom::{Custom, CustomSerialize};
+#[cfg(not(feature = "no-std"))]
+pub use crate::error::{
+    Backtrace, BacktraceFrame, ConversionError, Location, OwnedError, PathSegment,
+};
+pub use crate::error::{CamlError, Error, IntoException};
 pub use crate::pointer::Pointer;
 pub use crate::runtime::Runtime;
 pub use crate::runtime::*;
 pub use crate::tag::Tag;
-pub use crate::types::{bigarray, Array, List, Seq};
-pub use crate::value::{FromValue, Raw, ToValue, Value};
+pub use crate::types::{bigarray, Array, Callback, CallbackArgs, Ephemeron, List, Seq, Weak};
+#[cfg(not(feature = "no-std"))]
+pub use crate::value::TryFromValue;
+pub use crate::value::{FromValue, MarshalFlag, Owned, Raw, ToValue, Value};
 
 /// OCaml `float`
 pub type Float = f64;
@@ -0,0 +1,816 @@
+//! Runtime-free encoder and decoder for the OCaml `Marshal` format
+//!
+//! The functions in this module don't require the OCaml runtime to be initialized (or even
+//! linked), making it possible to read and write data produced by `Marshal.to_string`,
+//! `Marshal.to_channel` and `output_value` from Rust programs that don't embed OCaml.
+//!
+//! Marshalled data is decoded into an owned [Data] tree, objects that are shared (or cyclic)
+//! in the original value are represented using [Data::Shared], which refers to the index of an
+//! object in the order returned by [Data::objects].
+//!
+//! ```rust
+//! use ocaml::marshal::{self, Data};
+//!
+//! let data = Data::Block(0, vec![Data::Int(1), Data::String(b"abc".to_vec())]);
+//! let bytes = marshal::encode(&data).unwrap();
+//! assert_eq!(marshal::decode(&bytes).unwrap(), data);
+//! ```
+//!
+//! Closures and custom blocks other than `int32`, `int64` and `nativeint` are not supported.
+
+const MAGIC_NUMBER_SMALL: u32 = 0x8495A6BE;
+const MAGIC_NUMBER_BIG: u32 = 0x8495A6BF;
+const MAGIC_NUMBER_COMPRESSED: u32 = 0x8495A6BD;
+
+const HEADER_SIZE_SMALL: usize = 20;
+const HEADER_SIZE_BIG: usize = 32;
+
+const PREFIX_SMALL_BLOCK: u8 = 0x80;
+const PREFIX_SMALL_INT: u8 = 0x40;
+const PREFIX_SMALL_STRING: u8 = 0x20;
+
+const CODE_INT8: u8 = 0x00;
+const CODE_INT16: u8 = 0x01;
+const CODE_INT32: u8 = 0x02;
+const CODE_INT64: u8 = 0x03;
+const CODE_SHARED8: u8 = 0x04;
+const CODE_SHARED16: u8 = 0x05;
+const CODE_SHARED32: u8 = 0x06;
+const CODE_SHARED64: u8 = 0x14;
+const CODE_BLOCK32: u8 = 0x08;
+const CODE_BLOCK64: u8 = 0x13;
+const CODE_STRING8: u8 = 0x09;
+const CODE_STRING32: u8 = 0x0A;
+const CODE_STRING64: u8 = 0x15;
+const CODE_DOUBLE_BIG: u8 = 0x0B;
+const CODE_DOUBLE_LITTLE: u8 = 0x0C;
+const CODE_DOUBLE_ARRAY8_BIG: u8 = 0x0D;
+const CODE_DOUBLE_ARRAY8_LITTLE: u8 = 0x0E;
+const CODE_DOUBLE_ARRAY32_BIG: u8 = 0x0F;
+const CODE_DOUBLE_ARRAY32_LITTLE: u8 = 0x07;
+const CODE_DOUBLE_ARRAY64_BIG: u8 = 0x16;
+const CODE_DOUBLE_ARRAY64_LITTLE: u8 = 0x17;
+const CODE_CODEPOINTER: u8 = 0x10;
+const CODE_INFIXPOINTER: u8 = 0x11;
+const CODE_CUSTOM: u8 = 0x12;
+const CODE_CUSTOM_LEN: u8 = 0x18;
+const CODE_CUSTOM_FIXED: u8 = 0x19;
+
+/// Blocks with tags greater than or equal to `No_scan_tag` aren't structured blocks
+const NO_SCAN_TAG: u8 = 251;
+
+/// An owned representation of a marshalled OCaml value
+///
+/// `Drop` and `PartialEq` are implemented without recursion, so deeply nested values like long
+/// lists can be handled safely
+#[derive(Debug, Clone)]
+pub enum Data {
+    /// Immediate value: `int`, `char`, `bool`, constant constructors
+    Int(i64),
+
+    /// Structured block with a tag and fields: tuples, records, arrays, non-constant
+    /// constructors
+    Block(u8, Vec<Data>),
+
+    /// `string` or `bytes`
+    String(Vec<u8>),
+
+    /// Boxed `float`
+    Double(f64),
+
+    /// Unboxed float array: `float array`, `floatarray` or a record with only `float` fields
+    DoubleArray(Vec<f64>),
+
+    /// `int32` custom block
+    Int32(i32),
+
+    /// `int64` custom block
+    Int64(i64),
+
+    /// `nativeint` custom block
+    Nativeint(i64),
+
+    /// Reference to an object that has already been encountered, see `Data::objects`
+    Shared(usize),
+}
+
+impl Data {
+    /// Returns true when the value is allocated in the OCaml heap, these are the values that can
+    /// be referred to using `Data::Shared`
+    pub fn is_object(&self) -> bool {
+        match self {
+            Data::Int(_) | Data::Shared(_) => false,
+            Data::Block(_, fields) => !fields.is_empty(),
+            _ => true,
+        }
+    }
+
+    /// Returns all objects in the order they're numbered in the marshalled data, the index of an
+    /// object in this list is the index used by `Data::Shared`
+    pub fn objects(&self) -> Vec<&Data> {
+        let mut objects = Vec::new();
+        let mut stack = vec![self];
+        while let Some(data) = stack.pop() {
+            if data.is_object() {
+                objects.push(data);
+            }
+
+            if let Data::Block(_, fields) = data {
+                stack.extend(fields.iter().rev());
+            }
+        }
+        objects
+    }
+}
+
+impl Drop for Data {
+    fn drop(&mut self) {
+        let mut stack = match self {
+            Data::Block(_, fields) if !fields.is_empty() => core::mem::take(fields),
+            _ => return,
+        };
+
+        // The fields of each block are moved onto the stack before it's dropped, so every value
+        // is dropped without any fields
+        while let Some(mut data) = stack.pop() {
+            if let Data::Block(_, fields) = &mut data {
+                stack.append(fields);
+            }
+        }
+    }
+}
+
+impl PartialEq for Data {
+    fn eq(&self, other: &Data) -> bool {
+        let mut stack = vec![(self, other)];
+        while let Some(pair) = stack.pop() {
+            let eq = match pair {
+                (Data::Block(a_tag, a), Data::Block(b_tag, b)) => {
+                    if a_tag != b_tag || a.len() != b.len() {
+                        return false;
+                    }
+                    stack.extend(a.iter().zip(b));
+                    true
+                }
+                (Data::Int(a), Data::Int(b)) => a == b,
+                (Data::String(a), Data::String(b)) => a == b,
+                (Data::Double(a), Data::Double(b)) => a == b,
+                (Data::DoubleArray(a), Data::DoubleArray(b)) => a == b,
+                (Data::Int32(a), Data::Int32(b)) => a == b,
+                (Data::Int64(a), Data::Int64(b)) => a == b,
+                (Data::Nativeint(a), Data::Nativeint(b)) => a == b,
+                (Data::Shared(a), Data::Shared(b)) => a == b,
+                _ => false,
+            };
+
+            if !eq {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Marshal encoding and decoding errors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The input ended before the value was fully decoded
+    UnexpectedEof,
+
+    /// The input doesn't start with a known magic number
+    InvalidMagicNumber(u32),
+
+    /// Compressed marshalled data is not supported
+    Compressed,
+
+    /// The input contains an unknown or unsupported code
+    UnsupportedCode(u8),
+
+    /// The input contains a custom block that can't be decoded
+    UnsupportedCustom(String),
+
+    /// A shared reference points to an object that doesn't exist
+    InvalidShared(usize),
+
+    /// A block has a tag that can't be used for structured blocks
+    InvalidTag(u8),
+
+    /// The length from the header doesn't match the length of the data
+    InvalidLength,
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::UnexpectedEof => write!(f, "unexpected end of input"),
+            Error::InvalidMagicNumber(n) => write!(f, "invalid magic number: {n:#x}"),
+            Error::Compressed => write!(f, "compressed data is not supported"),
+            Error::UnsupportedCode(c) => write!(f, "unsupported code: {c:#x}"),
+            Error::UnsupportedCustom(s) => write!(f, "unsupported custom block: {s}"),
+            Error::InvalidShared(n) => write!(f, "invalid shared reference: {n}"),
+            Error::InvalidTag(t) => write!(f, "invalid block tag: {t}"),
+            Error::InvalidLength => write!(f, "invalid data length"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(n).ok_or(Error::UnexpectedEof)?;
+        let b = self.data.get(self.pos..end).ok_or(Error::UnexpectedEof)?;
+        self.pos = end;
+        Ok(b)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        let mut dest = [0; N];
+        dest.copy_from_slice(self.bytes(N)?);
+        Ok(dest)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_be_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_be_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    fn size(&self, n: u64) -> Result<usize, Error> {
+        usize::try_from(n).map_err(|_| Error::InvalidLength)
+    }
+
+    fn double(&mut self, big_endian: bool) -> Result<f64, Error> {
+        let b = self.array()?;
+        Ok(if big_endian {
+            f64::from_be_bytes(b)
+        } else {
+            f64::from_le_bytes(b)
+        })
+    }
+
+    fn doubles(&mut self, n: usize, big_endian: bool) -> Result<Vec<f64>, Error> {
+        // Check the length before allocating
+        if self.data.len() - self.pos < n.saturating_mul(8) {
+            return Err(Error::UnexpectedEof);
+        }
+        (0..n).map(|_| self.double(big_endian)).collect()
+    }
+
+    fn cstr(&mut self) -> Result<&'a [u8], Error> {
+        let rest = &self.data[self.pos..];
+        let n = rest
+            .iter()
+            .position(|x| *x == 0)
+            .ok_or(Error::UnexpectedEof)?;
+        self.pos += n + 1;
+        Ok(&rest[..n])
+    }
+
+    fn custom(&mut self, code: u8) -> Result<Data, Error> {
+        let identifier = self.cstr()?;
+        if code == CODE_CUSTOM_LEN {
+            // Skip 32-bit and 64-bit sizes
+            self.bytes(12)?;
+        }
+
+        match identifier {
+            b"_i" => Ok(Data::Int32(self.u32()? as i32)),
+            b"_j" => Ok(Data::Int64(self.u64()? as i64)),
+            b"_n" => match self.u8()? {
+                1 => Ok(Data::Nativeint(self.u32()? as i32 as i64)),
+                2 => Ok(Data::Nativeint(self.u64()? as i64)),
+                _ => Err(Error::UnsupportedCustom("_n".into())),
+            },
+            x => Err(Error::UnsupportedCustom(
+                String::from_utf8_lossy(x).into_owned(),
+            )),
+        }
+    }
+}
+
+enum Item {
+    Data(Data),
+    Block(u8, usize),
+}
+
+/// Reads the header, returning the length of the data that follows it
+fn header(r: &mut Reader) -> Result<usize, Error> {
+    let data_len = match r.u32()? {
+        MAGIC_NUMBER_SMALL => {
+            let data_len = r.u32()? as u64;
+            // Skip number of objects and heap sizes
+            r.bytes(12)?;
+            data_len
+        }
+        MAGIC_NUMBER_BIG => {
+            r.bytes(4)?;
+            let data_len = r.u64()?;
+            r.bytes(16)?;
+            data_len
+        }
+        MAGIC_NUMBER_COMPRESSED => return Err(Error::Compressed),
+        n => return Err(Error::InvalidMagicNumber(n)),
+    };
+    r.size(data_len)
+}
+
+/// Returns the total size in bytes of the marshalled value starting at the beginning of `data`,
+/// including the header, like `Marshal.total_size`
+pub fn total_size(data: &[u8]) -> Result<usize, Error> {
+    let mut r = Reader { data, pos: 0 };
+    let data_len = header(&mut r)?;
+    r.pos.checked_add(data_len).ok_or(Error::InvalidLength)
+}
+
+/// Decode the marshalled value at the beginning of `data`, any bytes following the value are
+/// ignored
+pub fn decode(data: &[u8]) -> Result<Data, Error> {
+    let mut r = Reader { data, pos: 0 };
+    let data_len = header(&mut r)?;
+    let end = r.pos.checked_add(data_len).ok_or(Error::InvalidLength)?;
+    if end > data.len() {
+        return Err(Error::UnexpectedEof);
+    }
+    r.data = &data[..end];
+
+    let mut obj_counter = 0;
+
+    // Blocks are decoded using an explicit stack to avoid overflowing the native stack on
+    // deeply nested values like long lists
+    let mut stack: Vec<(u8, usize, Vec<Data>)> = Vec::new();
+
+    loop {
+        let code = r.u8()?;
+        let item = if code >= PREFIX_SMALL_BLOCK {
+            let tag = code & 0xF;
+            let size = ((code >> 4) & 0x7) as usize;
+            Item::Block(tag, size)
+        } else if code >= PREFIX_SMALL_INT {
+            Item::Data(Data::Int((code & 0x3F) as i64))
+        } else if code >= PREFIX_SMALL_STRING {
+            let len = (code & 0x1F) as usize;
+            Item::Data(Data::String(r.bytes(len)?.to_vec()))
+        } else {
+            match code {
+                CODE_INT8 => Item::Data(Data::Int(r.u8()? as i8 as i64)),
+                CODE_INT16 => Item::Data(Data::Int(r.u16()? as i16 as i64)),
+                CODE_INT32 => Item::Data(Data::Int(r.u32()? as i32 as i64)),
+                CODE_INT64 => Item::Data(Data::Int(r.u64()? as i64)),
+                CODE_SHARED8 | CODE_SHARED16 | CODE_SHARED32 | CODE_SHARED64 => {
+                    let ofs = match code {
+                        CODE_SHARED8 => r.u8()? as u64,
+                        CODE_SHARED16 => r.u16()? as u64,
+                        CODE_SHARED32 => r.u32()? as u64,
+                        _ => r.u64()?,
+                    };
+                    let ofs = r.size(ofs)?;
+                    if ofs == 0 || ofs > obj_counter {
+                        return Err(Error::InvalidShared(ofs));
+                    }
+                    Item::Data(Data::Shared(obj_counter - ofs))
+                }
+                CODE_BLOCK32 => {
+                    let header = r.u32()? as u64;
+                    Item::Block((header & 0xFF) as u8, r.size(header >> 10)?)
+                }
+                CODE_BLOCK64 => {
+                    let header = r.u64()?;
+                    Item::Block((header & 0xFF) as u8, r.size(header >> 10)?)
+                }
+                CODE_STRING8 | CODE_STRING32 | CODE_STRING64 => {
+                    let len = match code {
+                        CODE_STRING8 => r.u8()? as u64,
+                        CODE_STRING32 => r.u32()? as u64,
+                        _ => r.u64()?,
+                    };
+                    let len = r.size(len)?;
+                    Item::Data(Data::String(r.bytes(len)?.to_vec()))
+                }
+                CODE_DOUBLE_BIG | CODE_DOUBLE_LITTLE => {
+                    Item::Data(Data::Double(r.double(code == CODE_DOUBLE_BIG)?))
+                }
+                CODE_DOUBLE_ARRAY8_BIG
+                | CODE_DOUBLE_ARRAY8_LITTLE
+                | CODE_DOUBLE_ARRAY32_BIG
+                | CODE_DOUBLE_ARRAY32_LITTLE
+                | CODE_DOUBLE_ARRAY64_BIG
+                | CODE_DOUBLE_ARRAY64_LITTLE => {
+                    let len = match code {
+                        CODE_DOUBLE_ARRAY8_BIG | CODE_DOUBLE_ARRAY8_LITTLE => r.u8()? as u64,
+                        CODE_DOUBLE_ARRAY32_BIG | CODE_DOUBLE_ARRAY32_LITTLE => r.u32()? as u64,
+                        _ => r.u64()?,
+                    };
+                    let len = r.size(len)?;
+                    let big_endian = matches!(
+                        code,
+                        CODE_DOUBLE_ARRAY8_BIG | CODE_DOUBLE_ARRAY32_BIG | CODE_DOUBLE_ARRAY64_BIG
+                    );
+                    Item::Data(Data::DoubleArray(r.doubles(len, big_endian)?))
+                }
+                CODE_CUSTOM | CODE_CUSTOM_LEN | CODE_CUSTOM_FIXED => Item::Data(r.custom(code)?),
+                _ => return Err(Error::UnsupportedCode(code)),
+            }
+        };
+
+        let mut value = match item {
+            Item::Block(tag, size) if size > 0 => {
+                if tag >= NO_SCAN_TAG {
+                    return Err(Error::InvalidTag(tag));
+                }
+                obj_counter += 1;
+                // Avoid allocating based on untrusted sizes, the fields are pushed as they're
+                // decoded
+                stack.push((tag, size, Vec::with_capacity(size.min(1024))));
+                continue;
+            }
+            Item::Block(tag, _) => Data::Block(tag, Vec::new()),
+            Item::Data(data) => {
+                if data.is_object() {
+                    obj_counter += 1;
+                }
+                data
+            }
+        };
+
+        // Add the value to its parent, finishing any blocks that are now complete
+        loop {
+            match stack.last_mut() {
+                None => {
+                    if r.pos != end {
+                        return Err(Error::InvalidLength);
+                    }
+                    return Ok(value);
+                }
+                Some((tag, size, fields)) => {
+                    fields.push(value);
+                    if fields.len() < *size {
+                        break;
+                    }
+                    let tag = *tag;
+                    let fields = core::mem::take(fields);
+                    stack.pop();
+                    value = Data::Block(tag, fields);
+                }
+            }
+        }
+    }
+}
+
+/// Checks that the marshalled value at the beginning of `data` is well-formed without decoding it,
+/// returning its total size. This is used by `Value::unmarshal`, since the runtime raises an
+/// exception for invalid data
+///
+/// Unlike `decode`, closures and any custom blocks are accepted: `custom_registered` is called
+/// with the identifier of each custom block and `code_registered` with the digest of each code
+/// pointer. Custom blocks other than `int32`, `int64` and `nativeint` don't store the size of their
+/// contents, so nothing after the first one is checked
+pub(crate) fn validate(
+    data: &[u8],
+    custom_registered: impl Fn(&[u8]) -> bool,
+    code_registered: impl Fn(&[u8]) -> bool,
+) -> Result<usize, Error> {
+    let mut r = Reader { data, pos: 0 };
+    let data_len = header(&mut r)?;
+    let end = r.pos.checked_add(data_len).ok_or(Error::InvalidLength)?;
+    if end > data.len() {
+        return Err(Error::UnexpectedEof);
+    }
+    r.data = &data[..end];
+
+    let mut obj_counter = 0;
+
+    // Number of values that haven't been read yet, the fields of a block follow its header
+    let mut remaining: usize = 1;
+
+    while remaining > 0 {
+        remaining -= 1;
+        let code = r.u8()?;
+        let fields = if code >= PREFIX_SMALL_BLOCK {
+            ((code >> 4) & 0x7) as usize
+        } else if code >= PREFIX_SMALL_INT {
+            continue;
+        } else if code >= PREFIX_SMALL_STRING {
+            r.bytes((code & 0x1F) as usize)?;
+            obj_counter += 1;
+            continue;
+        } else {
+            match code {
+                CODE_INT8 | CODE_INT16 | CODE_INT32 | CODE_INT64 => {
+                    r.bytes(1 << (code - CODE_INT8))?;
+                    0
+                }
+                CODE_SHARED8 | CODE_SHARED16 | CODE_SHARED32 | CODE_SHARED64 => {
+                    let ofs = match code {
+                        CODE_SHARED8 => r.u8()? as u64,
+                        CODE_SHARED16 => r.u16()? as u64,
+                        CODE_SHARED32 => r.u32()? as u64,
+                        _ => r.u64()?,
+                    };
+                    let ofs = r.size(ofs)?;
+                    if ofs == 0 || ofs > obj_counter {
+                        return Err(Error::InvalidShared(ofs));
+                    }
+                    0
+                }
+                CODE_BLOCK32 | CODE_BLOCK64 => {
+                    let header = if code == CODE_BLOCK32 {
+                        r.u32()? as u64
+                    } else {
+                        r.u64()?
+                    };
+                    let tag = (header & 0xFF) as u8;
+                    if tag >= NO_SCAN_TAG {
+                        return Err(Error::InvalidTag(tag));
+                    }
+                    r.size(header >> 10)?
+                }
+                CODE_STRING8 | CODE_STRING32 | CODE_STRING64 => {
+                    let len = match code {
+                        CODE_STRING8 => r.u8()? as u64,
+                        CODE_STRING32 => r.u32()? as u64,
+                        _ => r.u64()?,
+                    };
+                    let len = r.size(len)?;
+                    r.bytes(len)?;
+                    obj_counter += 1;
+                    0
+                }
+                CODE_DOUBLE_BIG | CODE_DOUBLE_LITTLE => {
+                    r.bytes(8)?;
+                    obj_counter += 1;
+                    0
+                }
+                CODE_DOUBLE_ARRAY8_BIG
+                | CODE_DOUBLE_ARRAY8_LITTLE
+                | CODE_DOUBLE_ARRAY32_BIG
+                | CODE_DOUBLE_ARRAY32_LITTLE
+                | CODE_DOUBLE_ARRAY64_BIG
+                | CODE_DOUBLE_ARRAY64_LITTLE => {
+                    let len = match code {
+                        CODE_DOUBLE_ARRAY8_BIG | CODE_DOUBLE_ARRAY8_LITTLE => r.u8()? as u64,
+                        CODE_DOUBLE_ARRAY32_BIG | CODE_DOUBLE_ARRAY32_LITTLE => r.u32()? as u64,
+                        _ => r.u64()?,
+                    };
+                    let len = r.size(len)?.checked_mul(8).ok_or(Error::InvalidLength)?;
+                    r.bytes(len)?;
+                    obj_counter += 1;
+                    0
+                }
+                CODE_CODEPOINTER => {
+                    r.bytes(4)?;
+                    if !code_registered(r.bytes(16)?) {
+                        return Err(Error::UnsupportedCode(code));
+                    }
+                    0
+                }
+                CODE_INFIXPOINTER => {
+                    // Followed by the closure that contains the infix block
+                    r.bytes(4)?;
+                    remaining += 1;
+                    0
+                }
+                CODE_CUSTOM | CODE_CUSTOM_LEN | CODE_CUSTOM_FIXED => {
+                    let identifier = r.cstr()?;
+                    if !custom_registered(identifier) {
+                        return Err(Error::UnsupportedCustom(
+                            String::from_utf8_lossy(identifier).into_owned(),
+                        ));
+                    }
+                    if code == CODE_CUSTOM_LEN {
+                        r.bytes(12)?;
+                    }
+                    match identifier {
+                        b"_i" => r.bytes(4)?,
+                        b"_j" => r.bytes(8)?,
+                        b"_n" => match r.u8()? {
+                            1 => r.bytes(4)?,
+                            2 => r.bytes(8)?,
+                            _ => return Err(Error::UnsupportedCustom("_n".into())),
+                        },
+                        _ => return Ok(end),
+                    };
+                    obj_counter += 1;
+                    0
+                }
+                _ => return Err(Error::UnsupportedCode(code)),
+            }
+        };
+
+        if fields > 0 {
+            obj_counter += 1;
+            remaining = remaining.checked_add(fields).ok_or(Error::InvalidLength)?;
+        }
+    }
+
+    if r.pos != end {
+        return Err(Error::InvalidLength);
+    }
+    Ok(end)
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+    obj_counter: usize,
+    size_32: u64,
+    size_64: u64,
+}
+
+impl Writer {
+    fn code8(&mut self, code: u8, x: u8) {
+        self.buf.push(code);
+        self.buf.push(x);
+    }
+
+    fn code16(&mut self, code: u8, x: u16) {
+        self.buf.push(code);
+        self.buf.extend_from_slice(&x.to_be_bytes());
+    }
+
+    fn code32(&mut self, code: u8, x: u32) {
+        self.buf.push(code);
+        self.buf.extend_from_slice(&x.to_be_bytes());
+    }
+
+    fn code64(&mut self, code: u8, x: u64) {
+        self.buf.push(code);
+        self.buf.extend_from_slice(&x.to_be_bytes());
+    }
+
+    fn object(&mut self, size_32: u64, size_64: u64) {
+        self.obj_counter += 1;
+        self.size_32 += 1 + size_32;
+        self.size_64 += 1 + size_64;
+    }
+
+    fn int(&mut self, n: i64) {
+        if (0..0x40).contains(&n) {
+            self.buf.push(PREFIX_SMALL_INT + n as u8);
+        } else if (-(1 << 7)..(1 << 7)).contains(&n) {
+            self.code8(CODE_INT8, n as u8);
+        } else if (-(1 << 15)..(1 << 15)).contains(&n) {
+            self.code16(CODE_INT16, n as u16);
+        } else if !(-(1 << 30)..(1 << 30)).contains(&n) {
+            self.code64(CODE_INT64, n as u64);
+        } else {
+            self.code32(CODE_INT32, n as u32);
+        }
+    }
+
+    fn header(&mut self, tag: u8, size: usize) {
+        if tag < 16 && size < 8 {
+            self.buf
+                .push(PREFIX_SMALL_BLOCK + tag + ((size as u8) << 4));
+        } else if size < (1 << 22) {
+            self.code32(CODE_BLOCK32, ((size as u32) << 10) | tag as u32);
+        } else {
+            self.code64(CODE_BLOCK64, ((size as u64) << 10) | tag as u64);
+        }
+    }
+
+    fn string(&mut self, s: &[u8]) {
+        let len = s.len();
+        if len < 0x20 {
+            self.buf.push(PREFIX_SMALL_STRING + len as u8);
+        } else if len < 0x100 {
+            self.code8(CODE_STRING8, len as u8);
+        } else if len <= 0xFFFF_FFFB {
+            self.code32(CODE_STRING32, len as u32);
+        } else {
+            self.code64(CODE_STRING64, len as u64);
+        }
+        self.buf.extend_from_slice(s);
+        let len = len as u64;
+        self.object((len + 4) / 4, (len + 8) / 8);
+    }
+
+    fn double_array(&mut self, d: &[f64]) {
+        let len = d.len();
+        if len < 0x100 {
+            self.code8(CODE_DOUBLE_ARRAY8_LITTLE, len as u8);
+        } else if len < (1 << 32) {
+            self.code32(CODE_DOUBLE_ARRAY32_LITTLE, len as u32);
+        } else {
+            self.code64(CODE_DOUBLE_ARRAY64_LITTLE, len as u64);
+        }
+        for x in d {
+            self.buf.extend_from_slice(&x.to_le_bytes());
+        }
+        let len = len as u64;
+        self.object(len * 2, len);
+    }
+
+    fn custom(&mut self, identifier: &[u8], data: &[u8], size_32: u64, size_64: u64) {
+        // Only `_i` and `_j` have fixed-length custom operations, everything else (like `_n`)
+        // is written with explicit 32-bit and 64-bit sizes
+        let fixed = identifier == b"_i" || identifier == b"_j";
+        self.buf.push(if fixed {
+            CODE_CUSTOM_FIXED
+        } else {
+            CODE_CUSTOM_LEN
+        });
+        self.buf.extend_from_slice(identifier);
+        self.buf.push(0);
+        if !fixed {
+            self.buf.extend_from_slice(&(size_32 as u32).to_be_bytes());
+            self.buf.extend_from_slice(&size_64.to_be_bytes());
+        }
+        self.buf.extend_from_slice(data);
+        // Custom blocks also store a pointer to their custom operations
+        self.object(1 + size_32.div_ceil(4), 1 + size_64.div_ceil(8));
+    }
+
+    fn shared(&mut self, index: usize) -> Result<(), Error> {
+        if index >= self.obj_counter {
+            return Err(Error::InvalidShared(index));
+        }
+        let ofs = (self.obj_counter - index) as u64;
+        if ofs < 0x100 {
+            self.code8(CODE_SHARED8, ofs as u8);
+        } else if ofs < 0x10000 {
+            self.code16(CODE_SHARED16, ofs as u16);
+        } else if ofs < (1 << 32) {
+            self.code32(CODE_SHARED32, ofs as u32);
+        } else {
+            self.code64(CODE_SHARED64, ofs);
+        }
+        Ok(())
+    }
+}
+
+/// Encode a value using the same representation as `Marshal.to_string v []` on a 64-bit
+/// little-endian platform
+pub fn encode(data: &Data) -> Result<Vec<u8>, Error> {
+    let mut w = Writer::default();
+
+    // Objects are numbered in the order they're written, which is a pre-order traversal
+    let mut stack = vec![data];
+    while let Some(data) = stack.pop() {
+        match data {
+            Data::Int(n) => w.int(*n),
+            Data::Block(tag, fields) => {
+                if *tag >= NO_SCAN_TAG {
+                    return Err(Error::InvalidTag(*tag));
+                }
+                w.header(*tag, fields.len());
+                if !fields.is_empty() {
+                    let size = fields.len() as u64;
+                    w.object(size, size);
+                }
+                stack.extend(fields.iter().rev());
+            }
+            Data::String(s) => w.string(s),
+            Data::Double(d) => {
+                w.buf.push(CODE_DOUBLE_LITTLE);
+                w.buf.extend_from_slice(&d.to_le_bytes());
+                w.object(2, 1);
+            }
+            Data::DoubleArray(d) => w.double_array(d),
+            Data::Int32(i) => w.custom(b"_i", &i.to_be_bytes(), 4, 4),
+            Data::Int64(i) => w.custom(b"_j", &i.to_be_bytes(), 8, 8),
+            Data::Nativeint(i) => match i32::try_from(*i) {
+                Ok(i) => w.custom(b"_n", &[&[1], &i.to_be_bytes()[..]].concat(), 4, 8),
+                Err(_) => w.custom(b"_n", &[&[2], &i.to_be_bytes()[..]].concat(), 4, 8),
+            },
+            Data::Shared(index) => w.shared(*index)?,
+        }
+    }
+
+    let data_len = w.buf.len() as u64;
+    let num_objects = w.obj_counter as u64;
+    let small = data_len < (1 << 32) && w.size_32 < (1 << 32) && w.size_64 < (1 << 32);
+    let mut dest = Vec::with_capacity(HEADER_SIZE_BIG + w.buf.len());
+    if small {
+        dest.extend_from_slice(&MAGIC_NUMBER_SMALL.to_be_bytes());
+        dest.extend_from_slice(&(data_len as u32).to_be_bytes());
+        dest.extend_from_slice(&(num_objects as u32).to_be_bytes());
+        dest.extend_from_slice(&(w.size_32 as u32).to_be_bytes());
+        dest.extend_from_slice(&(w.size_64 as u32).to_be_bytes());
+        debug_assert_eq!(dest.len(), HEADER_SIZE_SMALL);
+    } else {
+        dest.extend_from_slice(&MAGIC_NUMBER_BIG.to_be_bytes());
+        dest.extend_from_slice(&[0; 4]);
+        dest.extend_from_slice(&data_len.to_be_bytes());
+        dest.extend_from_slice(&num_objects.to_be_bytes());
+        dest.extend_from_slice(&w.size_64.to_be_bytes());
+        debug_assert_eq!(dest.len(), HEADER_SIZE_BIG);
+    }
+    dest.extend_from_slice(&w.buf);
+    Ok(dest)
+}
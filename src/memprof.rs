@@ -0,0 +1,245 @@
+//! Statistical memory profiling using `Gc.Memprof`
+//!
+//! `Gc.Memprof` is available in OCaml 4.11 to 4.14 and OCaml 5.3 or newer, `start` returns an
+//! error when using a version of OCaml without it. The tracker callbacks are OCaml closures backed
+//! by Rust functions, so this requires the `ocaml_rs_closure` named value that is registered by
+//! the bindings generated by `ocaml-build`. Panics inside the callbacks are handled the same way
+//! as panics in `#[ocaml::func]`.
+
+use crate::{sys, Error, FromValue, Pointer, Runtime, Tag, Value};
+
+/// The kind of allocation that was sampled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationSource {
+    /// Regular allocation
+    Normal,
+    /// Allocation made while unmarshalling
+    Marshal,
+    /// Custom block
+    Custom,
+    /// Bigarray mapped from a file
+    MapFile,
+}
+
+/// Information about a sampled allocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Allocation {
+    /// Number of samples in this block
+    pub n_samples: usize,
+    /// Size of the block, in words, excluding the header
+    pub size: usize,
+    /// The kind of allocation
+    pub source: AllocationSource,
+    /// Return addresses of the callstack captured at the allocation point
+    pub callstack: Vec<usize>,
+}
+
+impl Allocation {
+    unsafe fn from_value(v: &Value) -> Allocation {
+        let source = match v.field(2).int_val() {
+            0 => AllocationSource::Normal,
+            1 => AllocationSource::Marshal,
+            2 => AllocationSource::Custom,
+            _ => AllocationSource::MapFile,
+        };
+        let callstack = v.field(3);
+        let len = sys::wosize_val(callstack.raw().0);
+        Allocation {
+            n_samples: v.field(0).int_val() as usize,
+            size: v.field(1).int_val() as usize,
+            source,
+            // Backtrace slots are code pointers with the lowest bit set so they look like integers
+            callstack: (0..len)
+                .map(|i| callstack.field(i).raw().0 as usize & !1)
+                .collect(),
+        }
+    }
+}
+
+type AllocFn = Box<dyn Fn(&Runtime, &Allocation) -> bool>;
+type DeallocFn = Box<dyn Fn(&Runtime, &Allocation)>;
+
+/// Callbacks used by `start`, these follow `Gc.Memprof.tracker`
+///
+/// `alloc_minor`, `alloc_major` and `promote` return true to keep tracking the block, the
+/// `Allocation` recorded when the block was sampled is passed to every callback
+#[derive(Default)]
+pub struct Tracker {
+    alloc_minor: Option<AllocFn>,
+    alloc_major: Option<AllocFn>,
+    promote: Option<AllocFn>,
+    dealloc_minor: Option<DeallocFn>,
+    dealloc_major: Option<DeallocFn>,
+}
+
+impl Tracker {
+    /// Create a tracker that doesn't track any blocks
+    pub fn new() -> Tracker {
+        Tracker::default()
+    }
+
+    /// Called when a block in the minor heap is sampled
+    pub fn alloc_minor(mut self, f: impl Fn(&Runtime, &Allocation) -> bool + 'static) -> Self {
+        self.alloc_minor = Some(Box::new(f));
+        self
+    }
+
+    /// Called when a block in the major heap is sampled
+    pub fn alloc_major(mut self, f: impl Fn(&Runtime, &Allocation) -> bool + 'static) -> Self {
+        self.alloc_major = Some(Box::new(f));
+        self
+    }
+
+    /// Called when a tracked block is promoted to the major heap
+    pub fn promote(mut self, f: impl Fn(&Runtime, &Allocation) -> bool + 'static) -> Self {
+        self.promote = Some(Box::new(f));
+        self
+    }
+
+    /// Called when a tracked block in the minor heap is deallocated
+    pub fn dealloc_minor(mut self, f: impl Fn(&Runtime, &Allocation) + 'static) -> Self {
+        self.dealloc_minor = Some(Box::new(f));
+        self
+    }
+
+    /// Called when a tracked block in the major heap is deallocated
+    pub fn dealloc_major(mut self, f: impl Fn(&Runtime, &Allocation) + 'static) -> Self {
+        self.dealloc_major = Some(Box::new(f));
+        self
+    }
+}
+
+// Passed back to OCaml as the `'minor` and `'major` values of the tracker
+struct Tracked(Allocation);
+
+crate::custom!(Tracked);
+
+// Domains that profiling has been started on using `start`, `Gc.Memprof.start` and
+// `Gc.Memprof.stop` raise an exception if profiling is already running or hasn't been started
+static RUNNING: std::sync::Mutex<Vec<usize>> = std::sync::Mutex::new(Vec::new());
+
+fn running() -> std::sync::MutexGuard<'static, Vec<usize>> {
+    RUNNING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns true when `Gc.Memprof` is supported by the version of OCaml being used
+pub fn is_supported() -> bool {
+    unsafe { sys::caml_sys_memprof_supported() != 0 }
+}
+
+/// Start sampling allocations, see `Gc.Memprof.start`
+///
+/// Returns an error if profiling has already been started on the current domain using `start`.
+/// Profiling started from OCaml isn't detected, so `Gc.Memprof` and this module shouldn't be used
+/// together
+pub fn start(
+    rt: &Runtime,
+    sampling_rate: f64,
+    callstack_size: usize,
+    tracker: Tracker,
+) -> Result<(), Error> {
+    if !is_supported() {
+        return Err(Error::Message(
+            "Gc.Memprof is not supported by this version of OCaml",
+        ));
+    }
+
+    let domain = crate::domain::id().unwrap_or_default();
+    if running().contains(&domain) {
+        return Err(Error::Message("Gc.Memprof has already been started"));
+    }
+
+    let tracker = std::rc::Rc::new(tracker);
+
+    let alloc = |f: fn(&Tracker) -> &Option<AllocFn>| {
+        let tracker = tracker.clone();
+        crate::closure::alloc(rt, move |rt, v| unsafe {
+            let f = match f(&tracker) {
+                Some(f) => f,
+                None => return Value::none(),
+            };
+            let info = Allocation::from_value(&v);
+            if f(rt, &info) {
+                Value::some(rt, Pointer::alloc_custom(Tracked(info)))
+            } else {
+                Value::none()
+            }
+        })
+    };
+
+    let promote = {
+        let tracker = tracker.clone();
+        crate::closure::alloc(rt, move |rt, v| unsafe {
+            let f = match &tracker.promote {
+                Some(f) => f,
+                None => return Value::none(),
+            };
+            let tracked = Pointer::<Tracked>::from_value(v.clone());
+            if f(rt, &tracked.as_ref().0) {
+                Value::some(rt, v)
+            } else {
+                Value::none()
+            }
+        })
+    };
+
+    let dealloc = |f: fn(&Tracker) -> &Option<DeallocFn>| {
+        let tracker = tracker.clone();
+        crate::closure::alloc(rt, move |rt, v| {
+            if let Some(f) = f(&tracker) {
+                let tracked = Pointer::<Tracked>::from_value(v);
+                f(rt, &tracked.as_ref().0);
+            }
+            Value::unit()
+        })
+    };
+
+    let fields = [
+        alloc(|t| &t.alloc_minor)?,
+        alloc(|t| &t.alloc_major)?,
+        promote?,
+        dealloc(|t| &t.dealloc_minor)?,
+        dealloc(|t| &t.dealloc_major)?,
+    ];
+
+    unsafe {
+        let mut record = Value::alloc(fields.len(), Tag(0));
+        for (i, f) in fields.iter().enumerate() {
+            record.store_field(rt, i, f);
+        }
+
+        let sampling_rate = Value::double(sampling_rate);
+        sys::caml_sys_memprof_start(
+            sampling_rate.raw().0,
+            sys::val_int(callstack_size as isize),
+            record.raw().0,
+        );
+    }
+
+    running().push(domain);
+    Ok(())
+}
+
+/// Stop sampling allocations, see `Gc.Memprof.stop`
+///
+/// Returns an error if profiling hasn't been started on the current domain using `start`
+pub fn stop(_rt: &Runtime) -> Result<(), Error> {
+    if !is_supported() {
+        return Err(Error::Message(
+            "Gc.Memprof is not supported by this version of OCaml",
+        ));
+    }
+
+    let domain = crate::domain::id().unwrap_or_default();
+    let mut running = running();
+    match running.iter().position(|x| *x == domain) {
+        Some(index) => running.swap_remove(index),
+        None => return Err(Error::Message("Gc.Memprof has not been started")),
+    };
+    drop(running);
+
+    unsafe {
+        sys::caml_sys_memprof_stop(sys::UNIT);
+    }
+    Ok(())
+}
@@ -25,6 +25,16 @@ unsafe impl<T> FromValue for Pointer<T> {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T> crate::TryFromValue for Pointer<T> {
+    fn try_from_value(value: Value) -> Result<Self, crate::ConversionError> {
+        unsafe {
+            crate::ConversionError::expect_block(&value, "custom block", crate::Tag::CUSTOM, None)?;
+        }
+        Ok(Pointer(value, PhantomData))
+    }
+}
+
 impl<T: Custom> From<T> for Pointer<T> {
     fn from(x: T) -> Self {
         Pointer::alloc_custom(x)
@@ -43,7 +53,11 @@ impl<T: Custom> Pointer<T> {
         T: Custom,
     {
         unsafe {
-            let mut ptr = Pointer(Value::alloc_custom::<T>(), PhantomData);
+            let value = match x.mem_size() {
+                0 => Value::alloc_custom::<T>(),
+                mem => Value::alloc_custom_mem::<T>(mem),
+            };
+            let mut ptr = Pointer(value, PhantomData);
             ptr.set(x);
             ptr
         }
@@ -1,8 +1,17 @@
 /// OCaml runtime handle
+///
+/// `Runtime` is neither `Send` nor `Sync`, a handle is only valid on the thread that holds the
+/// runtime lock
 pub struct Runtime {
     _panic_guard: PanicGuard,
+    _not_send: core::marker::PhantomData<*const ()>,
 }
 
+// Allows the handle returned by `Runtime::recover_handle` to be stored in a static
+struct StaticRuntime(Runtime);
+
+unsafe impl Sync for StaticRuntime {}
+
 static RUNTIME_INIT: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
 
 impl Runtime {
@@ -11,6 +20,7 @@ impl Runtime {
         Self::init_persistent();
         Self {
             _panic_guard: PanicGuard::new(),
+            _not_send: core::marker::PhantomData,
         }
     }
 
@@ -52,10 +62,26 @@ impl Runtime {
     #[doc(hidden)]
     #[inline(always)]
     pub unsafe fn recover_handle() -> &'static Self {
-        static RUNTIME: Runtime = Runtime {
+        static RUNTIME: StaticRuntime = StaticRuntime(Runtime {
             _panic_guard: PanicGuard,
-        };
-        &RUNTIME
+            _not_send: core::marker::PhantomData,
+        });
+        &RUNTIME.0
+    }
+
+    /// Get a runtime handle for the current domain, returns `None` if the current thread isn't
+    /// attached to an OCaml domain or the runtime hasn't been initialized
+    ///
+    /// When using OCaml 4 the runtime state is shared by every thread, so this returns `Some` on
+    /// any thread once the runtime has been initialized, even one that doesn't hold the runtime
+    /// lock
+    pub fn current() -> Option<&'static Runtime> {
+        unsafe {
+            if crate::sys::caml_sys_get_domain_state().is_null() {
+                return None;
+            }
+            Some(Runtime::recover_handle())
+        }
     }
 
     /// Wrapper for `caml_leave_blocking_section`
@@ -63,10 +89,133 @@ impl Runtime {
         unsafe { crate::sys::caml_leave_blocking_section() }
     }
 
-    /// Wrapper for `caml_leave_blocking_section`
+    /// Wrapper for `caml_enter_blocking_section`, `Runtime::without_lock` should be preferred
+    /// since it ensures the lock is always reacquired
     pub fn enter_blocking_section(&self) {
         unsafe { crate::sys::caml_enter_blocking_section() }
     }
+
+    /// Acquire the runtime lock from a thread registered using `ThreadRegistration`, run `f` and
+    /// release the lock again
+    ///
+    /// Calls to `with_lock` can be nested, the lock is only acquired by the outermost call. This
+    /// will panic if the current thread hasn't been registered. The result must implement `Owned`,
+    /// so OCaml values can't be used after the lock has been released
+    ///
+    /// ```rust,no_run
+    /// let rt = ocaml::Runtime::init();
+    /// let handle = rt.without_lock(|| {
+    ///     std::thread::spawn(|| {
+    ///         let _thread = ocaml::ThreadRegistration::new().unwrap();
+    ///         ocaml::Runtime::with_lock(|rt| {
+    ///             // Call into OCaml here
+    ///         })
+    ///     })
+    /// });
+    /// ```
+    #[cfg(not(feature = "no-std"))]
+    pub fn with_lock<T: crate::Owned>(f: impl FnOnce(&Runtime) -> T) -> T {
+        // Releases the lock when dropped, even if `f` panics
+        struct Release;
+
+        impl Drop for Release {
+            fn drop(&mut self) {
+                THREAD_STATE.with(|state| state.lock_depth.set(state.lock_depth.get() - 1));
+                unsafe { crate::sys::caml_enter_blocking_section() }
+            }
+        }
+
+        let depth = THREAD_STATE.with(|state| {
+            if !state.registered.get() {
+                panic!("Runtime::with_lock called from a thread that hasn't been registered");
+            }
+            state.lock_depth.get()
+        });
+
+        let rt = unsafe { Runtime::recover_handle() };
+        if depth > 0 {
+            return f(rt);
+        }
+
+        unsafe { crate::sys::caml_leave_blocking_section() };
+        THREAD_STATE.with(|state| state.lock_depth.set(1));
+        let _release = Release;
+        f(rt)
+    }
+
+    /// Run `f` with the runtime lock released, allowing other OCaml threads to run. The lock is
+    /// reacquired before returning, even if `f` panics
+    ///
+    /// `f` must be `Send`, since `Value`, `Raw`, `Array`, `List` and `&Runtime` are not `Send`
+    /// they can't be captured by the closure
+    ///
+    /// ```rust,no_run
+    /// # let rt = ocaml::Runtime::init();
+    /// let data = vec![1u64, 2, 3];
+    /// let sum = rt.without_lock(move || data.iter().sum::<u64>());
+    /// ```
+    pub fn without_lock<T: Send, F: FnOnce() -> T + Send>(&self, f: F) -> T {
+        unsafe { release_lock(f) }
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+struct ThreadState {
+    registered: core::cell::Cell<bool>,
+    lock_depth: core::cell::Cell<usize>,
+}
+
+#[cfg(not(feature = "no-std"))]
+thread_local! {
+    static THREAD_STATE: ThreadState = const {
+        ThreadState {
+            registered: core::cell::Cell::new(false),
+            lock_depth: core::cell::Cell::new(0),
+        }
+    };
+}
+
+/// Registers a thread that wasn't created by OCaml with the runtime, the thread is unregistered
+/// when the value is dropped
+///
+/// This is required before calling `Runtime::with_lock` from Rust threads, the OCaml `threads`
+/// library must be linked and initialized. A registered thread doesn't hold the runtime lock,
+/// use `Runtime::with_lock` to acquire it before calling into OCaml
+#[cfg(not(feature = "no-std"))]
+pub struct ThreadRegistration {
+    _not_send: core::marker::PhantomData<*const ()>,
+}
+
+#[cfg(not(feature = "no-std"))]
+impl ThreadRegistration {
+    /// Register the current thread with the OCaml runtime, this fails if the thread has already
+    /// been registered
+    pub fn new() -> Result<ThreadRegistration, crate::Error> {
+        if THREAD_STATE.with(|state| state.registered.get()) {
+            return Err(crate::Error::Message("thread is already registered"));
+        }
+
+        if unsafe { crate::sys::caml_c_thread_register() } == 0 {
+            return Err(crate::Error::Message(
+                "unable to register thread with the OCaml runtime",
+            ));
+        }
+
+        THREAD_STATE.with(|state| state.registered.set(true));
+        Ok(ThreadRegistration {
+            _not_send: core::marker::PhantomData,
+        })
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+impl Drop for ThreadRegistration {
+    fn drop(&mut self) {
+        THREAD_STATE.with(|state| state.registered.set(false));
+        unsafe {
+            crate::sys::caml_c_thread_unregister();
+        }
+    }
 }
 
 /// Initialize the OCaml runtime, the runtime will be
@@ -85,11 +234,81 @@ pub fn leave_blocking_section() {
     unsafe { crate::sys::caml_leave_blocking_section() }
 }
 
-/// Wrapper for `caml_leave_blocking_section`
+/// Wrapper for `caml_enter_blocking_section`, `Runtime::without_lock` should be preferred since it
+/// ensures the lock is always reacquired
 pub fn enter_blocking_section() {
     unsafe { crate::sys::caml_enter_blocking_section() }
 }
 
+// Re-enters the runtime when dropped, even if `release_lock` is unwinding. The lock depth used by
+// `Runtime::with_lock` is cleared while the lock is released and restored here
+struct Reacquire {
+    #[cfg(not(feature = "no-std"))]
+    depth: usize,
+}
+
+impl Drop for Reacquire {
+    fn drop(&mut self) {
+        unsafe { crate::sys::caml_leave_blocking_section() }
+
+        #[cfg(not(feature = "no-std"))]
+        THREAD_STATE.with(|state| state.lock_depth.set(self.depth));
+    }
+}
+
+/// Run `f` with the runtime lock released, used by `#[ocaml::func(release_lock)]`
+///
+/// # Safety
+/// `f` must not access any OCaml values
+#[doc(hidden)]
+pub unsafe fn release_lock<T>(f: impl FnOnce() -> T) -> T {
+    // The panic hook calls into OCaml, so it's disabled until the lock has been reacquired
+    #[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+    let count = GUARD_COUNT.with(|count| count.replace(0));
+
+    let res = {
+        let _reacquire = Reacquire {
+            #[cfg(not(feature = "no-std"))]
+            depth: THREAD_STATE.with(|state| state.lock_depth.replace(0)),
+        };
+        crate::sys::caml_enter_blocking_section();
+
+        #[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+        {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        }
+
+        #[cfg(any(feature = "no-panic-hook", feature = "no-std"))]
+        {
+            f()
+        }
+    };
+
+    #[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+    {
+        GUARD_COUNT.with(|c| c.set(count));
+
+        // Now that the lock is held the panic policy can be applied, the panic was already
+        // reported by the default hook when it happened. Outside of an OCaml function the panic
+        // continues unwinding
+        match res {
+            Ok(x) => x,
+            Err(err) if count > 0 => {
+                let payload = panic_payload(&*err);
+                drop(err);
+                handle_panic(payload, || ())
+            }
+            Err(err) => std::panic::resume_unwind(err),
+        }
+    }
+
+    #[cfg(any(feature = "no-panic-hook", feature = "no-std"))]
+    res
+}
+
+#[doc(hidden)]
+pub fn assert_owned<T: crate::Owned>() {}
+
 /// Run minor GC collection
 pub fn gc_minor() {
     unsafe {
@@ -112,6 +331,163 @@ pub unsafe fn gc_compact() {
     ocaml_sys::caml_gc_compaction(ocaml_sys::UNIT);
 }
 
+/// GC statistics, see `Gc.stat`
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct GcStat {
+    /// Number of words allocated in the minor heap
+    pub minor_words: f64,
+    /// Number of words allocated in the minor heap that survived a minor collection
+    pub promoted_words: f64,
+    /// Number of words allocated in the major heap, including promoted words
+    pub major_words: f64,
+    /// Number of minor collections
+    pub minor_collections: usize,
+    /// Number of major collection cycles completed
+    pub major_collections: usize,
+    /// Total size of the major heap, in words
+    pub heap_words: usize,
+    /// Number of contiguous pieces of memory that make up the major heap
+    pub heap_chunks: usize,
+    /// Number of words of live data in the major heap
+    pub live_words: usize,
+    /// Number of live blocks in the major heap
+    pub live_blocks: usize,
+    /// Number of words in the free list
+    pub free_words: usize,
+    /// Number of blocks in the free list
+    pub free_blocks: usize,
+    /// Size of the largest block in the free list, in words
+    pub largest_free: usize,
+    /// Number of wasted words due to fragmentation
+    pub fragments: usize,
+    /// Number of heap compactions
+    pub compactions: usize,
+    /// Maximum size reached by the major heap, in words
+    pub top_heap_words: usize,
+    /// Current size of the stack, in words
+    pub stack_size: usize,
+    /// Number of forced full major collections completed
+    pub forced_major_collections: usize,
+}
+
+impl GcStat {
+    unsafe fn from_raw(v: ocaml_sys::Value) -> GcStat {
+        let float = |i| ocaml_sys::caml_sys_double_val(*ocaml_sys::field(v, i));
+        let int = |i| ocaml_sys::int_val(*ocaml_sys::field(v, i)) as usize;
+        GcStat {
+            minor_words: float(0),
+            promoted_words: float(1),
+            major_words: float(2),
+            minor_collections: int(3),
+            major_collections: int(4),
+            heap_words: int(5),
+            heap_chunks: int(6),
+            live_words: int(7),
+            live_blocks: int(8),
+            free_words: int(9),
+            free_blocks: int(10),
+            largest_free: int(11),
+            fragments: int(12),
+            compactions: int(13),
+            top_heap_words: int(14),
+            stack_size: int(15),
+            // Added in OCaml 4.12
+            forced_major_collections: if ocaml_sys::wosize_val(v) > 16 {
+                int(16)
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// Get GC statistics, this walks the entire major heap. See `Gc.stat`
+pub fn gc_stat() -> GcStat {
+    unsafe { GcStat::from_raw(ocaml_sys::caml_gc_stat(ocaml_sys::UNIT)) }
+}
+
+/// Get GC statistics without walking the major heap, `live_words`, `live_blocks`, `free_words`,
+/// `free_blocks`, `largest_free` and `fragments` are set to 0. See `Gc.quick_stat`
+pub fn gc_quick_stat() -> GcStat {
+    unsafe { GcStat::from_raw(ocaml_sys::caml_gc_quick_stat(ocaml_sys::UNIT)) }
+}
+
+/// GC parameters, see `Gc.control`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GcControl {
+    /// Size of the minor heap, in words
+    pub minor_heap_size: usize,
+    /// How much to add to the major heap when increasing it
+    pub major_heap_increment: usize,
+    /// Major GC speed, as a percentage of live data
+    pub space_overhead: usize,
+    /// Flags controlling GC messages printed to stderr
+    pub verbose: usize,
+    /// Heap compaction is triggered when the estimated amount of wasted memory is more than this
+    /// percentage of live data
+    pub max_overhead: usize,
+    /// Maximum size of the stack, in words
+    pub stack_limit: usize,
+    /// Policy used for allocating in the major heap
+    pub allocation_policy: usize,
+    /// Window size used by the major GC for smoothing out variations in its workload
+    pub window_size: usize,
+    /// Target ratio of floating garbage to major heap size for out-of-heap memory held by custom
+    /// values in the major heap
+    pub custom_major_ratio: usize,
+    /// Bound on floating garbage for out-of-heap memory held by custom values in the minor heap
+    pub custom_minor_ratio: usize,
+    /// Maximum amount of out-of-heap memory for each custom value allocated in the minor heap
+    pub custom_minor_max_size: usize,
+}
+
+/// Get the current GC parameters, see `Gc.get`
+pub fn gc_get() -> GcControl {
+    unsafe {
+        let v = ocaml_sys::caml_gc_get(ocaml_sys::UNIT);
+        let int = |i| ocaml_sys::int_val(*ocaml_sys::field(v, i)) as usize;
+        GcControl {
+            minor_heap_size: int(0),
+            major_heap_increment: int(1),
+            space_overhead: int(2),
+            verbose: int(3),
+            max_overhead: int(4),
+            stack_limit: int(5),
+            allocation_policy: int(6),
+            window_size: int(7),
+            custom_major_ratio: int(8),
+            custom_minor_ratio: int(9),
+            custom_minor_max_size: int(10),
+        }
+    }
+}
+
+/// Update GC parameters, see `Gc.set`
+pub fn gc_set(control: &GcControl) {
+    unsafe {
+        // Use the record returned by `caml_gc_get` so it always has the size expected by the
+        // runtime, all of the fields are immediate values so nothing else is allocated
+        let v = ocaml_sys::caml_gc_get(ocaml_sys::UNIT);
+        let fields = [
+            control.minor_heap_size,
+            control.major_heap_increment,
+            control.space_overhead,
+            control.verbose,
+            control.max_overhead,
+            control.stack_limit,
+            control.allocation_policy,
+            control.window_size,
+            control.custom_major_ratio,
+            control.custom_minor_ratio,
+            control.custom_minor_max_size,
+        ];
+        for (i, x) in fields.iter().enumerate() {
+            *ocaml_sys::field(v, i) = ocaml_sys::val_int(*x as isize);
+        }
+        ocaml_sys::caml_gc_set(v);
+    }
+}
+
 #[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
 thread_local! {
     #[allow(clippy::missing_const_for_thread_local)]
@@ -124,6 +500,145 @@ static INIT: std::sync::Once = std::sync::Once::new();
 #[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
 const RUST_PANIC_HOOK: &core::ffi::CStr = c"rust_panic_hook";
 
+/// Determines how Rust panics are handled when they reach an OCaml function boundary, see
+/// `Runtime::set_panic_policy`
+#[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PanicPolicy {
+    /// Raise `Failure "rust panic: ..."`, this is the default
+    Failure,
+
+    /// Raise `Rust_panic of string * string` with the panic message and the Rust backtrace. The
+    /// exception is declared by the bindings generated by `ocaml-build`, `Failure` is raised
+    /// when it hasn't been registered
+    Exception,
+
+    /// Print the panic message using the default panic hook and abort the process
+    Abort,
+}
+
+#[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+static PANIC_POLICY: core::sync::atomic::AtomicU8 = core::sync::atomic::AtomicU8::new(0);
+
+#[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+type PanicHook = std::sync::Arc<dyn Fn(&str, &std::backtrace::Backtrace) + Send + Sync>;
+
+#[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+static PANIC_HOOK: std::sync::Mutex<Option<PanicHook>> = std::sync::Mutex::new(None);
+
+#[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+impl Runtime {
+    /// Set how Rust panics in functions called from OCaml are handled, this applies to
+    /// `ocaml::func`, `ocaml::native_func` and `ocaml::bytecode_func`
+    pub fn set_panic_policy(&self, policy: PanicPolicy) {
+        let policy = match policy {
+            PanicPolicy::Failure => 0,
+            PanicPolicy::Exception => 1,
+            PanicPolicy::Abort => 2,
+        };
+        PANIC_POLICY.store(policy, core::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Get the current `PanicPolicy`
+    pub fn panic_policy(&self) -> PanicPolicy {
+        match PANIC_POLICY.load(core::sync::atomic::Ordering::Relaxed) {
+            1 => PanicPolicy::Exception,
+            2 => PanicPolicy::Abort,
+            _ => PanicPolicy::Failure,
+        }
+    }
+
+    /// Set a function that is called with the panic message and backtrace before the
+    /// `PanicPolicy` is applied, this runs before the `rust_panic_hook` OCaml callback
+    pub fn set_panic_hook(
+        &self,
+        f: impl Fn(&str, &std::backtrace::Backtrace) + Send + Sync + 'static,
+    ) {
+        *PANIC_HOOK.lock().unwrap_or_else(|e| e.into_inner()) = Some(std::sync::Arc::new(f));
+    }
+
+    /// Remove the function set using `set_panic_hook`
+    pub fn clear_panic_hook(&self) {
+        *PANIC_HOOK.lock().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+}
+
+// Raise `Rust_panic (msg, backtrace)`, returns if the exception hasn't been registered
+#[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+unsafe fn raise_rust_panic(msg: &str, backtrace: &std::backtrace::Backtrace) {
+    let rt = Runtime::recover_handle();
+    let args = [
+        crate::ToValue::to_value(&msg, rt),
+        crate::ToValue::to_value(&backtrace.to_string(), rt),
+    ];
+    let exn = match crate::Error::exception_with_args(rt, "Rust_panic", &args) {
+        crate::Error::Caml(crate::CamlError::Exception(exn)) => exn,
+        _ => return,
+    };
+
+    // Release the roots before raising, nothing is allocated after this
+    let raw = exn.raw().0;
+    drop(args);
+    drop(exn);
+    crate::sys::caml_raise(raw);
+}
+
+// Convert a panic payload to a string
+#[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+fn panic_payload(err: &(dyn core::any::Any + Send)) -> String {
+    if let Some(s) = err.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = err.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        format!("{err:?}")
+    }
+}
+
+// Apply the `PanicPolicy` to a panic that reached an OCaml function boundary, this raises an OCaml
+// exception or aborts after calling `report`. The runtime lock must be held
+#[cfg(not(any(feature = "no-panic-hook", feature = "no-std")))]
+unsafe fn handle_panic(payload: String, report: impl FnOnce()) -> ! {
+    let msg = format!("rust panic: {payload}");
+
+    let rt = Runtime::recover_handle();
+    let policy = rt.panic_policy();
+    let hook = PANIC_HOOK.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    let backtrace = if hook.is_some() || policy == PanicPolicy::Exception {
+        std::backtrace::Backtrace::force_capture()
+    } else {
+        std::backtrace::Backtrace::disabled()
+    };
+
+    if let Some(hook) = hook {
+        hook(&payload, &backtrace);
+    }
+
+    let f = crate::sys::caml_named_value(RUST_PANIC_HOOK.as_ptr() as *const _);
+    if !f.is_null() {
+        let value = crate::sys::caml_alloc_string(msg.len());
+        let ptr = crate::sys::string_val(value);
+        core::ptr::copy_nonoverlapping(msg.as_ptr(), ptr, msg.len());
+        if policy == PanicPolicy::Failure {
+            // The hook is allowed to raise its own exception in place of `Failure`
+            crate::sys::caml_callback(*f, value);
+        } else {
+            // Exceptions raised by the hook are ignored so they can't bypass the policy
+            crate::sys::caml_callback_exn(*f, value);
+        }
+    }
+
+    match policy {
+        PanicPolicy::Failure => (),
+        PanicPolicy::Exception => raise_rust_panic(&payload, &backtrace),
+        PanicPolicy::Abort => {
+            report();
+            std::process::abort();
+        }
+    }
+    crate::Error::raise_failure(&msg)
+}
+
 struct PanicGuard;
 
 impl PanicGuard {
@@ -133,25 +648,8 @@ impl PanicGuard {
             let original_hook = std::panic::take_hook();
             std::panic::set_hook(Box::new(move |panic_info| {
                 if GUARD_COUNT.with(|count| count.get()) > 0 {
-                    let err = panic_info.payload();
-                    let msg = if let Some(s) = err.downcast_ref::<&str>() {
-                        format!("rust panic: {s}")
-                    } else if let Some(s) = err.downcast_ref::<String>() {
-                        format!("rust panic: {s}")
-                    } else {
-                        format!("rust panic: {err:?}")
-                    };
-
-                    unsafe {
-                        let f = crate::sys::caml_named_value(RUST_PANIC_HOOK.as_ptr() as *const _);
-                        if !f.is_null() {
-                            let value = crate::sys::caml_alloc_string(msg.len());
-                            let ptr = crate::sys::string_val(value);
-                            core::ptr::copy_nonoverlapping(msg.as_ptr(), ptr, msg.len());
-                            crate::sys::caml_callback(*f, value);
-                        }
-                        crate::Error::raise_failure(&msg);
-                    }
+                    let payload = panic_payload(panic_info.payload());
+                    unsafe { handle_panic(payload, || original_hook(panic_info)) }
                 } else {
                     original_hook(panic_info);
                 }
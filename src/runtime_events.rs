@@ -0,0 +1,337 @@
+//! Runtime events, available when using OCaml 5
+//!
+//! The OCaml 5 runtime can write GC and allocation events to a ring buffer, stored in
+//! `<pid>.events` in the current directory or `OCAML_RUNTIME_EVENTS_DIR`. `start` enables tracing
+//! for the current process and `Cursor` reads events from the current process or any other OCaml
+//! process. Reading events doesn't require the runtime lock, so a `Cursor` can be moved to another
+//! thread.
+//!
+//! `Cursor` requires the `runtime-events` feature. On OCaml 5 the consumer is part of the
+//! `runtime_events` library, which must be linked along with your Rust library, for example by
+//! adding `runtime_events` to the `libraries` field in dune.
+//!
+//! Phase and counter IDs are passed through as-is, their values are defined by `ev_runtime_phase`
+//! and `ev_runtime_counter` in `caml/runtime_events.h` and vary between OCaml versions. User
+//! events, written using `Runtime_events.User`, are decoded on the OCaml heap so they're only
+//! returned by `Cursor::read_poll_user`, which requires the runtime lock.
+
+use crate::{sys, Error, Runtime};
+
+/// Lifecycle events, see `ev_lifecycle`
+#[cfg(feature = "runtime-events")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    /// The ring buffer has been created
+    RingStart,
+    /// The ring buffer has been stopped
+    RingStop,
+    /// Tracing has been paused
+    RingPause,
+    /// Tracing has been resumed
+    RingResume,
+    /// The process forked, emitted by the parent
+    ForkParent,
+    /// The process forked, emitted by the child
+    ForkChild,
+    /// A domain has been spawned
+    DomainSpawn,
+    /// A domain has terminated
+    DomainTerminate,
+    /// Lifecycle event not known to this crate
+    Unknown(i64),
+}
+
+#[cfg(feature = "runtime-events")]
+impl From<i64> for Lifecycle {
+    fn from(x: i64) -> Lifecycle {
+        match x {
+            0 => Lifecycle::RingStart,
+            1 => Lifecycle::RingStop,
+            2 => Lifecycle::RingPause,
+            3 => Lifecycle::RingResume,
+            4 => Lifecycle::ForkParent,
+            5 => Lifecycle::ForkChild,
+            6 => Lifecycle::DomainSpawn,
+            7 => Lifecycle::DomainTerminate,
+            x => Lifecycle::Unknown(x),
+        }
+    }
+}
+
+/// User event data, see `Runtime_events.Type`
+#[cfg(feature = "runtime-events")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserData {
+    /// Event with no data
+    Unit,
+    /// Event with an integer value
+    Int(i64),
+    /// The beginning of a span
+    SpanBegin,
+    /// The end of a span
+    SpanEnd,
+    /// Event using a custom type, the value isn't available from Rust
+    Custom,
+}
+
+/// Event data
+#[cfg(feature = "runtime-events")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EventKind {
+    /// The start of a runtime phase
+    PhaseBegin(u32),
+    /// The end of a runtime phase
+    PhaseEnd(u32),
+    /// Runtime counter and its value
+    Counter {
+        /// Counter ID
+        counter: u32,
+        /// Counter value
+        value: u64,
+    },
+    /// Number of allocations for each size bucket
+    Alloc([u64; sys::RUNTIME_EVENTS_NUM_ALLOC_BUCKETS]),
+    /// Lifecycle event
+    Lifecycle {
+        /// Event type
+        event: Lifecycle,
+        /// Event data, for example the process ID for `RingStart`
+        data: i64,
+    },
+    /// Number of words that were lost because the ring buffer was overwritten before being read
+    LostEvents(usize),
+    /// User event, only returned by `Cursor::read_poll_user`
+    User {
+        /// Event name, passed to `Runtime_events.User.register`
+        name: String,
+        /// Event data
+        data: UserData,
+    },
+}
+
+/// An event read from the ring buffer
+#[cfg(feature = "runtime-events")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    /// The domain that emitted the event
+    pub domain_id: usize,
+    /// Timestamp in nanoseconds, this is 0 for `LostEvents`
+    pub timestamp: u64,
+    /// Event data
+    pub kind: EventKind,
+}
+
+#[cfg(feature = "runtime-events")]
+impl Event {
+    unsafe fn from_raw(ev: &sys::caml_sys_runtime_event) -> Option<Event> {
+        let kind = match ev.kind {
+            sys::CAML_SYS_EV_PHASE_BEGIN => EventKind::PhaseBegin(ev.id as u32),
+            sys::CAML_SYS_EV_PHASE_END => EventKind::PhaseEnd(ev.id as u32),
+            sys::CAML_SYS_EV_COUNTER => EventKind::Counter {
+                counter: ev.id as u32,
+                value: ev.data as u64,
+            },
+            sys::CAML_SYS_EV_ALLOC => {
+                let mut buckets = [0; sys::RUNTIME_EVENTS_NUM_ALLOC_BUCKETS];
+                if !ev.buckets.is_null() {
+                    buckets.copy_from_slice(core::slice::from_raw_parts(
+                        ev.buckets,
+                        sys::RUNTIME_EVENTS_NUM_ALLOC_BUCKETS,
+                    ));
+                }
+                EventKind::Alloc(buckets)
+            }
+            sys::CAML_SYS_EV_LIFECYCLE => EventKind::Lifecycle {
+                event: Lifecycle::from(ev.id),
+                data: ev.data,
+            },
+            sys::CAML_SYS_EV_LOST_EVENTS => EventKind::LostEvents(ev.data as usize),
+            sys::CAML_SYS_EV_USER_UNIT
+            | sys::CAML_SYS_EV_USER_INT
+            | sys::CAML_SYS_EV_USER_SPAN
+            | sys::CAML_SYS_EV_USER_CUSTOM => {
+                let name = core::slice::from_raw_parts(ev.name as *const u8, ev.name_len);
+                let data = match ev.kind {
+                    sys::CAML_SYS_EV_USER_UNIT => UserData::Unit,
+                    sys::CAML_SYS_EV_USER_INT => UserData::Int(ev.data),
+                    sys::CAML_SYS_EV_USER_SPAN if ev.id == 0 => UserData::SpanBegin,
+                    sys::CAML_SYS_EV_USER_SPAN => UserData::SpanEnd,
+                    _ => UserData::Custom,
+                };
+                EventKind::User {
+                    name: String::from_utf8_lossy(name).into_owned(),
+                    data,
+                }
+            }
+            _ => return None,
+        };
+
+        Some(Event {
+            domain_id: ev.domain_id as usize,
+            timestamp: ev.timestamp,
+            kind,
+        })
+    }
+}
+
+fn check(err: i32) -> Result<(), Error> {
+    let msg = match err {
+        0 => return Ok(()),
+        -1 => "runtime events cursor is already being polled",
+        -2 => "runtime events stream is corrupt",
+        -3 => "unable to allocate runtime events cursor",
+        -4 => "invalid runtime events path",
+        -5 => "unable to open runtime events ring",
+        -6 => "no runtime events ring is available for the current process",
+        -7 => "unable to map runtime events ring",
+        -8 => "runtime events cursor is not open",
+        -100 => "runtime events are not supported by this version of OCaml",
+        _ => "unknown runtime events error",
+    };
+    Err(Error::Message(msg))
+}
+
+/// Returns true when runtime events are supported by the version of OCaml being used
+pub fn is_supported() -> bool {
+    unsafe { sys::caml_sys_runtime_events_supported() != 0 }
+}
+
+/// Start writing runtime events for the current process, see `Runtime_events.start`
+pub fn start(_rt: &Runtime) -> Result<(), Error> {
+    if !is_supported() {
+        return check(-100);
+    }
+    unsafe { sys::caml_sys_runtime_events_start() }
+    Ok(())
+}
+
+/// Pause writing runtime events, see `Runtime_events.pause`
+pub fn pause(_rt: &Runtime) -> Result<(), Error> {
+    if !is_supported() {
+        return check(-100);
+    }
+    unsafe { sys::caml_sys_runtime_events_pause() }
+    Ok(())
+}
+
+/// Resume writing runtime events after `pause`, see `Runtime_events.resume`
+pub fn resume(_rt: &Runtime) -> Result<(), Error> {
+    if !is_supported() {
+        return check(-100);
+    }
+    unsafe { sys::caml_sys_runtime_events_resume() }
+    Ok(())
+}
+
+/// A cursor used to read events from a runtime events ring buffer
+#[cfg(feature = "runtime-events")]
+pub struct Cursor(*mut core::ffi::c_void);
+
+// The cursor only reads from the memory mapped ring buffer, it doesn't access the OCaml heap
+#[cfg(feature = "runtime-events")]
+unsafe impl Send for Cursor {}
+
+#[cfg(feature = "runtime-events")]
+struct Poll<F> {
+    f: F,
+    panic: Option<Box<dyn core::any::Any + Send>>,
+}
+
+#[cfg(feature = "runtime-events")]
+unsafe extern "C" fn poll_callback<F: FnMut(Event)>(
+    data: *mut core::ffi::c_void,
+    event: *const sys::caml_sys_runtime_event,
+) -> i32 {
+    let poll = &mut *(data as *mut Poll<F>);
+    let event = match Event::from_raw(&*event) {
+        Some(x) => x,
+        None => return 1,
+    };
+
+    // Unwinding through the C consumer isn't possible, the panic is resumed once polling stops
+    let f = &mut poll.f;
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(event))) {
+        Ok(()) => 1,
+        Err(e) => {
+            poll.panic = Some(e);
+            0
+        }
+    }
+}
+
+#[cfg(feature = "runtime-events")]
+impl Cursor {
+    fn open_raw(path: *const sys::Char, pid: i32) -> Result<Cursor, Error> {
+        let mut cursor = core::ptr::null_mut();
+        unsafe { check(sys::caml_sys_runtime_events_open(path, pid, &mut cursor))? }
+        Ok(Cursor(cursor))
+    }
+
+    /// Open the ring buffer for the current process, `start` should be called first
+    pub fn current() -> Result<Cursor, Error> {
+        Cursor::open_raw(core::ptr::null(), -1)
+    }
+
+    /// Open the ring buffer for the process `pid`, `dir` is the directory containing the
+    /// `<pid>.events` file
+    pub fn open(dir: impl AsRef<std::path::Path>, pid: u32) -> Result<Cursor, Error> {
+        let path = match dir.as_ref().to_str().map(std::ffi::CString::new) {
+            Some(Ok(x)) => x,
+            _ => return Err(Error::Message("invalid runtime events path")),
+        };
+        Cursor::open_raw(path.as_ptr() as *const sys::Char, pid as i32)
+    }
+
+    /// Read events from the ring buffer, calling `f` for each event. At most `max_events` are
+    /// read, or all available events if `max_events` is 0. Returns the number of events that
+    /// were consumed. User events are skipped, see `read_poll_user`
+    pub fn read_poll<F: FnMut(Event)>(&mut self, max_events: usize, f: F) -> Result<usize, Error> {
+        self.poll(max_events, f)
+    }
+
+    /// Like `read_poll`, but user events are also passed to `f`. Decoding user events allocates
+    /// OCaml values, so this requires the runtime lock
+    pub fn read_poll_user<F: FnMut(Event)>(
+        &mut self,
+        _rt: &Runtime,
+        max_events: usize,
+        f: F,
+    ) -> Result<usize, Error> {
+        unsafe { sys::caml_sys_runtime_events_set_user(self.0, 1) };
+        let res =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| self.poll(max_events, f)));
+        unsafe { sys::caml_sys_runtime_events_set_user(self.0, 0) };
+        match res {
+            Ok(x) => x,
+            Err(e) => std::panic::resume_unwind(e),
+        }
+    }
+
+    fn poll<F: FnMut(Event)>(&mut self, max_events: usize, f: F) -> Result<usize, Error> {
+        let mut poll = Poll { f, panic: None };
+        let mut consumed = 0;
+        let err = unsafe {
+            sys::caml_sys_runtime_events_read_poll(
+                self.0,
+                poll_callback::<F>,
+                &mut poll as *mut Poll<F> as *mut core::ffi::c_void,
+                max_events,
+                &mut consumed,
+            )
+        };
+
+        if let Some(e) = poll.panic {
+            std::panic::resume_unwind(e);
+        }
+
+        check(err)?;
+        Ok(consumed)
+    }
+}
+
+#[cfg(feature = "runtime-events")]
+impl Drop for Cursor {
+    fn drop(&mut self) {
+        unsafe { sys::caml_sys_runtime_events_free(self.0) }
+    }
+}
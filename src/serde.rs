@@ -0,0 +1,1058 @@
+//! Convert between OCaml values and types implementing `serde::Serialize`/`serde::Deserialize`
+//!
+//! The layout matches the one used by `derive(ToValue, FromValue)`:
+//!
+//! | Rust                                | OCaml                                         |
+//! | ----------------------------------- | --------------------------------------------- |
+//! | `bool`, `i8`, `u8`, `i16`, `u16`    | `bool`, `int`                                 |
+//! | `i32`, `u32`, `i64`, `u64`          | `int32`, `int64`                              |
+//! | `f32`, `f64`                        | `float`                                       |
+//! | `char`                              | `Uchar.t`                                     |
+//! | `String`, `&[u8]`                   | `string`, `bytes`                             |
+//! | `Option<T>`                         | `'a option`                                   |
+//! | `()`, unit structs                  | `unit`                                        |
+//! | structs, tuple structs              | records (float records when all fields are floats) |
+//! | tuples                              | tuples                                        |
+//! | sequences                           | `'a array` (or `'a list`, see `Serializer::lists`) |
+//! | maps                                | association lists, `('k * 'v) list`           |
+//! | enums                               | variants                                      |
+//!
+//! NOTE: serde only provides the index of an enum variant, not whether the other variants carry
+//! any data. OCaml numbers constant constructors and constructors with arguments separately, so
+//! enums need to be registered using `register_enum` (or `register_enum_variants` for enums that
+//! only implement `Serialize`) before they're converted. Converting an enum that hasn't been
+//! registered returns an error.
+
+use ::serde::{de, ser};
+
+use crate::{sys, ConversionError, Runtime, Tag, ToValue, TryFromValue, Value};
+
+/// Error type used when serializing or deserializing values
+#[derive(Debug)]
+pub enum Error {
+    /// Value doesn't match the expected representation
+    Conversion(ConversionError),
+
+    /// Error message from a `Serialize` or `Deserialize` implementation
+    Message(String),
+}
+
+impl core::fmt::Display for Error {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Error::Conversion(e) => write!(f, "{e}"),
+            Error::Message(s) => f.write_str(s),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: core::fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl de::Error for Error {
+    fn custom<T: core::fmt::Display>(msg: T) -> Self {
+        Error::Message(msg.to_string())
+    }
+}
+
+impl From<ConversionError> for Error {
+    fn from(e: ConversionError) -> Error {
+        Error::Conversion(e)
+    }
+}
+
+/// Convert a serializable Rust value to an OCaml value
+pub fn to_value<T: ser::Serialize + ?Sized>(rt: &Runtime, x: &T) -> Result<Value, Error> {
+    x.serialize(Serializer::new(rt))
+}
+
+/// Convert an OCaml value to a deserializable Rust value
+pub fn from_value<T: de::DeserializeOwned>(v: Value) -> Result<T, Error> {
+    T::deserialize(Deserializer::new(v))
+}
+
+// Variant layouts registered using `register_enum`, enums are identified by their name and the
+// names of their variants
+struct EnumLayout {
+    name: &'static str,
+    variants: &'static [&'static str],
+    unit: Vec<bool>,
+}
+
+static ENUMS: std::sync::Mutex<Vec<EnumLayout>> = std::sync::Mutex::new(Vec::new());
+
+/// Register an enum so it can be converted, this determines which variants are unit variants
+/// using `T`'s `Deserialize` implementation
+pub fn register_enum<T: de::DeserializeOwned>() -> Result<(), Error> {
+    let mut probe = Probe {
+        index: 0,
+        name: "",
+        variants: &[],
+        unit: None,
+    };
+    let mut unit = Vec::new();
+    loop {
+        probe.index = unit.len() as u32;
+        probe.unit = None;
+        let _ = T::deserialize(&mut probe);
+        match probe.unit {
+            Some(x) => unit.push(x),
+            None => {
+                return Err(Error::Message(format!(
+                    "unable to determine the variants of {}",
+                    core::any::type_name::<T>()
+                )))
+            }
+        }
+        if unit.len() >= probe.variants.len() {
+            break;
+        }
+    }
+
+    register_enum_variants(probe.name, probe.variants, &unit)
+}
+
+/// Register an enum using the names passed to serde, `unit[i]` is true when the variant at index
+/// `i` has no fields. This can be used for enums that don't implement `Deserialize`
+pub fn register_enum_variants(
+    name: &'static str,
+    variants: &'static [&'static str],
+    unit: &[bool],
+) -> Result<(), Error> {
+    if unit.len() != variants.len() {
+        return Err(Error::Message(format!(
+            "expected {} variants for {name}",
+            variants.len()
+        )));
+    }
+
+    let mut enums = ENUMS.lock().unwrap_or_else(|e| e.into_inner());
+    match enums
+        .iter()
+        .find(|e| e.name == name && e.variants == variants)
+    {
+        Some(e) if e.unit == unit => Ok(()),
+        Some(_) => Err(Error::Message(format!(
+            "an enum named {name} with the same variants has already been registered"
+        ))),
+        None => {
+            enums.push(EnumLayout {
+                name,
+                variants,
+                unit: unit.to_vec(),
+            });
+            Ok(())
+        }
+    }
+}
+
+fn unregistered(name: &str) -> Error {
+    Error::Message(format!(
+        "enum {name} has not been registered, see ocaml::serde::register_enum"
+    ))
+}
+
+// Returns the OCaml constructor index of a variant, constant constructors and constructors with
+// arguments are numbered separately. Only the name of the variant is known when serializing, so
+// this fails if enums that disagree on the index have been registered with the same name
+fn ocaml_index(name: &'static str, index: u32, variant: &'static str) -> Result<u32, Error> {
+    let enums = ENUMS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut result = None;
+    let layouts = enums
+        .iter()
+        .filter(|e| e.name == name && e.variants.get(index as usize) == Some(&variant));
+    for e in layouts {
+        let unit = e.unit[index as usize];
+        let n = e.unit[..index as usize]
+            .iter()
+            .filter(|x| **x == unit)
+            .count() as u32;
+        match result {
+            Some(x) if x != n => {
+                return Err(Error::Message(format!(
+                    "multiple enums named {name} have been registered"
+                )))
+            }
+            _ => result = Some(n),
+        }
+    }
+    result.ok_or_else(|| unregistered(name))
+}
+
+// Returns the serde variant index of the `n`th constant constructor or constructor with
+// arguments, the inverse of `ocaml_index`
+fn serde_index(
+    name: &'static str,
+    variants: &'static [&'static str],
+    unit: bool,
+    n: usize,
+) -> Result<Option<usize>, Error> {
+    let enums = ENUMS.lock().unwrap_or_else(|e| e.into_inner());
+    match enums
+        .iter()
+        .find(|e| e.name == name && e.variants == variants)
+    {
+        Some(e) => Ok(e
+            .unit
+            .iter()
+            .enumerate()
+            .filter(|(_, x)| **x == unit)
+            .nth(n)
+            .map(|(i, _)| i)),
+        None => Err(unregistered(name)),
+    }
+}
+
+// Deserializer used by `register_enum`, it selects the variant at `index` and records whether it
+// is a unit variant
+struct Probe {
+    index: u32,
+    name: &'static str,
+    variants: &'static [&'static str],
+    unit: Option<bool>,
+}
+
+impl<'de> de::Deserializer<'de> for &mut Probe {
+    type Error = de::value::Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, _visitor: V) -> Result<V::Value, Self::Error> {
+        Err(de::Error::custom("expected an enum"))
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.name = name;
+        self.variants = variants;
+        visitor.visit_enum(self)
+    }
+
+    ::serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option
+        unit unit_struct newtype_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+impl<'de> de::EnumAccess<'de> for &mut Probe {
+    type Error = de::value::Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), Self::Error> {
+        let index = de::value::U32Deserializer::new(self.index);
+        let v = seed.deserialize(index)?;
+        Ok((v, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for &mut Probe {
+    type Error = de::value::Error;
+
+    fn unit_variant(self) -> Result<(), Self::Error> {
+        self.unit = Some(true);
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        _seed: T,
+    ) -> Result<T::Value, Self::Error> {
+        self.unit = Some(false);
+        Err(de::Error::custom("variant has fields"))
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(
+        self,
+        _len: usize,
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.unit = Some(false);
+        Err(de::Error::custom("variant has fields"))
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        _fields: &'static [&'static str],
+        _visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.unit = Some(false);
+        Err(de::Error::custom("variant has fields"))
+    }
+}
+
+fn variant_tag(index: u32) -> Result<Tag, Error> {
+    // Tags from `Lazy_tag` and up are reserved by the runtime
+    if index >= sys::LAZY as u32 {
+        return Err(Error::Message(format!(
+            "variant index out of range: {index}"
+        )));
+    }
+    Ok(Tag(index as sys::Tag))
+}
+
+/// Serializer converting Rust values to OCaml values
+#[derive(Clone, Copy)]
+pub struct Serializer<'a> {
+    rt: &'a Runtime,
+    lists: bool,
+}
+
+impl<'a> Serializer<'a> {
+    /// Create a new serializer, sequences are converted to arrays
+    pub fn new(rt: &'a Runtime) -> Self {
+        Serializer { rt, lists: false }
+    }
+
+    /// Convert sequences to lists instead of arrays
+    pub fn lists(mut self, lists: bool) -> Self {
+        self.lists = lists;
+        self
+    }
+
+    fn compound(self, kind: CompoundKind, len: Option<usize>) -> Compound<'a> {
+        Compound {
+            ser: self,
+            kind,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+        }
+    }
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        Ok(unsafe { Value::int(v as crate::Int) })
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        Ok(v.to_value(self.rt))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        Ok(Value::none())
+    }
+
+    fn serialize_some<T: ser::Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        let v = value.serialize(self)?;
+        Ok(unsafe { Value::some(self.rt, v) })
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        Ok(Value::unit())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        Ok(Value::unit())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        let index = ocaml_index(name, variant_index, variant)?;
+        Ok(unsafe { Value::int(index as crate::Int) })
+    }
+
+    fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let mut record = self.compound(CompoundKind::Record, Some(1));
+        record.push(value)?;
+        record.finish()
+    }
+
+    fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let tag = variant_tag(ocaml_index(name, variant_index, variant)?)?;
+        let mut variant = self.compound(CompoundKind::Block(tag), Some(1));
+        variant.push(value)?;
+        variant.finish()
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(CompoundKind::Seq, len))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(CompoundKind::Block(Tag(0)), Some(len)))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(CompoundKind::Record, Some(len)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        let tag = variant_tag(ocaml_index(name, variant_index, variant)?)?;
+        Ok(self.compound(CompoundKind::Block(tag), Some(len)))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(CompoundKind::Map, len.map(|x| x * 2)))
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<Compound<'a>, Error> {
+        Ok(self.compound(CompoundKind::Record, Some(len)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Compound<'a>, Error> {
+        let tag = variant_tag(ocaml_index(name, variant_index, variant)?)?;
+        Ok(self.compound(CompoundKind::Block(tag), Some(len)))
+    }
+}
+
+enum CompoundKind {
+    Seq,
+    Map,
+    Record,
+    Block(Tag),
+}
+
+/// Used to serialize sequences, tuples, maps, structs and enum variants with fields
+///
+/// Each item is converted to a rooted `Value` before the final block is allocated
+pub struct Compound<'a> {
+    ser: Serializer<'a>,
+    kind: CompoundKind,
+    items: Vec<Value>,
+}
+
+impl<'a> Compound<'a> {
+    fn push<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let v = value.serialize(self.ser)?;
+        self.items.push(v);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let rt = self.ser.rt;
+        let is_float = |v: &Value| unsafe { v.is_block() && v.tag() == Tag::DOUBLE };
+        let all_floats = !self.items.is_empty() && self.items.iter().all(is_float);
+        unsafe {
+            match self.kind {
+                CompoundKind::Seq if self.ser.lists => Ok(list(rt, self.items)),
+                CompoundKind::Seq if all_floats && sys::FLAT_FLOAT_ARRAY => {
+                    Ok(double_array(self.items))
+                }
+                CompoundKind::Seq => Ok(block(rt, Tag(0), self.items)),
+                CompoundKind::Record if all_floats => Ok(double_array(self.items)),
+                CompoundKind::Record => Ok(block(rt, Tag(0), self.items)),
+                CompoundKind::Block(tag) => Ok(block(rt, tag, self.items)),
+                CompoundKind::Map => {
+                    let mut pairs = Vec::with_capacity(self.items.len() / 2);
+                    let mut items = self.items.into_iter();
+                    while let (Some(k), Some(v)) = (items.next(), items.next()) {
+                        pairs.push(block(rt, Tag(0), vec![k, v]));
+                    }
+                    Ok(list(rt, pairs))
+                }
+            }
+        }
+    }
+}
+
+unsafe fn block(rt: &Runtime, tag: Tag, items: Vec<Value>) -> Value {
+    let mut value = Value::alloc(items.len(), tag);
+    for (i, item) in items.iter().enumerate() {
+        value.store_field(rt, i, item);
+    }
+    value
+}
+
+unsafe fn double_array(items: Vec<Value>) -> Value {
+    let mut value = Value::alloc_double_array(items.len());
+    for (i, item) in items.iter().enumerate() {
+        value.store_double_field(i, item.double_val());
+    }
+    value
+}
+
+unsafe fn list(rt: &Runtime, items: Vec<Value>) -> Value {
+    let mut list = crate::List::empty();
+    for item in items.iter().rev() {
+        list = list.add(rt, item);
+    }
+    list.to_value(rt)
+}
+
+impl<'a> ser::SerializeSeq for Compound<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for Compound<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for Compound<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for Compound<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeMap for Compound<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: ser::Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        self.push(key)
+    }
+
+    fn serialize_value<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStruct for Compound<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for Compound<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+/// Deserializer converting OCaml values to Rust values
+pub struct Deserializer {
+    value: Value,
+    lists: bool,
+}
+
+impl Deserializer {
+    /// Create a new deserializer, sequences are expected to be arrays
+    pub fn new(value: Value) -> Self {
+        Deserializer {
+            value: value.root(),
+            lists: false,
+        }
+    }
+
+    /// Expect sequences to be lists instead of arrays
+    pub fn lists(mut self, lists: bool) -> Self {
+        self.lists = lists;
+        self
+    }
+
+    fn child(&self, value: Value) -> Deserializer {
+        Deserializer {
+            value,
+            lists: self.lists,
+        }
+    }
+
+    /// Returns the fields of a block, float arrays are converted to boxed floats
+    unsafe fn fields(&self, expected: &str, size: Option<usize>) -> Result<Vec<Value>, Error> {
+        let v = &self.value;
+        if v.is_block() && v.tag() == Tag::DOUBLE_ARRAY {
+            ConversionError::expect_block(v, expected, Tag::DOUBLE_ARRAY, size)?;
+            let len = v.array_length();
+            return Ok((0..len).map(|i| Value::double(v.double_field(i))).collect());
+        }
+
+        ConversionError::expect_block(v, expected, Tag(0), size)?;
+        let len = sys::wosize_val(v.raw().0);
+        Ok((0..len).map(|i| v.field(i)).collect())
+    }
+
+    /// Returns the items of a list
+    unsafe fn list_items(&self, expected: &str) -> Result<Vec<Value>, Error> {
+        let mut items = Vec::new();
+        let mut tmp = self.value.clone();
+        while tmp.is_block() {
+            ConversionError::expect_block(&tmp, expected, Tag(0), Some(2))?;
+            items.push(tmp.field(0));
+            tmp = tmp.field(1);
+        }
+        ConversionError::expect_immediate(&tmp, expected)?;
+        Ok(items)
+    }
+
+    fn seq(&self, items: Vec<Value>) -> SeqDeserializer {
+        SeqDeserializer {
+            items: items.into_iter(),
+            lists: self.lists,
+        }
+    }
+}
+
+macro_rules! deserialize_primitive {
+    ($($f:ident: $t:ty => $visit:ident),* $(,)?) => {
+        $(
+            fn $f<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                visitor.$visit(<$t>::try_from_value(self.value)?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer {
+    type Error = Error;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        unsafe {
+            let v = &self.value;
+            if !v.is_block() {
+                return visitor.visit_i64(v.int_val() as i64);
+            }
+
+            match v.tag() {
+                Tag::STRING => match core::str::from_utf8(v.bytes_val()) {
+                    Ok(s) => visitor.visit_str(s),
+                    Err(_) => visitor.visit_bytes(v.bytes_val()),
+                },
+                Tag::DOUBLE => visitor.visit_f64(v.double_val()),
+                Tag::CUSTOM => match crate::error::custom_identifier(v).map(|x| x.to_bytes()) {
+                    Some(b"_i") => visitor.visit_i32(v.int32_val()),
+                    Some(b"_j") => visitor.visit_i64(v.int64_val()),
+                    Some(b"_n") => visitor.visit_i64(v.nativeint_val() as i64),
+                    _ => Err(ConversionError::new("serializable value", v).into()),
+                },
+                Tag(t) if t < sys::LAZY || t == Tag::DOUBLE_ARRAY.0 => {
+                    let items = self.fields("block", None)?;
+                    visitor.visit_seq(self.seq(items))
+                }
+                _ => Err(ConversionError::new("serializable value", v).into()),
+            }
+        }
+    }
+
+    deserialize_primitive! {
+        deserialize_bool: bool => visit_bool,
+        deserialize_i8: i8 => visit_i8,
+        deserialize_i16: i16 => visit_i16,
+        deserialize_i32: i32 => visit_i32,
+        deserialize_i64: i64 => visit_i64,
+        deserialize_u8: u8 => visit_u8,
+        deserialize_u16: u16 => visit_u16,
+        deserialize_u32: u32 => visit_u32,
+        deserialize_u64: u64 => visit_u64,
+        deserialize_f32: f32 => visit_f32,
+        deserialize_f64: f64 => visit_f64,
+    }
+
+    fn deserialize_char<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let c = unsafe { ConversionError::expect_immediate(&self.value, "Uchar.t")? };
+        match u32::try_from(c).ok().and_then(char::from_u32) {
+            Some(c) => visitor.visit_char(c),
+            None => Err(ConversionError::new("Uchar.t", &self.value).into()),
+        }
+    }
+
+    fn deserialize_str<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let s = <&str>::try_from_value(self.value.clone())?;
+        visitor.visit_str(s)
+    }
+
+    fn deserialize_string<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let s = <&[u8]>::try_from_value(self.value.clone())?;
+        visitor.visit_bytes(s)
+    }
+
+    fn deserialize_byte_buf<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        unsafe {
+            if !self.value.is_block() {
+                if ConversionError::expect_immediate(&self.value, "option")? != 0 {
+                    return Err(ConversionError::new("option", &self.value).into());
+                }
+                return visitor.visit_none();
+            }
+            ConversionError::expect_block(&self.value, "option", Tag(0), Some(1))?;
+            visitor.visit_some(self.child(self.value.field(0)))
+        }
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        <()>::try_from_value(self.value)?;
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let mut fields = unsafe { self.fields(name, Some(1))? };
+        visitor.visit_newtype_struct(self.child(fields.remove(0)))
+    }
+
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let items = unsafe {
+            if self.lists {
+                self.list_items("list")?
+            } else {
+                self.fields("array", None)?
+            }
+        };
+        visitor.visit_seq(self.seq(items))
+    }
+
+    fn deserialize_tuple<V: de::Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let items = unsafe { self.fields("tuple", Some(len))? };
+        visitor.visit_seq(self.seq(items))
+    }
+
+    fn deserialize_tuple_struct<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let items = unsafe { self.fields(name, Some(len))? };
+        visitor.visit_seq(self.seq(items))
+    }
+
+    fn deserialize_map<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        let mut entries = Vec::new();
+        unsafe {
+            for item in self.list_items("association list")? {
+                ConversionError::expect_block(&item, "pair", Tag(0), Some(2))?;
+                entries.push(item.field(0));
+                entries.push(item.field(1));
+            }
+        }
+        visitor.visit_map(self.seq(entries))
+    }
+
+    fn deserialize_struct<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let items = unsafe { self.fields(name, Some(fields.len()))? };
+        visitor.visit_seq(self.seq(items))
+    }
+
+    fn deserialize_enum<V: de::Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let (unit, n) = unsafe {
+            if self.value.is_block() {
+                (false, self.value.tag().0 as usize)
+            } else {
+                let n = ConversionError::expect_immediate(&self.value, name)?;
+                (true, usize::try_from(n).unwrap_or(usize::MAX))
+            }
+        };
+        let index = match serde_index(name, variants, unit, n)? {
+            Some(index) if index < variants.len() => index,
+            _ => {
+                return Err(ConversionError::new(format!("variant of {name}"), &self.value).into())
+            }
+        };
+        visitor.visit_enum(EnumDeserializer {
+            de: self,
+            name,
+            index: index as u32,
+            tag: Tag(n as sys::Tag),
+        })
+    }
+
+    fn deserialize_identifier<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_any(visitor)
+    }
+
+    fn deserialize_ignored_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+}
+
+struct SeqDeserializer {
+    items: std::vec::IntoIter<Value>,
+    lists: bool,
+}
+
+impl SeqDeserializer {
+    fn next_deserializer(&mut self) -> Option<Deserializer> {
+        let value = self.items.next()?;
+        Some(Deserializer {
+            value,
+            lists: self.lists,
+        })
+    }
+}
+
+impl<'de> de::SeqAccess<'de> for SeqDeserializer {
+    type Error = Error;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        match self.next_deserializer() {
+            Some(de) => seed.deserialize(de).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+impl<'de> de::MapAccess<'de> for SeqDeserializer {
+    type Error = Error;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.next_deserializer() {
+            Some(de) => seed.deserialize(de).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        match self.next_deserializer() {
+            Some(de) => seed.deserialize(de),
+            None => Err(Error::Message("missing map value".into())),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len() / 2)
+    }
+}
+
+struct EnumDeserializer {
+    de: Deserializer,
+    name: &'static str,
+    index: u32,
+    tag: Tag,
+}
+
+impl<'de> de::EnumAccess<'de> for EnumDeserializer {
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(self, seed: V) -> Result<(V::Value, Self), Error> {
+        let index = de::value::U32Deserializer::<Error>::new(self.index);
+        let v = seed.deserialize(index)?;
+        Ok((v, self))
+    }
+}
+
+impl EnumDeserializer {
+    fn fields(&self, len: usize) -> Result<SeqDeserializer, Error> {
+        let expected = format!("variant of {}", self.name);
+        unsafe { ConversionError::expect_block(&self.de.value, &expected, self.tag, Some(len))? };
+        let items = (0..len)
+            .map(|i| unsafe { self.de.value.field(i) })
+            .collect();
+        Ok(self.de.seq(items))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for EnumDeserializer {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        if unsafe { self.de.value.is_block() } {
+            let expected = format!("variant of {}", self.name);
+            return Err(ConversionError::new(expected, &self.de.value).into());
+        }
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        let mut fields = self.fields(1)?;
+        match fields.next_deserializer() {
+            Some(de) => seed.deserialize(de),
+            None => unreachable!(),
+        }
+    }
+
+    fn tuple_variant<V: de::Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(self.fields(len)?)
+    }
+
+    fn struct_variant<V: de::Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_seq(self.fields(fields.len())?)
+    }
+}
@@ -11,6 +11,9 @@ use core::{
     mem, slice,
 };
 
+#[cfg(not(feature = "no-std"))]
+use crate::{value::TryFromValue, ConversionError};
+
 use crate::value::{FromValue, Size, ToValue, Value};
 
 #[derive(Clone, PartialEq, Eq)]
@@ -30,6 +33,19 @@ unsafe impl<T: FromValue> FromValue for Seq<T> {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T: FromValue> TryFromValue for Seq<T> {
+    fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+        unsafe {
+            if value.is_block() && value.tag() == Tag::INFIX {
+                return Ok(Seq(value, PhantomData));
+            }
+            ConversionError::expect_block(&value, "Seq.t", Tag::CLOSURE, None)?;
+        }
+        Ok(Seq(value, PhantomData))
+    }
+}
+
 impl<T: FromValue> core::iter::Iterator for Seq<T> {
     type Item = Result<T, Error>;
 
@@ -47,6 +63,155 @@ impl<T: FromValue> core::iter::Iterator for Seq<T> {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+impl<T: FromValue + ToValue + 'static> Seq<T> {
+    /// Create an OCaml `'a Seq.t` that pulls elements from `iter` as the sequence is forced
+    ///
+    /// Each node is memoized using `Lazy`, so the resulting sequence is persistent: forcing the
+    /// same node twice returns the same element without reading from the iterator again. The
+    /// iterator is dropped once the sequence has been collected. This requires the
+    /// `ocaml_rs_closure` and `ocaml_rs_seq_memo` named values, which are registered by the
+    /// bindings generated by `ocaml-build`
+    pub fn from_iter(
+        rt: &Runtime,
+        iter: impl Iterator<Item = T> + 'static,
+    ) -> Result<Seq<T>, Error> {
+        let iter = std::rc::Rc::new(core::cell::RefCell::new(iter));
+        Ok(Seq(seq_node(rt, iter)?, PhantomData))
+    }
+}
+
+// Allocate the `unit -> 'a Seq.node` closure for the next element of `iter`, the node is stored
+// in an OCaml `Lazy.t` rather than in the Rust closure so the GC can trace it
+#[cfg(not(feature = "no-std"))]
+fn seq_node<T: ToValue + 'static, I: Iterator<Item = T> + 'static>(
+    rt: &Runtime,
+    iter: std::rc::Rc<core::cell::RefCell<I>>,
+) -> Result<Value, Error> {
+    let memo = match unsafe { Value::named("ocaml_rs_seq_memo") } {
+        Some(x) => x,
+        None => return Err(Error::Message("ocaml_rs_seq_memo has not been registered")),
+    };
+
+    let f = crate::closure::alloc(rt, move |rt, _| {
+        // The iterator isn't borrowed while converting values, in case that calls back into the
+        // same sequence. `Nil` has the same representation as `unit`
+        let next = iter.borrow_mut().next();
+        let node = match next {
+            None => Ok(Value::unit()),
+            Some(x) => {
+                let x = x.to_value(rt);
+                seq_node(rt, iter.clone()).map(|tail| unsafe {
+                    let mut cons = Value::alloc(2, Tag(0));
+                    cons.store_field(rt, 0, x);
+                    cons.store_field(rt, 1, tail);
+                    cons
+                })
+            }
+        };
+        node.to_value(rt)
+    })?;
+    unsafe { memo.call1(rt, f) }
+}
+
+/// Arguments that can be passed to a `Callback`, this is implemented for tuples of `ToValue`
+/// types and `()`, which passes a single `unit` argument
+pub trait CallbackArgs {
+    /// Convert the arguments to OCaml values and call `f` with them
+    fn with_args<R>(self, rt: &Runtime, f: impl FnOnce(&[Raw]) -> R) -> R;
+}
+
+impl CallbackArgs for () {
+    fn with_args<R>(self, _rt: &Runtime, f: impl FnOnce(&[Raw]) -> R) -> R {
+        f(&[Value::unit().raw()])
+    }
+}
+
+macro_rules! callback_args_impl {
+    ($($t:ident: $n:tt),*) => {
+        impl<$($t: ToValue),*> CallbackArgs for ($($t,)*) {
+            fn with_args<R>(self, rt: &Runtime, f: impl FnOnce(&[Raw]) -> R) -> R {
+                // The values stay rooted until `f` returns
+                let values = [$(self.$n.to_value(rt)),*];
+                let raw = values.each_ref().map(|x| x.raw());
+                f(&raw)
+            }
+        }
+    };
+}
+
+callback_args_impl!(A: 0);
+callback_args_impl!(A: 0, B: 1);
+callback_args_impl!(A: 0, B: 1, C: 2);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3, E: 4);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10);
+callback_args_impl!(A: 0, B: 1, C: 2, D: 3, E: 4, F: 5, G: 6, H: 7, I: 8, J: 9, K: 10, L: 11);
+
+/// `Callback<Args, Ret>` is a rooted OCaml closure with typed arguments, unlike the closures
+/// returned by `ocaml::function!` it can be stored and called later
+///
+/// `Args` is a tuple of the argument types, `Callback<(Int, String), ()>` wraps
+/// `int -> string -> unit`. Closures that return another closure are handled by the runtime,
+/// a `Callback` with two arguments can wrap `fun a -> fun b -> ...`. `Ret` can also be another
+/// `Callback` to call the returned closure separately
+///
+/// ```rust,no_run
+/// # use ocaml::{Callback, Int};
+/// #[ocaml::func]
+/// #[ocaml::sig("(int -> string -> unit) -> unit")]
+/// pub fn call_handler(f: Callback<(Int, String), ()>) -> Result<(), ocaml::Error> {
+///     f.call(gc, (1, "event".to_string()))
+/// }
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Callback<Args, Ret>(Value, PhantomData<fn(Args) -> Ret>);
+
+unsafe impl<Args, Ret> ToValue for Callback<Args, Ret> {
+    fn to_value(&self, _rt: &Runtime) -> Value {
+        self.0.clone()
+    }
+}
+
+unsafe impl<Args, Ret> FromValue for Callback<Args, Ret> {
+    fn from_value(value: Value) -> Self {
+        Callback(value.root(), PhantomData)
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+unsafe impl<Args, Ret> TryFromValue for Callback<Args, Ret> {
+    fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+        unsafe {
+            // Functions defined using `let rec ... and ...` are stored as infix blocks
+            if value.is_block() && value.tag() == Tag::INFIX {
+                return Ok(Callback(value.root(), PhantomData));
+            }
+            ConversionError::expect_block(&value, "closure", Tag::CLOSURE, None)?;
+        }
+        Ok(Callback(value.root(), PhantomData))
+    }
+}
+
+impl<Args: CallbackArgs, Ret: FromValue> Callback<Args, Ret> {
+    /// Call the closure, returning an error if an exception is raised
+    pub fn call(&self, rt: &Runtime, args: Args) -> Result<Ret, Error> {
+        let res = args.with_args(rt, |args| unsafe { self.0.call_n(args) })?;
+        Ok(Ret::from_value(res))
+    }
+
+    /// Get the underlying closure
+    pub fn value(&self) -> &Value {
+        &self.0
+    }
+}
+
 /// `Array<A>` wraps an OCaml `'a array` without converting it to Rust
 #[derive(Clone, PartialEq, Eq)]
 #[repr(transparent)]
@@ -64,6 +229,19 @@ unsafe impl<T: ToValue + FromValue> FromValue for Array<T> {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T: ToValue + FromValue> TryFromValue for Array<T> {
+    fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+        unsafe {
+            if value.is_block() && value.tag() == Tag::DOUBLE_ARRAY {
+                return Ok(Array(value, PhantomData));
+            }
+            ConversionError::expect_block(&value, "array", Tag(0), None)?;
+        }
+        Ok(Array(value, PhantomData))
+    }
+}
+
 impl Array<crate::Float> {
     /// Set value to double array
     pub fn set_double(&mut self, i: usize, f: f64) -> Result<(), Error> {
@@ -233,6 +411,22 @@ unsafe impl<T: ToValue + FromValue> FromValue for List<T> {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T: ToValue + FromValue> TryFromValue for List<T> {
+    fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+        unsafe {
+            if value.is_long() {
+                if value.int_val() != 0 {
+                    return Err(ConversionError::new("list", &value));
+                }
+            } else {
+                ConversionError::expect_block(&value, "list", Tag(0), Some(2))?;
+            }
+        }
+        Ok(List(value, PhantomData))
+    }
+}
+
 impl<T: ToValue + FromValue> List<T> {
     /// An empty list
     #[inline(always)]
@@ -338,6 +532,195 @@ impl Iterator for ListIterator {
     }
 }
 
+/// `Weak<A>` wraps an OCaml `'a Weak.t`, values stored in a weak array don't prevent them from
+/// being collected
+#[derive(Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Weak<T: ToValue + FromValue>(Value, PhantomData<T>);
+
+unsafe impl<T: ToValue + FromValue> ToValue for Weak<T> {
+    fn to_value(&self, _rt: &Runtime) -> Value {
+        self.0.clone()
+    }
+}
+
+unsafe impl<T: ToValue + FromValue> FromValue for Weak<T> {
+    fn from_value(value: Value) -> Self {
+        Weak(value, PhantomData)
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+unsafe impl<T: ToValue + FromValue> TryFromValue for Weak<T> {
+    fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+        unsafe {
+            ConversionError::expect_block(&value, "Weak.t", Tag::ABSTRACT, None)?;
+        }
+        Ok(Weak(value, PhantomData))
+    }
+}
+
+impl<T: ToValue + FromValue> Weak<T> {
+    /// Create a new weak array with `n` empty slots, see `Weak.create`
+    pub fn create(_rt: &Runtime, n: usize) -> Weak<T> {
+        unsafe {
+            let x = Value::new(sys::caml_weak_create(sys::val_int(n as isize)));
+            Weak(x, PhantomData)
+        }
+    }
+
+    /// Weak array length
+    pub fn len(&self) -> usize {
+        unsafe { sys::wosize_val(self.0.raw().0) - sys::CAML_EPHE_FIRST_KEY }
+    }
+
+    /// Returns true when the weak array has no slots
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Set or clear the value at index `i`
+    pub fn set(&mut self, rt: &Runtime, i: usize, v: Option<&T>) -> Result<(), Error> {
+        if i >= self.len() {
+            return Err(CamlError::ArrayBoundError.into());
+        }
+
+        unsafe {
+            let v = match v {
+                Some(v) => Value::some(rt, v),
+                None => Value::none(),
+            };
+            sys::caml_weak_set(self.0.raw().0, sys::val_int(i as isize), v.raw().0);
+        }
+        Ok(())
+    }
+
+    /// Get the value at index `i`, returns `None` if the slot is empty or the value has been
+    /// collected
+    pub fn get(&self, i: usize) -> Result<Option<T>, Error> {
+        if i >= self.len() {
+            return Err(CamlError::ArrayBoundError.into());
+        }
+
+        unsafe {
+            let x = Value::new(sys::caml_weak_get(self.0.raw().0, sys::val_int(i as isize)));
+            Ok(FromValue::from_value(x))
+        }
+    }
+
+    /// Returns true when index `i` contains a value, without keeping it alive
+    pub fn check(&self, i: usize) -> Result<bool, Error> {
+        if i >= self.len() {
+            return Err(CamlError::ArrayBoundError.into());
+        }
+
+        unsafe {
+            let x = sys::caml_weak_check(self.0.raw().0, sys::val_int(i as isize));
+            Ok(x == sys::TRUE)
+        }
+    }
+}
+
+/// `Ephemeron<K, V>` wraps an OCaml ephemeron with a single key, the data is kept alive as long
+/// as the key is reachable. See `Ephemeron.K1`
+#[derive(Clone, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Ephemeron<K: ToValue + FromValue, V: ToValue + FromValue>(Value, PhantomData<(K, V)>);
+
+unsafe impl<K: ToValue + FromValue, V: ToValue + FromValue> ToValue for Ephemeron<K, V> {
+    fn to_value(&self, _rt: &Runtime) -> Value {
+        self.0.clone()
+    }
+}
+
+unsafe impl<K: ToValue + FromValue, V: ToValue + FromValue> FromValue for Ephemeron<K, V> {
+    fn from_value(value: Value) -> Self {
+        Ephemeron(value, PhantomData)
+    }
+}
+
+#[cfg(not(feature = "no-std"))]
+unsafe impl<K: ToValue + FromValue, V: ToValue + FromValue> TryFromValue for Ephemeron<K, V> {
+    fn try_from_value(value: Value) -> Result<Self, ConversionError> {
+        unsafe {
+            ConversionError::expect_block(
+                &value,
+                "Ephemeron.t",
+                Tag::ABSTRACT,
+                Some(sys::CAML_EPHE_FIRST_KEY + 1),
+            )?;
+        }
+        Ok(Ephemeron(value, PhantomData))
+    }
+}
+
+impl<K: ToValue + FromValue, V: ToValue + FromValue> Ephemeron<K, V> {
+    /// Create a new ephemeron without a key or data
+    pub fn create(_rt: &Runtime) -> Ephemeron<K, V> {
+        unsafe {
+            let x = Value::new(sys::caml_ephe_create(sys::val_int(1)));
+            Ephemeron(x, PhantomData)
+        }
+    }
+
+    /// Set the key
+    pub fn set_key(&mut self, rt: &Runtime, k: &K) {
+        unsafe {
+            let k = k.to_value(rt);
+            sys::caml_ephe_set_key(self.0.raw().0, sys::val_int(0), k.raw().0);
+        }
+    }
+
+    /// Remove the key
+    pub fn unset_key(&mut self) {
+        unsafe {
+            sys::caml_ephe_unset_key(self.0.raw().0, sys::val_int(0));
+        }
+    }
+
+    /// Get the key, returns `None` if it isn't set or has been collected
+    pub fn get_key(&self) -> Option<K> {
+        unsafe {
+            let x = Value::new(sys::caml_ephe_get_key(self.0.raw().0, sys::val_int(0)));
+            FromValue::from_value(x)
+        }
+    }
+
+    /// Returns true when the key is set, without keeping it alive
+    pub fn check_key(&self) -> bool {
+        unsafe { sys::caml_ephe_check_key(self.0.raw().0, sys::val_int(0)) == sys::TRUE }
+    }
+
+    /// Set the data
+    pub fn set_data(&mut self, rt: &Runtime, v: &V) {
+        unsafe {
+            let v = v.to_value(rt);
+            sys::caml_ephe_set_data(self.0.raw().0, v.raw().0);
+        }
+    }
+
+    /// Remove the data
+    pub fn unset_data(&mut self) {
+        unsafe {
+            sys::caml_ephe_unset_data(self.0.raw().0);
+        }
+    }
+
+    /// Get the data, returns `None` if it isn't set or has been released because the key was
+    /// collected
+    pub fn get_data(&self) -> Option<V> {
+        unsafe {
+            let x = Value::new(sys::caml_ephe_get_data(self.0.raw().0));
+            FromValue::from_value(x)
+        }
+    }
+
+    /// Returns true when the data is set, without keeping it alive
+    pub fn check_data(&self) -> bool {
+        unsafe { sys::caml_ephe_check_data(self.0.raw().0) == sys::TRUE }
+    }
+}
+
 /// `bigarray` contains wrappers for OCaml `Bigarray` values. These types can be used to transfer arrays of numbers between Rust
 /// and OCaml directly without the allocation overhead of an `array` or `list`
 pub mod bigarray {
@@ -393,6 +776,30 @@ pub mod bigarray {
         }
     }
 
+    #[cfg(not(feature = "no-std"))]
+    unsafe impl<T: Kind> crate::TryFromValue for Array1<T> {
+        fn try_from_value(value: Value) -> Result<Array1<T>, ConversionError> {
+            unsafe { check_bigarray::<T>(&value, "Bigarray.Array1.t", 1)? };
+            Ok(Array1(value, PhantomData))
+        }
+    }
+
+    /// Checks that `value` is a bigarray with the expected kind and number of dimensions
+    #[cfg(not(feature = "no-std"))]
+    pub(crate) unsafe fn check_bigarray<T: Kind>(
+        value: &Value,
+        expected: &str,
+        num_dims: sys::Intnat,
+    ) -> Result<(), ConversionError> {
+        ConversionError::expect_custom(value, expected, "_bigarr02")?;
+        let ba = value.custom_ptr_val::<bigarray::Bigarray>();
+        let kind = (*ba).flags & bigarray::Kind::KIND_MASK as sys::Intnat;
+        if (*ba).num_dims != num_dims || kind != T::kind() as sys::Intnat {
+            return Err(ConversionError::new(expected, value));
+        }
+        Ok(())
+    }
+
     impl<T: Copy + Kind> Array1<T> {
         /// Array1::of_slice is used to convert from a slice to OCaml Bigarray,
         /// the `data` parameter must outlive the resulting bigarray or there is
@@ -538,6 +945,15 @@ pub(crate) mod bigarray_ext {
         }
     }
 
+    unsafe impl<T: Kind> crate::TryFromValue for Array2<T> {
+        fn try_from_value(value: Value) -> Result<Array2<T>, crate::ConversionError> {
+            unsafe {
+                crate::bigarray::check_bigarray::<T>(&value, "Bigarray.Array2.t", 2)?;
+            }
+            Ok(Array2(value, PhantomData))
+        }
+    }
+
     impl<T: Copy + Kind> Array2<T> {
         /// Create a new OCaml `Bigarray.Array2` with the given type and shape
         pub unsafe fn create(dim: ndarray::Ix2) -> Array2<T> {
@@ -622,6 +1038,15 @@ pub(crate) mod bigarray_ext {
         }
     }
 
+    unsafe impl<T: Kind> crate::TryFromValue for Array3<T> {
+        fn try_from_value(value: Value) -> Result<Array3<T>, crate::ConversionError> {
+            unsafe {
+                crate::bigarray::check_bigarray::<T>(&value, "Bigarray.Array3.t", 3)?;
+            }
+            Ok(Array3(value, PhantomData))
+        }
+    }
+
     impl<T: Copy + Kind> Array3<T> {
         /// Create a new OCaml `Bigarray.Array3` with the given type and shape
         pub unsafe fn create(dim: ndarray::Ix3) -> Array3<T> {
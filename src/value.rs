@@ -1,3 +1,5 @@
+#[cfg(not(feature = "no-std"))]
+use crate::error::ConversionError;
 use crate::error::{CamlError, Error};
 use crate::tag::Tag;
 use crate::{root::Root, sys, util, Pointer, Runtime};
@@ -16,12 +18,33 @@ pub enum Value {
     Raw(sys::Value),
 }
 
+/// Flags used by `Value::marshal`, these correspond to `Marshal.extern_flags`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarshalFlag {
+    /// Don't preserve sharing
+    NoSharing,
+
+    /// Send function closures
+    Closures,
+
+    /// Ensure output is readable on 32-bit platforms
+    Compat32,
+}
+
 /// Wrapper around sys::Value
+///
+/// `Raw` is not `Send`, it isn't safe to use OCaml values from other threads or while the runtime
+/// lock is released
 #[derive(Debug, Clone, Copy, PartialEq, PartialOrd, Eq)]
 #[repr(transparent)]
-pub struct Raw(pub sys::Value);
+pub struct Raw(pub sys::Value, core::marker::PhantomData<*const ()>);
 
 impl Raw {
+    /// Wrap a `sys::Value`, this replaces the `Raw(x)` constructor
+    pub const fn new(x: sys::Value) -> Raw {
+        Raw(x, core::marker::PhantomData)
+    }
+
     /// Convert a `Raw` value to `Value`, this should only be used in custom value destructors
     /// and other cases where you know the underlying `sys::Value` was created using `Value::new`
     /// or it is not possible for the value to be garbage collected (i.e. inside of a `@@noalloc`
@@ -44,7 +67,7 @@ impl AsRef<sys::Value> for Raw {
 
 impl From<sys::Value> for Raw {
     fn from(x: sys::Value) -> Raw {
-        Raw(x)
+        Raw::new(x)
     }
 }
 
@@ -86,6 +109,27 @@ pub unsafe trait FromValue {
     fn from_value(v: Value) -> Self;
 }
 
+/// `Owned` is implemented for types that don't reference the OCaml heap, these can be used as
+/// arguments and return values in functions that release the runtime lock using
+/// `#[ocaml::func(release_lock)]`
+///
+/// # Safety
+/// Implementors must not contain any OCaml values (including `Value`, `Raw` and anything built on
+/// them) or borrow from the OCaml heap
+pub unsafe trait Owned {}
+
+/// `TryFromValue` is a fallible alternative to `FromValue`: the shape of the OCaml value
+/// (immediate or block, tag, size and string encoding) is checked before it is converted
+///
+/// When used with `#[ocaml::func(checked)]`, arguments that fail to convert will raise
+/// `Invalid_argument` instead of being accessed blindly
+#[cfg(not(feature = "no-std"))]
+pub unsafe trait TryFromValue: Sized {
+    /// Convert from OCaml value, returning an error if the value doesn't match the expected
+    /// representation
+    fn try_from_value(v: Value) -> Result<Self, ConversionError>;
+}
+
 unsafe impl ToValue for Value {
     fn to_value(&self, _rt: &Runtime) -> Value {
         self.clone()
@@ -98,6 +142,13 @@ unsafe impl FromValue for Value {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+unsafe impl TryFromValue for Value {
+    fn try_from_value(v: Value) -> Result<Value, ConversionError> {
+        Ok(v)
+    }
+}
+
 unsafe impl ToValue for Raw {
     fn to_value(&self, _rt: &Runtime) -> Value {
         unsafe { Value::new(self.0) }
@@ -111,12 +162,19 @@ unsafe impl FromValue for Raw {
     }
 }
 
+#[cfg(not(feature = "no-std"))]
+unsafe impl TryFromValue for Raw {
+    fn try_from_value(v: Value) -> Result<Raw, ConversionError> {
+        Ok(v.raw())
+    }
+}
+
 impl Value {
     /// Get raw OCaml value
     pub fn raw(&self) -> Raw {
         match self {
             Value::Root(r) => unsafe { r.get().into() },
-            Value::Raw(r) => Raw(*r),
+            Value::Raw(r) => Raw::from(*r),
         }
     }
 
@@ -184,6 +242,10 @@ impl Value {
 
     /// Allocate custom value
     pub unsafe fn alloc_custom<T: crate::Custom>() -> Value {
+        if T::OPS.deserialize.is_some() {
+            crate::custom::register::<T>();
+        }
+
         let size = core::mem::size_of::<T>();
         Value::new(sys::caml_alloc_custom(
             T::ops() as *const _ as *const sys::custom_operations,
@@ -193,6 +255,21 @@ impl Value {
         ))
     }
 
+    /// Allocate a new value with a custom type that holds `mem` bytes outside of the OCaml heap,
+    /// this is used by `Pointer::alloc_custom` when `Custom::mem_size` is non-zero
+    pub unsafe fn alloc_custom_mem<T: crate::Custom>(mem: usize) -> Value {
+        if T::OPS.deserialize.is_some() {
+            crate::custom::register::<T>();
+        }
+
+        let size = core::mem::size_of::<T>();
+        Value::new(sys::caml_alloc_custom_mem(
+            T::ops() as *const _ as *mut sys::custom_operations,
+            size,
+            mem,
+        ))
+    }
+
     /// Allocate an abstract pointer value, it is best to ensure the value is
     /// on the heap using `Box::into_raw(Box::from(...))` to create the pointer
     /// and `Box::from_raw` to free it
@@ -224,6 +301,77 @@ impl Value {
         sys::caml_remove_global_root(&mut self.raw().0)
     }
 
+    /// Allocate an OCaml closure that calls a Rust function, arguments are converted using
+    /// `FromValue` and the result using `ToValue`. The Rust function is dropped when the closure
+    /// is collected
+    ///
+    /// This requires the `ocaml_rs_closure` named values, which are registered by the bindings
+    /// generated by `ocaml-build`
+    ///
+    /// ```rust,no_run
+    /// # let rt = ocaml::Runtime::init();
+    /// // int -> int -> int
+    /// let add = ocaml::Value::closure(&rt, |_rt: &ocaml::Runtime, a: ocaml::Int, b: ocaml::Int| a + b);
+    /// ```
+    #[cfg(not(feature = "no-std"))]
+    pub fn closure<Args>(
+        rt: &Runtime,
+        f: impl crate::closure::IntoClosure<Args>,
+    ) -> Result<Value, Error> {
+        f.into_closure(rt)
+    }
+
+    /// Register a finaliser that is called with the value once it becomes unreachable, see
+    /// `Gc.finalise`. `f` is dropped without being called if the finaliser never runs.
+    ///
+    /// This requires the `ocaml_rs_closure` named value, which is registered by the bindings
+    /// generated by `ocaml-build`
+    #[cfg(not(feature = "no-std"))]
+    pub fn finalise(&self, rt: &Runtime, f: impl FnOnce(Value) + 'static) -> Result<(), Error> {
+        let f = core::cell::Cell::new(Some(f));
+        self.register_finaliser(rt, false, move |_, v| {
+            if let Some(f) = f.take() {
+                f(v)
+            }
+            Value::unit()
+        })
+    }
+
+    /// Register a finaliser that is called once the value becomes unreachable, unlike
+    /// `Value::finalise` the value itself isn't available to the finaliser. See `Gc.finalise_last`
+    #[cfg(not(feature = "no-std"))]
+    pub fn finalise_last(&self, rt: &Runtime, f: impl FnOnce() + 'static) -> Result<(), Error> {
+        let f = core::cell::Cell::new(Some(f));
+        self.register_finaliser(rt, true, move |_, _| {
+            if let Some(f) = f.take() {
+                f()
+            }
+            Value::unit()
+        })
+    }
+
+    #[cfg(not(feature = "no-std"))]
+    fn register_finaliser(
+        &self,
+        rt: &Runtime,
+        last: bool,
+        f: impl Fn(&Runtime, Value) -> Value + 'static,
+    ) -> Result<(), Error> {
+        unsafe {
+            if !self.is_block() {
+                return Err(CamlError::InvalidArgument("Gc.finalise").into());
+            }
+
+            let f = crate::closure::alloc(rt, f)?;
+            if last {
+                sys::caml_final_register_called_without_value(f.raw().0, self.raw().0);
+            } else {
+                sys::caml_final_register(f.raw().0, self.raw().0);
+            }
+            Ok(())
+        }
+    }
+
     /// Get the tag for the underlying OCaml `value`
     pub unsafe fn tag(&self) -> Tag {
         sys::tag_val(self.raw().0).into()
@@ -486,12 +634,19 @@ impl Value {
             Value::Raw(mut r) => sys::caml_modify(&mut r, sys::extract_exception(r)),
         }
 
-        Err(CamlError::Exception(self).into())
+        let err = CamlError::from_exception(self);
+
+        #[cfg(not(feature = "no-std"))]
+        if let Some(bt) = crate::Backtrace::capture(Runtime::recover_handle()) {
+            return Err(Error::CamlBacktrace(err, bt));
+        }
+
+        Err(err.into())
     }
 
     /// Call a closure with a single argument, returning an exception result
     pub unsafe fn call1<A: ToValue>(&self, rt: &Runtime, arg1: A) -> Result<Value, Error> {
-        if self.tag() != Tag::CLOSURE {
+        if !matches!(self.tag(), Tag::CLOSURE | Tag::INFIX) {
             return Err(Error::NotCallable);
         }
 
@@ -509,7 +664,7 @@ impl Value {
         arg1: A,
         arg2: B,
     ) -> Result<Value, Error> {
-        if self.tag() != Tag::CLOSURE {
+        if !matches!(self.tag(), Tag::CLOSURE | Tag::INFIX) {
             return Err(Error::NotCallable);
         }
 
@@ -535,7 +690,7 @@ impl Value {
         arg2: B,
         arg3: C,
     ) -> Result<Value, Error> {
-        if self.tag() != Tag::CLOSURE {
+        if !matches!(self.tag(), Tag::CLOSURE | Tag::INFIX) {
             return Err(Error::NotCallable);
         }
 
@@ -557,7 +712,7 @@ impl Value {
 
     /// Call a closure with `n` arguments, returning an exception result
     pub unsafe fn call_n<A: AsRef<[Raw]>>(&self, args: A) -> Result<Value, Error> {
-        if self.tag() != Tag::CLOSURE {
+        if !matches!(self.tag(), Tag::CLOSURE | Tag::INFIX) {
             return Err(Error::NotCallable);
         }
 
@@ -579,7 +734,7 @@ impl Value {
         rt: &Runtime,
         args: [impl ToValue; N],
     ) -> Result<T, Error> {
-        if self.tag() != Tag::CLOSURE {
+        if !matches!(self.tag(), Tag::CLOSURE | Tag::INFIX) {
             return Err(Error::NotCallable);
         }
 
@@ -678,7 +833,9 @@ impl Value {
     #[cfg(not(feature = "no-std"))]
     pub unsafe fn exception_to_string(&self) -> Result<String, core::str::Utf8Error> {
         let ptr = ocaml_sys::caml_format_exception(self.raw().0);
-        std::ffi::CStr::from_ptr(ptr).to_str().map(|x| x.to_owned())
+        let s = std::ffi::CStr::from_ptr(ptr).to_str().map(|x| x.to_owned());
+        ocaml_sys::caml_stat_free(ptr as *mut core::ffi::c_void);
+        s
     }
 
     /// Initialize OCaml value using `caml_initialize`
@@ -748,6 +905,138 @@ impl Value {
         Value::new(slice0.as_ptr().offset(1) as isize)
     }
 
+    /// Serialize a value using `Marshal`, the result can be read using `Value::unmarshal`, the
+    /// OCaml `Marshal` module or `ocaml::marshal::decode`
+    ///
+    /// Values that can't be serialized (functions without `MarshalFlag::Closures`, custom blocks
+    /// without a `serialize` function and values that are too big for `MarshalFlag::Compat32`)
+    /// are returned as errors
+    #[cfg(not(feature = "no-std"))]
+    pub fn marshal(&self, flags: &[MarshalFlag]) -> Result<Vec<u8>, Error> {
+        unsafe {
+            self.check_marshal(flags)?;
+
+            let rt = Runtime::recover_handle();
+            let mut flags_list = crate::List::empty();
+            for flag in flags.iter().rev() {
+                flags_list = flags_list.add(rt, &Value::int(*flag as crate::Int));
+            }
+            let flags_list = flags_list.to_value(rt);
+
+            let mut buf = core::ptr::null_mut();
+            let mut len = 0;
+            sys::caml_output_value_to_malloc(self.raw().0, flags_list.raw().0, &mut buf, &mut len);
+            let dest = core::slice::from_raw_parts(buf as *const u8, len as usize).to_vec();
+            sys::caml_stat_free(buf as *mut core::ffi::c_void);
+            Ok(dest)
+        }
+    }
+
+    // Check for values that would cause `caml_output_value_to_malloc` to raise an exception
+    #[cfg(not(feature = "no-std"))]
+    unsafe fn check_marshal(&self, flags: &[MarshalFlag]) -> Result<(), Error> {
+        let closures = flags.contains(&MarshalFlag::Closures);
+        let compat_32 = flags.contains(&MarshalFlag::Compat32);
+
+        let mut seen = std::collections::HashSet::new();
+        let mut stack = vec![self.raw().0];
+        while let Some(v) = stack.pop() {
+            if sys::is_long(v) {
+                if compat_32 && !(-(1 << 30)..(1 << 30)).contains(&sys::int_val(v)) {
+                    return Err(CamlError::Failure(
+                        "output_value: integer cannot be read back on 32-bit platform",
+                    )
+                    .into());
+                }
+                continue;
+            }
+
+            if !seen.insert(v) {
+                continue;
+            }
+
+            let size = sys::wosize_val(v);
+            match Tag(sys::tag_val(v)) {
+                Tag::CLOSURE | Tag::INFIX if !closures => {
+                    return Err(CamlError::InvalidArgument("output_value: functional value").into())
+                }
+                Tag::INFIX => {
+                    // Infix blocks are stored as an offset into the enclosing closure
+                    stack.push(v - (size * core::mem::size_of::<sys::Value>()) as sys::Value);
+                }
+                Tag::CLOSURE => {
+                    for i in sys::caml_sys_closure_start_env(v)..size {
+                        stack.push(*sys::field(v, i));
+                    }
+                }
+                Tag::ABSTRACT => {
+                    return Err(CamlError::InvalidArgument(
+                        "output_value: abstract value (Abstract)",
+                    )
+                    .into())
+                }
+                Tag::CUSTOM => {
+                    let ops = *sys::field(v, 0) as *const sys::custom_operations;
+                    if (*ops).serialize.is_none() {
+                        return Err(CamlError::InvalidArgument(
+                            "output_value: abstract value (Custom)",
+                        )
+                        .into());
+                    }
+                }
+                Tag::STRING if compat_32 && sys::caml_string_length(v) > 0xFF_FFFB => {
+                    return Err(CamlError::Failure(
+                        "output_value: string cannot be read back on 32-bit platform",
+                    )
+                    .into())
+                }
+                Tag::DOUBLE_ARRAY if compat_32 && size > 0x3F_FFFF => {
+                    return Err(CamlError::Failure(
+                        "output_value: float array cannot be read back on 32-bit platform",
+                    )
+                    .into())
+                }
+                tag if tag < Tag::NO_SCAN => {
+                    if compat_32 && size > 0x3F_FFFF {
+                        return Err(CamlError::Failure(
+                            "output_value: array cannot be read back on 32-bit platform",
+                        )
+                        .into());
+                    }
+                    for i in 0..size {
+                        stack.push(*sys::field(v, i));
+                    }
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    /// Deserialize a value created using `Value::marshal`, the OCaml `Marshal` module or
+    /// `ocaml::marshal::encode`
+    ///
+    /// The data is checked before it's passed to the runtime: truncated or malformed data, custom
+    /// blocks that haven't been registered and closures from other programs are returned as
+    /// errors
+    #[cfg(not(feature = "no-std"))]
+    pub fn unmarshal(_rt: &Runtime, data: &[u8]) -> Result<Value, Error> {
+        let size = crate::marshal::validate(
+            data,
+            |ident| {
+                let ident = std::ffi::CString::new(ident).unwrap_or_default();
+                unsafe { sys::caml_sys_custom_operations_registered(ident.as_ptr()) != 0 }
+            },
+            |digest| unsafe { sys::caml_sys_code_fragment_registered(digest.as_ptr()) != 0 },
+        )?;
+
+        unsafe {
+            let v =
+                sys::caml_input_value_from_block(data.as_ptr() as *const sys::Char, size as isize);
+            Ok(Value::new(v))
+        }
+    }
+
     /// Ensure a value is rooted
     pub fn root(self) -> Value {
         match self {
@@ -81,6 +81,7 @@ fn link(out_dir: std::path::PathBuf, ocamlopt: String, ocaml_path: &str) -> std:
 #[allow(unused)]
 fn run() -> std::io::Result<()> {
     println!("cargo:rerun-if-file-changed=sys/ocaml-sys.c");
+    println!("cargo:rerun-if-file-changed=sys/runtime_events.c");
     println!("cargo:rerun-if-env-changed=OCAMLOPT");
     println!("cargo:rerun-if-env-changed=OCAML_VERSION");
     println!("cargo:rerun-if-env-changed=OCAML_WHERE_PATH");
@@ -158,6 +159,23 @@ fn run() -> std::io::Result<()> {
         .compile("ocaml-sys");
     println!("cargo:rustc-link-lib=static=ocaml-sys");
 
+    // The runtime events consumer is part of the `runtime_events` library on OCaml 5, so it's
+    // kept separate to avoid requiring that library for everything else
+    #[cfg(feature = "runtime-events")]
+    {
+        cc::Build::new()
+            .file("src/runtime_events.c")
+            .include(&ocaml_path)
+            .compile("ocaml-sys-runtime-events");
+        println!("cargo:rustc-link-lib=static=ocaml-sys-runtime-events");
+
+        #[cfg(feature = "link")]
+        if major >= 5 {
+            println!("cargo:rustc-link-search={ocaml_path}/runtime_events");
+            println!("cargo:rustc-link-lib=static=camlruntime_events");
+        }
+    }
+
     Ok(())
 }
 
@@ -37,3 +37,6 @@ extern "C" {
 extern "C" {
     pub fn caml_register_custom_operations(ops: *mut custom_operations);
 }
+extern "C" {
+    pub fn caml_find_custom_operations(ident: *const Char) -> *mut custom_operations;
+}
@@ -0,0 +1,36 @@
+use crate::{Char, Intnat, Value};
+
+extern "C" {
+    pub fn caml_output_value_to_malloc(
+        v: Value,
+        flags: Value,
+        buf: *mut *mut Char,
+        len: *mut Intnat,
+    );
+    pub fn caml_input_value_from_block(data: *const Char, len: Intnat) -> Value;
+
+    pub fn caml_sys_closure_start_env(v: Value) -> crate::Uintnat;
+    pub fn caml_sys_custom_operations_registered(ident: *const Char) -> i32;
+    pub fn caml_sys_code_fragment_registered(digest: *const u8) -> i32;
+
+    pub fn caml_serialize_int_1(i: i32);
+    pub fn caml_serialize_int_2(i: i32);
+    pub fn caml_serialize_int_4(i: i32);
+    pub fn caml_serialize_int_8(i: i64);
+    pub fn caml_serialize_float_4(f: f32);
+    pub fn caml_serialize_float_8(f: f64);
+    pub fn caml_serialize_block_1(data: *const core::ffi::c_void, len: Intnat);
+
+    pub fn caml_deserialize_uint_1() -> i32;
+    pub fn caml_deserialize_sint_1() -> i32;
+    pub fn caml_deserialize_uint_2() -> i32;
+    pub fn caml_deserialize_sint_2() -> i32;
+    pub fn caml_deserialize_uint_4() -> u32;
+    pub fn caml_deserialize_sint_4() -> i32;
+    pub fn caml_deserialize_uint_8() -> u64;
+    pub fn caml_deserialize_sint_8() -> i64;
+    pub fn caml_deserialize_float_4() -> f32;
+    pub fn caml_deserialize_float_8() -> f64;
+    pub fn caml_deserialize_block_1(data: *mut core::ffi::c_void, len: Intnat);
+    pub fn caml_deserialize_error(msg: *const Char) -> !;
+}
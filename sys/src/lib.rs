@@ -36,10 +36,13 @@ pub mod bigarray;
 mod callback;
 mod custom;
 mod fail;
+mod intext;
 mod printexc;
 mod runtime;
+mod runtime_events;
 mod state;
 mod tag;
+mod weak;
 
 pub use self::mlvalues::Value;
 pub use self::tag::Tag;
@@ -47,9 +50,12 @@ pub use alloc::*;
 pub use callback::*;
 pub use custom::*;
 pub use fail::*;
+pub use intext::*;
 pub use memory::*;
 pub use mlvalues::*;
 pub use printexc::*;
 pub use runtime::*;
+pub use runtime_events::*;
 pub use state::*;
 pub use tag::*;
+pub use weak::*;
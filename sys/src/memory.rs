@@ -60,6 +60,7 @@ impl Default for CamlRootsBlock {
 extern "C" {
     pub fn caml_modify(addr: *mut Value, value: Value);
     pub fn caml_initialize(addr: *mut Value, value: Value);
+    pub fn caml_stat_free(ptr: *mut core::ffi::c_void);
 }
 
 /// Stores the `$val` at `$offset` in the `$block`.
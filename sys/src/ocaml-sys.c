@@ -1,4 +1,6 @@
+#include <caml/memory.h>
 #include <caml/mlvalues.h>
+#include <caml/version.h>
 
 void caml_sys_store_double_val(value x, double f) { Store_double_val(x, f); }
 double caml_sys_double_val(value x) { return Double_val(x); }
@@ -9,4 +11,129 @@ void caml_sys_store_double_field(value x, mlsize_t index, double d) {
   Store_double_field(x, index, d);
 }
 
-caml_domain_state *caml_sys_get_domain_state() { return Caml_state; }
+// Returns NULL if the current thread isn't attached to a domain, since OCaml 5.1
+// `Caml_state` aborts in that case
+caml_domain_state *caml_sys_get_domain_state() {
+#ifdef Caml_state_opt
+  return Caml_state_opt;
+#else
+  return Caml_state;
+#endif
+}
+
+struct caml__roots_block *caml_sys_local_roots() {
+  return Caml_state->local_roots;
+}
+
+void caml_sys_set_local_roots(struct caml__roots_block *x) {
+  Caml_state->local_roots = x;
+}
+
+intnat caml_sys_domain_id() {
+#if OCAML_VERSION_MAJOR >= 5
+  return Caml_state->id;
+#else
+  return 0;
+#endif
+}
+
+// The marshalling functions raise exceptions that can't be caught without calling into OCaml, the
+// helpers below are used by `Value::marshal` and `Value::unmarshal` to detect the same errors
+// before calling `caml_output_value_to_malloc` or `caml_input_value_from_block`
+
+// Only declared by the runtime headers when CAML_INTERNALS is defined
+extern struct custom_operations *caml_find_custom_operations(const char *ident);
+#if OCAML_VERSION_MAJOR > 4 || OCAML_VERSION_MINOR >= 12
+extern struct code_fragment *
+caml_find_code_fragment_by_digest(unsigned char digest[16]);
+#endif
+
+// Returns the index of the first environment field of a closure, the fields before it contain
+// code pointers and closure information
+uintnat caml_sys_closure_start_env(value v) {
+#if OCAML_VERSION_MAJOR > 4 || OCAML_VERSION_MINOR >= 12
+  return Start_env_closinfo(Closinfo_val(v));
+#else
+  // The environment can't be located without the closure information
+  return Wosize_val(v);
+#endif
+}
+
+// Returns 1 if custom operations have been registered for `ident`
+int caml_sys_custom_operations_registered(const char *ident) {
+  return caml_find_custom_operations(ident) != NULL;
+}
+
+// Returns 1 if the code fragment with the given digest is part of the current program
+int caml_sys_code_fragment_registered(unsigned char digest[16]) {
+#if OCAML_VERSION_MAJOR > 4 || OCAML_VERSION_MINOR >= 12
+  return caml_find_code_fragment_by_digest(digest) != NULL;
+#else
+  return 1;
+#endif
+}
+
+// Gc.Memprof is available in OCaml 4.11 to 4.14 and from OCaml 5.3
+#if (OCAML_VERSION_MAJOR == 4 && OCAML_VERSION_MINOR >= 11) ||                 \
+    (OCAML_VERSION_MAJOR == 5 && OCAML_VERSION_MINOR >= 3) ||                  \
+    OCAML_VERSION_MAJOR > 5
+#define CAML_SYS_MEMPROF
+extern value caml_memprof_start(value lambda, value stack_size, value tracker);
+extern value caml_memprof_stop(value unit);
+#endif
+
+int caml_sys_memprof_supported() {
+#ifdef CAML_SYS_MEMPROF
+  return 1;
+#else
+  return 0;
+#endif
+}
+
+value caml_sys_memprof_start(value lambda, value stack_size, value tracker) {
+#ifdef CAML_SYS_MEMPROF
+  return caml_memprof_start(lambda, stack_size, tracker);
+#else
+  return Val_unit;
+#endif
+}
+
+value caml_sys_memprof_stop(value unit) {
+#ifdef CAML_SYS_MEMPROF
+  return caml_memprof_stop(unit);
+#else
+  return Val_unit;
+#endif
+}
+
+// Runtime events are only available in OCaml 5, the consumer functions are in
+// runtime_events.c since they're part of the runtime_events library
+#if OCAML_VERSION_MAJOR >= 5
+#include <caml/runtime_events.h>
+#endif
+
+int caml_sys_runtime_events_supported() {
+#if OCAML_VERSION_MAJOR >= 5
+  return 1;
+#else
+  return 0;
+#endif
+}
+
+void caml_sys_runtime_events_start() {
+#if OCAML_VERSION_MAJOR >= 5
+  caml_runtime_events_start();
+#endif
+}
+
+void caml_sys_runtime_events_pause() {
+#if OCAML_VERSION_MAJOR >= 5
+  caml_runtime_events_pause();
+#endif
+}
+
+void caml_sys_runtime_events_resume() {
+#if OCAML_VERSION_MAJOR >= 5
+  caml_runtime_events_resume();
+#endif
+}
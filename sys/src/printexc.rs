@@ -2,4 +2,8 @@ use crate::{Char, Value};
 
 extern "C" {
     pub fn caml_format_exception(v: Value) -> *const Char;
+    pub fn caml_backtrace_status(unit: Value) -> Value;
+    pub fn caml_get_exception_raw_backtrace(unit: Value) -> Value;
+    pub fn caml_convert_raw_backtrace(bt: Value) -> Value;
+    pub fn caml_debug_info_available() -> i32;
 }
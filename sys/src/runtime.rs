@@ -8,6 +8,8 @@ extern "C" {
     pub fn caml_enter_blocking_section();
     pub fn caml_leave_blocking_section();
     pub fn caml_thread_initialize(unit: Value) -> Value;
+    pub fn caml_c_thread_register() -> i32;
+    pub fn caml_c_thread_unregister() -> i32;
 }
 
 // GC control
@@ -16,4 +18,21 @@ extern "C" {
     pub fn caml_gc_major(v: Value);
     pub fn caml_gc_full_major(v: Value);
     pub fn caml_gc_compaction(v: Value);
+    pub fn caml_gc_stat(v: Value) -> Value;
+    pub fn caml_gc_quick_stat(v: Value) -> Value;
+    pub fn caml_gc_get(v: Value) -> Value;
+    pub fn caml_gc_set(v: Value) -> Value;
+    pub fn caml_final_register(f: Value, v: Value) -> Value;
+    pub fn caml_final_register_called_without_value(f: Value, v: Value) -> Value;
+}
+
+// Gc.Memprof, these are wrappers around the runtime functions because they aren't available in
+// every version of OCaml
+extern "C" {
+    #[doc(hidden)]
+    pub fn caml_sys_memprof_supported() -> i32;
+    #[doc(hidden)]
+    pub fn caml_sys_memprof_start(lambda: Value, stack_size: Value, tracker: Value) -> Value;
+    #[doc(hidden)]
+    pub fn caml_sys_memprof_stop(unit: Value) -> Value;
 }
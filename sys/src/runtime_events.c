@@ -0,0 +1,210 @@
+#include <caml/mlvalues.h>
+#include <caml/version.h>
+
+// Runtime events consumer, on OCaml 5 this requires linking the `runtime_events`
+// library. This file is only built when the `runtime-events` feature is enabled
+#if OCAML_VERSION_MAJOR >= 5
+#include <caml/runtime_events.h>
+#include <caml/runtime_events_consumer.h>
+#endif
+
+struct caml_sys_runtime_event {
+  int kind;
+  int domain_id;
+  uint64_t timestamp;
+  int64_t id;
+  int64_t data;
+  const uint64_t *buckets;
+  const char *name;
+  uintnat name_len;
+};
+
+typedef int (*caml_sys_runtime_event_callback)(
+    void *data, const struct caml_sys_runtime_event *event);
+
+struct caml_sys_runtime_events_poll {
+  caml_sys_runtime_event_callback f;
+  void *data;
+};
+
+enum {
+  CAML_SYS_EV_PHASE_BEGIN,
+  CAML_SYS_EV_PHASE_END,
+  CAML_SYS_EV_COUNTER,
+  CAML_SYS_EV_ALLOC,
+  CAML_SYS_EV_LIFECYCLE,
+  CAML_SYS_EV_LOST_EVENTS,
+  CAML_SYS_EV_USER_UNIT,
+  CAML_SYS_EV_USER_INT,
+  CAML_SYS_EV_USER_SPAN,
+  CAML_SYS_EV_USER_CUSTOM,
+};
+
+#if OCAML_VERSION_MAJOR >= 5
+static int caml_sys_runtime_event_emit(void *data, int kind, int domain_id,
+                                       uint64_t timestamp, int64_t id,
+                                       int64_t value, const uint64_t *buckets) {
+  struct caml_sys_runtime_events_poll *poll = data;
+  struct caml_sys_runtime_event event = {
+      kind, domain_id, timestamp, id, value, buckets, NULL, 0};
+  return poll->f(poll->data, &event);
+}
+
+static int caml_sys_runtime_begin(int domain_id, void *data, uint64_t timestamp,
+                                  ev_runtime_phase phase) {
+  return caml_sys_runtime_event_emit(data, CAML_SYS_EV_PHASE_BEGIN, domain_id,
+                                     timestamp, phase, 0, NULL);
+}
+
+static int caml_sys_runtime_end(int domain_id, void *data, uint64_t timestamp,
+                                ev_runtime_phase phase) {
+  return caml_sys_runtime_event_emit(data, CAML_SYS_EV_PHASE_END, domain_id,
+                                     timestamp, phase, 0, NULL);
+}
+
+static int caml_sys_runtime_counter(int domain_id, void *data,
+                                    uint64_t timestamp,
+                                    ev_runtime_counter counter, uint64_t val) {
+  return caml_sys_runtime_event_emit(data, CAML_SYS_EV_COUNTER, domain_id,
+                                     timestamp, counter, val, NULL);
+}
+
+static int caml_sys_runtime_alloc(int domain_id, void *data, uint64_t timestamp,
+                                  uint64_t *buckets) {
+  return caml_sys_runtime_event_emit(data, CAML_SYS_EV_ALLOC, domain_id,
+                                     timestamp, 0, 0, buckets);
+}
+
+static int caml_sys_runtime_lifecycle(int domain_id, void *data,
+                                      int64_t timestamp,
+                                      ev_lifecycle lifecycle, int64_t val) {
+  return caml_sys_runtime_event_emit(data, CAML_SYS_EV_LIFECYCLE, domain_id,
+                                     timestamp, lifecycle, val, NULL);
+}
+
+static int caml_sys_runtime_lost_events(int domain_id, void *data,
+                                        int lost_words) {
+  return caml_sys_runtime_event_emit(data, CAML_SYS_EV_LOST_EVENTS, domain_id,
+                                     0, 0, lost_words, NULL);
+}
+
+// User events are passed as `Runtime_events.User.t` records, the second field
+// is the name of the event
+static int caml_sys_runtime_user_emit(void *data, int kind, int domain_id,
+                                      int64_t timestamp, value event_type,
+                                      int64_t id, int64_t val) {
+  struct caml_sys_runtime_events_poll *poll = data;
+  value name = Field(event_type, 1);
+  struct caml_sys_runtime_event event = {kind,
+                                         domain_id,
+                                         timestamp,
+                                         id,
+                                         val,
+                                         NULL,
+                                         String_val(name),
+                                         caml_string_length(name)};
+  return poll->f(poll->data, &event);
+}
+
+static int caml_sys_runtime_user_unit(int domain_id, void *data,
+                                      int64_t timestamp, value event_type) {
+  return caml_sys_runtime_user_emit(data, CAML_SYS_EV_USER_UNIT, domain_id,
+                                    timestamp, event_type, 0, 0);
+}
+
+static int caml_sys_runtime_user_int(int domain_id, void *data,
+                                     int64_t timestamp, value event_type,
+                                     value event_content) {
+  return caml_sys_runtime_user_emit(data, CAML_SYS_EV_USER_INT, domain_id,
+                                    timestamp, event_type, 0,
+                                    Long_val(event_content));
+}
+
+static int caml_sys_runtime_user_span(int domain_id, void *data,
+                                      int64_t timestamp, value event_type,
+                                      int span) {
+  return caml_sys_runtime_user_emit(data, CAML_SYS_EV_USER_SPAN, domain_id,
+                                    timestamp, event_type, span, 0);
+}
+
+static int caml_sys_runtime_user_custom(int domain_id, void *data,
+                                        int64_t timestamp, value event_type,
+                                        value event_content) {
+  (void)event_content;
+  return caml_sys_runtime_user_emit(data, CAML_SYS_EV_USER_CUSTOM, domain_id,
+                                    timestamp, event_type, 0, 0);
+}
+#endif
+
+int caml_sys_runtime_events_open(const char *path, int pid, void **cursor) {
+#if OCAML_VERSION_MAJOR >= 5
+  struct caml_runtime_events_cursor *c = NULL;
+  runtime_events_error err =
+      caml_runtime_events_create_cursor(path, pid, &c);
+  if (err != E_SUCCESS) {
+    return err;
+  }
+
+  caml_runtime_events_set_runtime_begin(c, caml_sys_runtime_begin);
+  caml_runtime_events_set_runtime_end(c, caml_sys_runtime_end);
+  caml_runtime_events_set_runtime_counter(c, caml_sys_runtime_counter);
+  caml_runtime_events_set_alloc(c, caml_sys_runtime_alloc);
+  caml_runtime_events_set_lifecycle(c, caml_sys_runtime_lifecycle);
+  caml_runtime_events_set_lost_events(c, caml_sys_runtime_lost_events);
+  *cursor = c;
+  return 0;
+#else
+  (void)path;
+  (void)pid;
+  *cursor = NULL;
+  return -100;
+#endif
+}
+
+int caml_sys_runtime_events_read_poll(void *cursor,
+                                      caml_sys_runtime_event_callback f,
+                                      void *data, uintnat max_events,
+                                      uintnat *events_consumed) {
+#if OCAML_VERSION_MAJOR >= 5
+  struct caml_sys_runtime_events_poll poll = {f, data};
+  return caml_runtime_events_read_poll(cursor, &poll, max_events,
+                                       events_consumed);
+#else
+  (void)cursor;
+  (void)f;
+  (void)data;
+  (void)max_events;
+  *events_consumed = 0;
+  return -100;
+#endif
+}
+
+// Decoding user events allocates on the OCaml heap, so they're only enabled
+// while the runtime lock is held
+void caml_sys_runtime_events_set_user(void *cursor, int enabled) {
+#if OCAML_VERSION_MAJOR >= 5
+  struct caml_runtime_events_cursor *c = cursor;
+  caml_runtime_events_set_user_unit(c,
+                                    enabled ? caml_sys_runtime_user_unit : NULL);
+  caml_runtime_events_set_user_int(c,
+                                   enabled ? caml_sys_runtime_user_int : NULL);
+  // The span type is an enum, which is passed as an int
+  caml_runtime_events_set_user_span(
+      c, enabled ? (void *)caml_sys_runtime_user_span : NULL);
+  caml_runtime_events_set_user_custom(
+      c, enabled ? caml_sys_runtime_user_custom : NULL);
+#else
+  (void)cursor;
+  (void)enabled;
+#endif
+}
+
+void caml_sys_runtime_events_free(void *cursor) {
+#if OCAML_VERSION_MAJOR >= 5
+  if (cursor != NULL) {
+    caml_runtime_events_free_cursor(cursor);
+  }
+#else
+  (void)cursor;
+#endif
+}
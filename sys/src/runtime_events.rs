@@ -0,0 +1,77 @@
+#![allow(non_camel_case_types)]
+
+// Runtime events, these are wrappers around the runtime functions because they're only available
+// in OCaml 5
+
+extern "C" {
+    #[doc(hidden)]
+    pub fn caml_sys_runtime_events_supported() -> i32;
+    #[doc(hidden)]
+    pub fn caml_sys_runtime_events_start();
+    #[doc(hidden)]
+    pub fn caml_sys_runtime_events_pause();
+    #[doc(hidden)]
+    pub fn caml_sys_runtime_events_resume();
+}
+
+// Runtime events consumer, on OCaml 5 this requires linking the `runtime_events` library
+#[cfg(feature = "runtime-events")]
+mod consumer {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    #[doc(hidden)]
+    pub struct caml_sys_runtime_event {
+        pub kind: i32,
+        pub domain_id: i32,
+        pub timestamp: u64,
+        pub id: i64,
+        pub data: i64,
+        pub buckets: *const u64,
+        pub name: *const crate::Char,
+        pub name_len: crate::Uintnat,
+    }
+
+    #[doc(hidden)]
+    pub type caml_sys_runtime_event_callback = unsafe extern "C" fn(
+        data: *mut core::ffi::c_void,
+        event: *const caml_sys_runtime_event,
+    ) -> i32;
+
+    pub const CAML_SYS_EV_PHASE_BEGIN: i32 = 0;
+    pub const CAML_SYS_EV_PHASE_END: i32 = 1;
+    pub const CAML_SYS_EV_COUNTER: i32 = 2;
+    pub const CAML_SYS_EV_ALLOC: i32 = 3;
+    pub const CAML_SYS_EV_LIFECYCLE: i32 = 4;
+    pub const CAML_SYS_EV_LOST_EVENTS: i32 = 5;
+    pub const CAML_SYS_EV_USER_UNIT: i32 = 6;
+    pub const CAML_SYS_EV_USER_INT: i32 = 7;
+    pub const CAML_SYS_EV_USER_SPAN: i32 = 8;
+    pub const CAML_SYS_EV_USER_CUSTOM: i32 = 9;
+
+    /// Number of buckets in an allocation event
+    pub const RUNTIME_EVENTS_NUM_ALLOC_BUCKETS: usize = 20;
+
+    extern "C" {
+        #[doc(hidden)]
+        pub fn caml_sys_runtime_events_open(
+            path: *const crate::Char,
+            pid: i32,
+            cursor: *mut *mut core::ffi::c_void,
+        ) -> i32;
+        #[doc(hidden)]
+        pub fn caml_sys_runtime_events_read_poll(
+            cursor: *mut core::ffi::c_void,
+            f: caml_sys_runtime_event_callback,
+            data: *mut core::ffi::c_void,
+            max_events: crate::Uintnat,
+            events_consumed: *mut crate::Uintnat,
+        ) -> i32;
+        #[doc(hidden)]
+        pub fn caml_sys_runtime_events_set_user(cursor: *mut core::ffi::c_void, enabled: i32);
+        #[doc(hidden)]
+        pub fn caml_sys_runtime_events_free(cursor: *mut core::ffi::c_void);
+    }
+}
+
+#[cfg(feature = "runtime-events")]
+pub use consumer::*;
@@ -76,6 +76,11 @@ pub struct caml_domain_state {
     pub _stat_heap_chunks: isize,
 }
 
+/// The OCaml 5 domain state, this follows the layout used by OCaml 5.0
+///
+/// Mirroring the full structure is out of scope: fields are added, removed and reordered between
+/// OCaml 5 releases, so they shouldn't be accessed directly. The `caml_sys_*` helpers below read
+/// the fields that are needed from C instead
 #[repr(C)]
 #[derive(Debug)]
 #[cfg(feature = "ocaml5")]
@@ -143,14 +148,23 @@ pub struct caml_domain_state {
 extern "C" {
     #[doc(hidden)]
     pub fn caml_sys_get_domain_state() -> *mut caml_domain_state;
+
+    #[doc(hidden)]
+    pub fn caml_sys_domain_id() -> crate::Intnat;
+
+    #[doc(hidden)]
+    pub fn caml_sys_local_roots() -> *mut crate::memory::CamlRootsBlock;
+
+    #[doc(hidden)]
+    pub fn caml_sys_set_local_roots(x: *mut crate::memory::CamlRootsBlock);
 }
 
 #[doc(hidden)]
 pub unsafe fn local_roots() -> *mut crate::memory::CamlRootsBlock {
-    (*caml_sys_get_domain_state())._local_roots
+    caml_sys_local_roots()
 }
 
 #[doc(hidden)]
 pub unsafe fn set_local_roots(x: *mut crate::memory::CamlRootsBlock) {
-    (*caml_sys_get_domain_state())._local_roots = x
+    caml_sys_set_local_roots(x)
 }
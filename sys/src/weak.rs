@@ -0,0 +1,21 @@
+use crate::Value;
+
+// Weak arrays and ephemerons, the indices are OCaml ints
+extern "C" {
+    pub fn caml_weak_create(len: Value) -> Value;
+    pub fn caml_weak_set(ar: Value, n: Value, el: Value) -> Value;
+    pub fn caml_weak_get(ar: Value, n: Value) -> Value;
+    pub fn caml_weak_check(ar: Value, n: Value) -> Value;
+    pub fn caml_ephe_create(len: Value) -> Value;
+    pub fn caml_ephe_set_key(ar: Value, n: Value, el: Value) -> Value;
+    pub fn caml_ephe_unset_key(ar: Value, n: Value) -> Value;
+    pub fn caml_ephe_get_key(ar: Value, n: Value) -> Value;
+    pub fn caml_ephe_check_key(ar: Value, n: Value) -> Value;
+    pub fn caml_ephe_set_data(ar: Value, el: Value) -> Value;
+    pub fn caml_ephe_unset_data(ar: Value) -> Value;
+    pub fn caml_ephe_get_data(ar: Value) -> Value;
+    pub fn caml_ephe_check_data(ar: Value) -> Value;
+}
+
+/// Index of the first key in a weak array or ephemeron
+pub const CAML_EPHE_FIRST_KEY: usize = 2;
@@ -24,3 +24,58 @@ let%test "apply3 invalid_arg" =
 
 let%test "apply range 1" =
   Util.check_leaks (fun () -> apply_range (List.map (fun a  -> let () = Util.gc () in a + 1)) 0 10 = [1; 2; 3; 4; 5; 6; 7; 8; 9; 10])
+
+let%test "callback handlers" = Util.check_leaks (fun () ->
+  let events = ref [] in
+  callback_register (fun i s -> events := (i, s) :: !events);
+  callback_register (fun i _ -> events := (i * 10, "second") :: !events);
+  Util.gc ();
+  callback_fire 1;
+  callback_fire 2;
+  callback_clear ();
+  callback_fire 3;
+  List.rev !events = [(1, "event 1"); (10, "second"); (2, "event 2"); (20, "second")])
+
+let%test "callback currying" = Util.check_leaks (fun () ->
+  callback_curried (fun a b -> a * 10 + b) = 12
+  && callback_curried (fun a -> let x = a * 100 in fun b -> x + b) = 102
+  && callback_call2 (fun a -> let x = a * 100 in fun b -> x + b) = 102
+  && callback_call2 ( - ) = -1)
+
+let%test "callback infix" = Util.check_leaks (fun () ->
+  let rec f a b = if a <= 0 then b else g (a - 1) (b + 1)
+  and g a b = f a (b * 2) in
+  callback_call2_checked g = 10 && callback_call2_checked f = 6)
+
+let%test "callback unit" = Util.check_leaks (fun () ->
+  callback_unit (fun () -> "unit") = "unit")
+
+let%test "callback exception" =
+  try callback_call2 (fun _ _ -> failwith "callback") |> ignore; false
+  with Failure s -> s = "callback"
+
+let%test "closure add" = Util.check_leaks (fun () ->
+  let add = closure_add () in
+  let add1 = add 1 in
+  add 2 3 = 5 && add1 10 = 11 && List.map (add 10) [1; 2] = [11; 12])
+
+let%test "closure comparator" = Util.check_leaks (fun () ->
+  List.sort (closure_compare_len ()) ["ccc"; "a"; "bb"] = ["a"; "bb"; "ccc"])
+
+let%test "closure state" = Util.check_leaks (fun () ->
+  let next = closure_counter () in
+  let a = next () in
+  let b = next () in
+  a = 1 && b = 2)
+
+let%test "closure exception" =
+  let div = closure_checked_div () in
+  div 4 = 25 && (try ignore (div 0); false with Division_by_zero -> true)
+
+let%test "closure drop" =
+  let before = closures_dropped () in
+  let[@inline never] call () = (closure_with_drop ()) 1 in
+  let x = call () in
+  Gc.full_major ();
+  Gc.full_major ();
+  x = 1 && closures_dropped () > before
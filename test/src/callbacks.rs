@@ -28,3 +28,124 @@ pub unsafe fn apply_range(f: Value, start: ocaml::Int, stop: ocaml::Int) -> Resu
     let f = ocaml::function!(f, (a: ocaml::List<ocaml::Int>) -> Value);
     f(gc, &l)
 }
+
+thread_local! {
+    static HANDLERS: std::cell::RefCell<Vec<ocaml::Callback<(ocaml::Int, String), ()>>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+#[ocaml::func]
+#[ocaml::sig("(int -> string -> unit) -> unit")]
+pub fn callback_register(f: ocaml::Callback<(ocaml::Int, String), ()>) {
+    HANDLERS.with(|h| h.borrow_mut().push(f));
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> unit")]
+pub fn callback_fire(i: ocaml::Int) -> Result<(), Error> {
+    let handlers = HANDLERS.with(|h| h.borrow().clone());
+    for h in handlers {
+        h.call(gc, (i, format!("event {i}")))?;
+    }
+    Ok(())
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> unit")]
+pub fn callback_clear() {
+    HANDLERS.with(|h| h.borrow_mut().clear());
+}
+
+#[ocaml::func]
+#[ocaml::sig("(int -> int -> int) -> int")]
+pub fn callback_curried(
+    f: ocaml::Callback<(ocaml::Int,), ocaml::Callback<(ocaml::Int,), ocaml::Int>>,
+) -> Result<ocaml::Int, Error> {
+    let g = f.call(gc, (1,))?;
+    g.call(gc, (2,))
+}
+
+#[ocaml::func]
+#[ocaml::sig("(int -> int -> int) -> int")]
+pub fn callback_call2(
+    f: ocaml::Callback<(ocaml::Int, ocaml::Int), ocaml::Int>,
+) -> Result<ocaml::Int, Error> {
+    f.call(gc, (1, 2))
+}
+
+#[ocaml::func(checked)]
+#[ocaml::sig("(int -> int -> int) -> int")]
+pub fn callback_call2_checked(
+    f: ocaml::Callback<(ocaml::Int, ocaml::Int), ocaml::Int>,
+) -> Result<ocaml::Int, Error> {
+    f.call(gc, (1, 2))
+}
+
+#[ocaml::func]
+#[ocaml::sig("(unit -> string) -> string")]
+pub fn callback_unit(f: ocaml::Callback<(), String>) -> Result<String, Error> {
+    f.call(gc, ())
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> (int -> int -> int)")]
+pub fn closure_add() -> Result<Value, Error> {
+    Value::closure(gc, |_rt: &ocaml::Runtime, a: ocaml::Int, b: ocaml::Int| {
+        a + b
+    })
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> (string -> string -> int)")]
+pub fn closure_compare_len() -> Result<Value, Error> {
+    Value::closure(gc, |_rt: &ocaml::Runtime, a: String, b: String| {
+        a.len().cmp(&b.len()) as ocaml::Int
+    })
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> (unit -> int)")]
+pub fn closure_counter() -> Result<Value, Error> {
+    let n = std::cell::Cell::new(0);
+    Value::closure(gc, move |_rt: &ocaml::Runtime| {
+        n.set(n.get() + 1);
+        n.get() as ocaml::Int
+    })
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> (int -> int)")]
+pub fn closure_checked_div() -> Result<Value, Error> {
+    Value::closure(gc, |_rt: &ocaml::Runtime, a: ocaml::Int| {
+        if a == 0 {
+            return Err(Error::Caml(ocaml::CamlError::ZeroDivide));
+        }
+        Ok(100 / a)
+    })
+}
+
+static CLOSURES_DROPPED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+struct DropCounter;
+
+impl Drop for DropCounter {
+    fn drop(&mut self) {
+        CLOSURES_DROPPED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> (int -> int)")]
+pub fn closure_with_drop() -> Result<Value, Error> {
+    let counter = DropCounter;
+    Value::closure(gc, move |_rt: &ocaml::Runtime, a: ocaml::Int| {
+        let _ = &counter;
+        a
+    })
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int")]
+pub fn closures_dropped() -> ocaml::Int {
+    CLOSURES_DROPPED.load(std::sync::atomic::Ordering::SeqCst) as ocaml::Int
+}
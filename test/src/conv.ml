@@ -127,3 +127,70 @@ let%test "index float array f64" = Util.check_leaks (fun () ->
 let%test "index floatarray f64" = Util.check_leaks (fun () ->
   let arr = Rust.make_floatarray_f64_012 () in
   List.for_all (fun i -> Float.Array.get arr i = Float.of_int i) [0; 1; 2])
+
+let invalid_argument f =
+  try ignore (f ()); false with Invalid_argument _ -> true
+
+let%test "checked enum" = Util.check_leaks (fun () ->
+  enum1_is_empty_checked Empty && not (enum1_is_empty_checked (First 1)))
+
+let%test "checked enum (invalid)" = Util.check_leaks (fun () ->
+  invalid_argument (fun () -> enum1_is_empty_checked (Obj.magic 5)) &&
+  invalid_argument (fun () -> enum1_is_empty_checked (Obj.magic (Some 1.0))))
+
+let%test "checked struct" = Util.check_leaks (fun () ->
+  struct1_get_c_checked {a = 1; b = 2.0; c = Some "testing"; d = None} = Some "testing")
+
+let%test "checked struct (invalid)" = Util.check_leaks (fun () ->
+  invalid_argument (fun () -> struct1_get_c_checked (Obj.magic (1, 2))) &&
+  invalid_argument (fun () -> struct1_get_c_checked (Obj.magic (1, 2.0, Some 3, None))))
+
+let%test "checked float struct" = Util.check_leaks (fun () ->
+  all_float_struct_sum_checked {float_a = 1.0; float_b = 2.0} = 3.0 &&
+  invalid_argument (fun () -> all_float_struct_sum_checked (Obj.magic [| 1.0 |])))
+
+let%test "checked args" = Util.check_leaks (fun () ->
+  add_length_checked 1 "abc" = 4 &&
+  invalid_argument (fun () -> add_length_checked 1 (Obj.magic 2)) &&
+  invalid_argument (fun () -> add_length_checked (Obj.magic "abc") "abc"))
+
+let%test "checked error message" =
+  try ignore (struct1_get_c_checked (Obj.magic (1, 2.0, Some 3, None))); false
+  with Invalid_argument msg ->
+    msg = "struct1_get_c_checked: argument `s`: expected string, found int 3 at .c(Some)"
+
+let%test "serde record" = Util.check_leaks (fun () ->
+  let r = {label = "abc"; values = [| 1l; 2l |]; tags = ["a", 1L]; parent = None; shape = Rect (1.0, 2.0)} in
+  let r = serde_record_update r in
+  Util.gc ();
+  r.values = [| 1l; 2l; 2l |] && r.tags = ["a", 1L; "count", 3L] &&
+  r.parent = Some "abc" && r.shape = Rect (2.0, 1.0) &&
+  (serde_record_update r).shape = Rect (1.0, 2.0))
+
+let%test "serde variant" = Util.check_leaks (fun () ->
+  let r = {label = ""; values = [||]; tags = []; parent = Some "x"; shape = Circle 1.5} in
+  let r = serde_record_update r in
+  r.shape = Circle 3.0 && r.parent = Some "" && r.values = [| 0l |])
+
+let%test "serde mixed variant" = Util.check_leaks (fun () ->
+  let r = {label = ""; values = [||]; tags = []; parent = None; shape = Point} in
+  (serde_record_update r).shape = Point &&
+  (serde_record_update {r with shape = Rect (1.0, 2.0)}).shape = Rect (2.0, 1.0))
+
+let%test "serde invalid option" = Util.check_leaks (fun () ->
+  let r = {label = ""; values = [||]; tags = []; parent = Obj.magic 1; shape = Point} in
+  try ignore (serde_record_update r); false with Failure _ -> true)
+
+let%test "serde float record" = Util.check_leaks (fun () ->
+  serde_point_swap {px = 1.0; py = 2.0} = {px = 2.0; py = 1.0})
+
+let%test "serde list" = Util.check_leaks (fun () ->
+  serde_list_sum [1; 2; 3] = [6L] && serde_list_sum [] = [0L])
+
+let%test "serde unregistered enum" = Util.check_leaks (fun () ->
+  serde_level 1 = Error "enum SerdeLevel has not been registered, see ocaml::serde::register_enum" &&
+  serde_level 2 = Ok (Some 2L) &&
+  serde_level 0 = Ok None)
+
+let%test "serde error" = Util.check_leaks (fun () ->
+  serde_record_error (1, 2) = "expected SerdeRecord, found block with tag 0 and size 2")
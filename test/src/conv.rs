@@ -1,6 +1,6 @@
 use ocaml::{FromValue, ToValue};
 
-#[derive(ToValue, FromValue)]
+#[derive(ToValue, FromValue, ocaml::TryFromValue)]
 #[ocaml::sig("Empty | First of int | Second of string array")]
 enum Enum1 {
     Empty,
@@ -43,7 +43,7 @@ pub fn enum1_is_empty(e: Enum1) -> bool {
     matches!(e, Enum1::Empty)
 }
 
-#[derive(ToValue, FromValue, Default)]
+#[derive(ToValue, FromValue, ocaml::TryFromValue, Default)]
 #[ocaml::sig("{a: int; b: float; mutable c: string option; d: string array option;}")]
 struct Struct1 {
     a: ocaml::Int,
@@ -165,7 +165,7 @@ pub fn result_get_error(x: Result<ocaml::Value, ocaml::Value>) -> Option<ocaml::
     x.err()
 }
 
-#[derive(ocaml::ToValue, ocaml::FromValue, Debug)]
+#[derive(ocaml::ToValue, ocaml::FromValue, ocaml::TryFromValue, Debug)]
 #[ocaml::sig("{float_a: float; float_b: float}")]
 pub struct AllFloatStruct {
     float_a: ocaml::Float,
@@ -223,3 +223,126 @@ pub fn make_float_array_f64_012() -> Vec<f64> {
 pub fn make_floatarray_f64_012() -> Vec<f64> {
     vec![0.0, 1.0, 2.0]
 }
+
+#[ocaml::func(checked)]
+#[ocaml::sig("enum1 -> bool")]
+pub fn enum1_is_empty_checked(e: Enum1) -> bool {
+    matches!(e, Enum1::Empty)
+}
+
+#[ocaml::func(checked)]
+#[ocaml::sig("struct1 -> string option")]
+pub fn struct1_get_c_checked(s: Struct1) -> Option<String> {
+    s.c
+}
+
+#[ocaml::func(checked)]
+#[ocaml::sig("all_float_struct -> float")]
+pub fn all_float_struct_sum_checked(t: AllFloatStruct) -> ocaml::Float {
+    t.float_a + t.float_b
+}
+
+#[ocaml::func(rt, checked)]
+#[ocaml::sig("int -> string -> int")]
+pub fn add_length_checked(a: ocaml::Int, s: &str) -> ocaml::Int {
+    let _ = rt;
+    a + s.len() as ocaml::Int
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[ocaml::sig("Circle of float | Point | Rect of float * float")]
+pub enum SerdeShape {
+    Circle(f64),
+    Point,
+    Rect(f64, f64),
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[ocaml::sig("{label: string; values: int32 array; tags: (string * int64) list; parent: string option; shape: serde_shape}")]
+pub struct SerdeRecord {
+    label: String,
+    values: Vec<i32>,
+    tags: std::collections::BTreeMap<String, i64>,
+    parent: Option<String>,
+    shape: SerdeShape,
+}
+
+#[ocaml::func]
+#[ocaml::sig("serde_record -> serde_record")]
+pub fn serde_record_update(r: ocaml::Value) -> Result<ocaml::Value, ocaml::Error> {
+    ocaml::serde::register_enum::<SerdeShape>()?;
+    let mut r: SerdeRecord = ocaml::serde::from_value(r)?;
+    r.values.push(r.values.len() as i32);
+    r.tags.insert("count".into(), r.values.len() as i64);
+    r.parent = Some(r.label.clone());
+    r.shape = match r.shape {
+        SerdeShape::Circle(x) => SerdeShape::Circle(x * 2.0),
+        SerdeShape::Point => SerdeShape::Point,
+        SerdeShape::Rect(w, h) => SerdeShape::Rect(h, w),
+    };
+    Ok(ocaml::serde::to_value(gc, &r)?)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[ocaml::sig("{px: float; py: float}")]
+pub struct SerdePoint {
+    px: f64,
+    py: f64,
+}
+
+#[ocaml::func]
+#[ocaml::sig("serde_point -> serde_point")]
+pub fn serde_point_swap(p: ocaml::Value) -> Result<ocaml::Value, ocaml::Error> {
+    let p: SerdePoint = ocaml::serde::from_value(p)?;
+    Ok(ocaml::serde::to_value(
+        gc,
+        &SerdePoint { px: p.py, py: p.px },
+    )?)
+}
+
+#[ocaml::func]
+#[ocaml::sig("int list -> int64 list")]
+pub fn serde_list_sum(l: ocaml::Value) -> Result<ocaml::Value, ocaml::Error> {
+    let de = ocaml::serde::Deserializer::new(l).lists(true);
+    let l: Vec<i8> = serde::Deserialize::deserialize(de)?;
+    let ser = ocaml::serde::Serializer::new(gc).lists(true);
+    let sum = l.iter().map(|x| *x as i64).sum::<i64>();
+    Ok(serde::Serialize::serialize(&[sum], ser)?)
+}
+
+#[ocaml::func]
+#[ocaml::sig("'a -> string")]
+pub fn serde_record_error(r: ocaml::Value) -> String {
+    match ocaml::serde::from_value::<SerdeRecord>(r) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[derive(serde::Serialize)]
+pub enum SerdeLevel {
+    Low,
+    High(i64),
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> (int64 option, string) result")]
+pub fn serde_level(n: ocaml::Int) -> Result<Result<Option<i64>, String>, ocaml::Error> {
+    let level = if n > 0 {
+        SerdeLevel::High(n as i64)
+    } else {
+        SerdeLevel::Low
+    };
+
+    let v = match ocaml::serde::to_value(gc, &level) {
+        Ok(v) => v,
+        Err(e) => {
+            // `SerdeLevel` doesn't implement `Deserialize`, so it's registered explicitly
+            ocaml::serde::register_enum_variants("SerdeLevel", &["Low", "High"], &[true, false])?;
+            return Ok(Err(e.to_string()));
+        }
+    };
+    Ok(Ok(unsafe {
+        v.is_block().then(|| i64::from_value(v.field(0)))
+    }))
+}
@@ -39,3 +39,71 @@ let%test "testing abstract" = Util.check_leaks (fun () -> (
   let () = file_close f in
   s = s'
 ))
+
+let%test "testing serialize" = Util.check_leaks (fun () -> (
+  let t = testing_serialize_alloc "abc" [| 1L; -2L; Int64.max_int |] in
+  let s = Marshal.to_string (t, t) [] in
+  Util.gc ();
+  let (a, b) : testing_serialize * testing_serialize = Marshal.from_string s 0 in
+  a == b && testing_serialize_get a = ("abc", [| 1L; -2L; Int64.max_int |])
+))
+
+let%test "testing serialize unsupported" = Util.check_leaks (fun () -> (
+  try ignore (Marshal.to_string (testing_alloc 1L) []); false
+  with Invalid_argument _ -> true
+))
+
+let%test "testing derive compare" = Util.check_leaks (fun () -> (
+  let a = testing_derive_alloc 1L "b" in
+  let b = testing_derive_alloc 1L "b" in
+  let c = testing_derive_alloc 2L "a" in
+  a = b && a <> c && compare a c < 0 && compare c a > 0 &&
+  List.sort compare [c; a] = [a; c]
+))
+
+let%test "testing derive hash" = Util.check_leaks (fun () -> (
+  let a = testing_derive_alloc 1L "b" in
+  let b = testing_derive_alloc 1L "b" in
+  let tbl = Hashtbl.create 8 in
+  Hashtbl.add tbl a "value";
+  Hashtbl.hash a = Hashtbl.hash b && Hashtbl.find tbl b = "value" &&
+  Hashtbl.hash a <> Hashtbl.hash (testing_derive_alloc 2L "b")
+))
+
+let%test "testing derive serialize" = Util.check_leaks (fun () -> (
+  let a = testing_derive_alloc 3L "abc" in
+  let b : testing_derive = Marshal.from_string (Marshal.to_string a []) 0 in
+  a = b && testing_derive_name b = "abc"
+))
+
+let%test "testing register before unmarshal" = Util.check_leaks (fun () -> (
+  let s = testing_register_marshalled 5L in
+  let unknown =
+    try ignore (Marshal.from_string s 0 : testing_register); false
+    with Failure _ -> true
+  in
+  testing_register_init ();
+  let t : testing_register = Marshal.from_string s 0 in
+  unknown && testing_register_key t = 5L
+))
+
+let%test "testing mem size" = Util.check_leaks (fun () -> (
+  let before = (Gc.quick_stat ()).major_collections in
+  let total = ref 0 in
+  for _ = 1 to 100 do
+    total := !total + testing_mem_len (testing_mem_alloc (4 * 1024 * 1024))
+  done;
+  !total = 100 * 4 * 1024 * 1024 && (Gc.quick_stat ()).major_collections > before
+))
+
+let%test "testing methods" = Util.check_leaks (fun () -> (
+  let c = TestingCounter.new_ 1 in
+  TestingCounter.add c 2;
+  let d = TestingCounter.new_ 10 in
+  TestingCounter.add_many c d 1 2 3 4;
+  TestingCounter.get c = 23 && TestingCounter.get d = 10
+))
+
+let%test "testing methods acronym" = Util.check_leaks (fun () ->
+  TestingHTTPStatus.is_ok (TestingHTTPStatus.new_ 200)
+  && not (TestingHTTPStatus.is_ok (TestingHTTPStatus.new_ 404)))
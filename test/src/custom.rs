@@ -78,6 +78,215 @@ pub unsafe fn testing_callback_call(
     f(gc, &x)
 }
 
+#[ocaml::sig]
+struct TestingSerialize {
+    name: String,
+    values: Vec<i64>,
+}
+
+impl ocaml::CustomSerialize for TestingSerialize {
+    fn serialize(&self, s: &mut ocaml::custom::Serializer) {
+        s.write_u64(self.name.len() as u64);
+        s.write_bytes(self.name.as_bytes());
+        s.write_u32(self.values.len() as u32);
+        for x in &self.values {
+            s.write_i64(*x);
+        }
+    }
+
+    fn deserialize(d: &mut ocaml::custom::Deserializer) -> Result<Self, String> {
+        let mut name = vec![0; d.read_u64() as usize];
+        d.read_bytes(&mut name);
+        let name = String::from_utf8(name).map_err(|e| e.to_string())?;
+        let values = (0..d.read_u32()).map(|_| d.read_i64()).collect();
+        Ok(TestingSerialize { name, values })
+    }
+}
+
+ocaml::custom!(TestingSerialize: CustomSerialize);
+
+#[ocaml::func]
+#[ocaml::sig("string -> int64 array -> testing_serialize")]
+pub fn testing_serialize_alloc(name: String, values: Vec<i64>) -> ocaml::Pointer<TestingSerialize> {
+    TestingSerialize { name, values }.into()
+}
+
+#[ocaml::func]
+#[ocaml::sig("testing_serialize -> (string * int64 array)")]
+pub fn testing_serialize_get(t: &TestingSerialize) -> (String, Vec<i64>) {
+    (t.name.clone(), t.values.clone())
+}
+
+#[ocaml::sig]
+#[derive(ocaml::Custom, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[custom(
+    name = "rust.TestingDerive",
+    used = 1,
+    max = 1000,
+    serialize,
+    fixed_length
+)]
+struct TestingDerive {
+    key: i64,
+    name: String,
+}
+
+impl ocaml::CustomSerialize for TestingDerive {
+    fn serialize(&self, s: &mut ocaml::custom::Serializer) {
+        s.write_i64(self.key);
+        s.write_u64(self.name.len() as u64);
+        s.write_bytes(self.name.as_bytes());
+    }
+
+    fn deserialize(d: &mut ocaml::custom::Deserializer) -> Result<Self, String> {
+        let key = d.read_i64();
+        let mut name = vec![0; d.read_u64() as usize];
+        d.read_bytes(&mut name);
+        let name = String::from_utf8(name).map_err(|e| e.to_string())?;
+        Ok(TestingDerive { key, name })
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("int64 -> string -> testing_derive")]
+pub fn testing_derive_alloc(key: i64, name: String) -> ocaml::Pointer<TestingDerive> {
+    TestingDerive { key, name }.into()
+}
+
+#[ocaml::func]
+#[ocaml::sig("testing_derive -> string")]
+pub fn testing_derive_name(t: &TestingDerive) -> String {
+    t.name.clone()
+}
+
+#[ocaml::sig]
+#[derive(ocaml::Custom)]
+#[custom(name = "rust.TestingRegister", serialize, no_compare, no_hash)]
+struct TestingRegister(i64);
+
+impl ocaml::CustomSerialize for TestingRegister {
+    fn serialize(&self, s: &mut ocaml::custom::Serializer) {
+        s.write_i64(self.0);
+    }
+
+    fn deserialize(d: &mut ocaml::custom::Deserializer) -> Result<Self, String> {
+        Ok(TestingRegister(d.read_i64()))
+    }
+}
+
+// Builds the marshalled representation of a `TestingRegister` without allocating one, so the
+// custom operations aren't registered as a side effect
+#[ocaml::func]
+#[ocaml::sig("int64 -> string")]
+pub fn testing_register_marshalled(key: i64) -> ocaml::Value {
+    let size = core::mem::size_of::<TestingRegister>();
+    let mut data = vec![0x18]; // CODE_CUSTOM_LEN
+    data.extend_from_slice(b"rust.TestingRegister\0");
+    data.extend_from_slice(&(size as u32).to_be_bytes());
+    data.extend_from_slice(&(size as u64).to_be_bytes());
+    data.extend_from_slice(&key.to_be_bytes());
+
+    // The header and custom operations pointer are followed by the data
+    let words_32 = 2 + size.div_ceil(4);
+    let words_64 = 2 + size.div_ceil(8);
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(&0x8495A6BEu32.to_be_bytes());
+    bytes.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    bytes.extend_from_slice(&1u32.to_be_bytes());
+    bytes.extend_from_slice(&(words_32 as u32).to_be_bytes());
+    bytes.extend_from_slice(&(words_64 as u32).to_be_bytes());
+    bytes.extend_from_slice(&data);
+    unsafe { ocaml::Value::bytes(bytes) }
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> unit")]
+pub fn testing_register_init() {
+    ocaml::custom::register::<TestingRegister>();
+}
+
+#[ocaml::func]
+#[ocaml::sig("testing_register -> int64")]
+pub fn testing_register_key(t: &TestingRegister) -> i64 {
+    t.0
+}
+
+#[ocaml::sig]
+#[derive(ocaml::Custom)]
+#[custom(no_compare, no_hash, mem_size = TestingMem::capacity)]
+struct TestingMem(Vec<u8>);
+
+impl TestingMem {
+    fn capacity(&self) -> usize {
+        self.0.capacity()
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> testing_mem")]
+pub fn testing_mem_alloc(n: usize) -> ocaml::Pointer<TestingMem> {
+    TestingMem(vec![0; n]).into()
+}
+
+#[ocaml::func]
+#[ocaml::sig("testing_mem -> int")]
+pub fn testing_mem_len(t: &TestingMem) -> usize {
+    t.0.len()
+}
+
+#[ocaml::sig]
+#[derive(ocaml::Custom, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct TestingCounter {
+    count: ocaml::Int,
+}
+
+#[ocaml::methods]
+impl TestingCounter {
+    #[ocaml::sig("int -> t")]
+    pub fn new(count: ocaml::Int) -> ocaml::Pointer<Self> {
+        TestingCounter { count }.into()
+    }
+
+    #[ocaml::sig("t -> int -> unit")]
+    pub fn add(&mut self, n: ocaml::Int) {
+        self.count += n;
+    }
+
+    #[ocaml::sig("t -> int")]
+    pub fn get(&self) -> ocaml::Int {
+        self.count
+    }
+
+    #[ocaml::sig("t -> t -> int -> int -> int -> int -> unit")]
+    pub fn add_many(
+        &mut self,
+        other: &TestingCounter,
+        a: ocaml::Int,
+        b: ocaml::Int,
+        c: ocaml::Int,
+        d: ocaml::Int,
+    ) {
+        self.count += other.count + a + b + c + d;
+    }
+}
+
+#[ocaml::sig]
+#[derive(ocaml::Custom, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct TestingHTTPStatus(ocaml::Int);
+
+#[ocaml::methods]
+impl TestingHTTPStatus {
+    #[ocaml::sig("int -> t")]
+    pub fn new(code: ocaml::Int) -> ocaml::Pointer<Self> {
+        TestingHTTPStatus(code).into()
+    }
+
+    #[ocaml::sig("t -> bool")]
+    pub fn is_ok(&self) -> bool {
+        self.0 == 200
+    }
+}
+
 // Abstract
 
 use std::io::Read;
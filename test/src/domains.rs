@@ -0,0 +1,28 @@
+use ocaml::Value;
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int")]
+pub fn domain_current_id() -> ocaml::Int {
+    ocaml::domain::id().expect("not running in an OCaml domain") as ocaml::Int
+}
+
+#[ocaml::func]
+#[ocaml::sig("(unit -> 'a) array -> 'a array")]
+pub fn domain_parallel(fs: Vec<Value>) -> Result<Vec<Value>, ocaml::Error> {
+    ocaml::domain::parallel(gc, &fs)
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> int")]
+pub unsafe fn domain_boxroot_stress(n: ocaml::Int) -> ocaml::Int {
+    let roots: Vec<Value> = (0..n)
+        .map(|i| Value::string(i.to_string()).root())
+        .collect();
+    ocaml::runtime::gc_minor();
+    ocaml::runtime::gc_full_major();
+    roots
+        .iter()
+        .enumerate()
+        .filter(|(i, v)| v.string_val() == i.to_string())
+        .count() as ocaml::Int
+}
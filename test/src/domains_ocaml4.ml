@@ -0,0 +1,9 @@
+open Rust
+
+let%test "domain current id" = domain_current_id () = 0
+
+let%test "domain parallel unsupported" =
+  try
+    let _ = domain_parallel [| (fun () -> 1) |] in
+    false
+  with Failure _ -> true
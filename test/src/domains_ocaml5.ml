@@ -0,0 +1,33 @@
+open Rust
+
+let%test "domain current id" = domain_current_id () = (Domain.self () :> int)
+
+let%test "domain parallel boxroot" =
+  let results = domain_parallel (Array.init 4 (fun _ () -> domain_boxroot_stress 1000)) in
+  Array.for_all (fun n -> n = 1000) results
+
+let%test "domain parallel ids" =
+  let main = domain_current_id () in
+  let ids = domain_parallel (Array.init 4 (fun _ () -> domain_current_id ())) in
+  Array.for_all (fun id -> id <> main) ids
+  && List.length (List.sort_uniq compare (Array.to_list ids)) = 4
+
+let%test "domain parallel pointer" =
+  let counters =
+    domain_parallel
+      (Array.init 4 (fun i () ->
+           let c = TestingCounter.new_ i in
+           TestingCounter.add c (domain_boxroot_stress 100);
+           Gc.full_major ();
+           c))
+  in
+  Gc.full_major ();
+  let ok = ref true in
+  Array.iteri (fun i c -> if TestingCounter.get c <> i + 100 then ok := false) counters;
+  !ok
+
+let%test "domain parallel exception" =
+  try
+    let _ = domain_parallel [| (fun () -> 1); (fun () -> failwith "domain") |] in
+    false
+  with Failure s -> s = "domain"
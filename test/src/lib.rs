@@ -1,5 +1,7 @@
 pub mod callbacks;
 pub mod conv;
 pub mod custom;
+pub mod domains;
+pub mod marshalling;
 pub mod runtime;
 pub mod types;
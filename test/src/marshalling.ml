@@ -0,0 +1,79 @@
+open Rust
+
+type variant = A | B of float | C of string * int
+
+type record = {x: int; y: string; z: variant list}
+
+let reencode v =
+  let s = Marshal.to_string v [] in
+  marshal_reencode s = s
+
+let%test "marshal ints" = Util.check_leaks (fun () ->
+  List.for_all reencode [0; 1; 63; 64; -1; 127; -128; 1000; -32768; 1 lsl 20; 1 lsl 40; max_int; min_int])
+
+let%test "marshal strings" = Util.check_leaks (fun () ->
+  List.for_all (fun n -> reencode (String.make n 'a')) [0; 1; 31; 32; 255; 256; 100000])
+
+let%test "marshal floats" = Util.check_leaks (fun () ->
+  reencode 1.5 && reencode nan && reencode [| 1.0; 2.0 |] && reencode (Array.make 300 3.0) &&
+  reencode (Float.Array.make 3 1.0))
+
+let%test "marshal custom" = Util.check_leaks (fun () ->
+  reencode 1l && reencode Int32.min_int && reencode (-1L) && reencode Int64.max_int &&
+  reencode 1n && reencode Nativeint.min_int)
+
+let%test "marshal structured" = Util.check_leaks (fun () ->
+  reencode (Some (1, "a")) &&
+  reencode {x = 1; y = "abc"; z = [A; B 1.0; C ("c", 3)]} &&
+  reencode (Array.init 1000 (fun i -> (i, string_of_int i))) &&
+  reencode (List.init 10000 (fun i -> i)))
+
+let%test "marshal long list" = Util.check_leaks (fun () ->
+  let l = List.init 1_000_000 (fun i -> i) in
+  let s = Marshal.to_string l [] in
+  let s' = Marshal.to_string (List.rev l) [] in
+  reencode l && marshal_equal s s && not (marshal_equal s s'))
+
+let%test "marshal tags" = Util.check_leaks (fun () ->
+  let b = Obj.new_block 20 1 in
+  Obj.set_field b 0 (Obj.repr 1);
+  reencode b && reencode (Obj.new_block 30 0) && reencode (`Abc 1))
+
+let%test "marshal sharing" = Util.check_leaks (fun () ->
+  let s = "shared" in
+  let rec l = 1 :: 2 :: l in
+  reencode (s, s, [s]) && reencode l)
+
+let%test "marshal describe" = Util.check_leaks (fun () ->
+  let s = Marshal.to_string (1, "a", 2.5, 3l, [| 1.0 |]) [] in
+  marshal_describe s = "Block(0, [Int(1), String([97]), Double(2.5), Int32(3), DoubleArray([1.0])])")
+
+let%test "marshal build" = Util.check_leaks (fun () ->
+  let s = marshal_build () in
+  let (a, b, c, d, e, f, g, h) : string * string * int64 * int32 * nativeint * float array * variant * int = Marshal.from_string s 0 in
+  a == b && a = "abc" && c = -5L && d = Int32.max_int && e = Nativeint.shift_left 1n 40 &&
+  f = [| 1.0; 2.5 |] && g = B 0.5 && h = -100000)
+
+let%test "marshal value" = Util.check_leaks (fun () ->
+  let r = {x = 1; y = "abc"; z = [A; B 1.0; C ("c", 3)]} in
+  let x = (r, r) in
+  marshal_value x false = Marshal.to_string x [] &&
+  marshal_value x true = Marshal.to_string x [Marshal.No_sharing] &&
+  unmarshal_value (marshal_value r false) = r &&
+  (Marshal.from_string (marshal_value [1L; 2L] false) 0 : int64 list) = [1L; 2L])
+
+let%test "marshal value errors" = Util.check_leaks (fun () ->
+  let s = Marshal.to_string (1, "abc") [] in
+  (try ignore (marshal_value (fun x -> x + 1) false); false with Invalid_argument _ -> true) &&
+  (try ignore (unmarshal_value (String.sub s 0 (String.length s - 1))); false with Failure _ -> true) &&
+  (try ignore (unmarshal_value "abc"); false with Failure _ -> true))
+
+let%test "unmarshal value closures" = Util.check_leaks (fun () ->
+  let n = 10 in
+  let f : int -> int = unmarshal_value (Marshal.to_string (fun x -> x + n) [Marshal.Closures]) in
+  let s = Marshal.to_string (1, "abc") [] in
+  let body = String.length s - 4 in
+  let corrupt = String.sub s 0 body ^ "\xff" ^ String.sub s (body + 1) 3 in
+  f 1 = 11 &&
+  (unmarshal_value (Marshal.to_string (1l, 2n) []) : int32 * nativeint) = (1l, 2n) &&
+  (try ignore (unmarshal_value corrupt); false with Failure _ -> true))
@@ -0,0 +1,60 @@
+use ocaml::marshal::{self, Data};
+
+#[ocaml::func]
+#[ocaml::sig("string -> string")]
+pub fn marshal_reencode(s: &[u8]) -> Result<ocaml::Value, ocaml::Error> {
+    let data = marshal::decode(s)?;
+    let bytes = marshal::encode(&data)?;
+    Ok(unsafe { ocaml::Value::bytes(bytes) })
+}
+
+#[ocaml::func]
+#[ocaml::sig("string -> string")]
+pub fn marshal_describe(s: &[u8]) -> Result<String, ocaml::Error> {
+    let data = marshal::decode(s)?;
+    Ok(format!("{data:?}"))
+}
+
+#[ocaml::func]
+#[ocaml::sig("string -> string -> bool")]
+pub fn marshal_equal(a: &[u8], b: &[u8]) -> Result<bool, ocaml::Error> {
+    Ok(marshal::decode(a)? == marshal::decode(b)?)
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> string")]
+pub fn marshal_build() -> Result<ocaml::Value, ocaml::Error> {
+    let data = Data::Block(
+        0,
+        vec![
+            Data::String(b"abc".to_vec()),
+            Data::Shared(1),
+            Data::Int64(-5),
+            Data::Int32(i32::MAX),
+            Data::Nativeint(1 << 40),
+            Data::DoubleArray(vec![1.0, 2.5]),
+            Data::Block(0, vec![Data::Double(0.5)]),
+            Data::Int(-100000),
+        ],
+    );
+    let bytes = marshal::encode(&data)?;
+    Ok(unsafe { ocaml::Value::bytes(bytes) })
+}
+
+#[ocaml::func]
+#[ocaml::sig("'a -> bool -> string")]
+pub fn marshal_value(v: ocaml::Value, no_sharing: bool) -> Result<ocaml::Value, ocaml::Error> {
+    let flags: &[ocaml::MarshalFlag] = if no_sharing {
+        &[ocaml::MarshalFlag::NoSharing]
+    } else {
+        &[]
+    };
+    let bytes = v.marshal(flags)?;
+    Ok(unsafe { ocaml::Value::bytes(bytes) })
+}
+
+#[ocaml::func]
+#[ocaml::sig("string -> 'a")]
+pub fn unmarshal_value(s: &[u8]) -> Result<ocaml::Value, ocaml::Error> {
+    ocaml::Value::unmarshal(gc, s)
+}
@@ -55,6 +55,52 @@ let%test "exn" = Util.check_leaks (fun () -> (
 ))
 
 
+let%test "error to string" = Util.check_leaks (fun () ->
+  error_to_string (fun () -> failwith "x") = "Failure(\"x\")"
+  && error_to_string (fun () -> raise Not_found) = "Not_found"
+  && error_to_string (fun () -> raise (Exc 1.5)) = Printexc.to_string (Exc 1.5)
+)
+
+let%test "error backtrace" =
+  let[@inline never] raise_exc () = raise (Rust "backtrace") in
+  Printexc.record_backtrace true;
+  let bt = error_backtrace raise_exc in
+  Printexc.record_backtrace false;
+  let without = error_backtrace raise_exc in
+  match bt, without with
+  | Some s, None -> String.length s > 0 && String.sub s 0 9 = "Raised at"
+  | _ -> false
+
+exception My_error of int * string
+
+let%test "error exception name" = Util.check_leaks (fun () ->
+  error_exception_name (fun () -> raise Not_found) = Some "Not_found"
+  && error_exception_name (fun () -> invalid_arg "x") = Some "Invalid_argument"
+  && error_exception_name (fun () -> raise (My_error (1, "a"))) = Some (Printexc.exn_slot_name (My_error (1, "a")))
+  && error_is_exception (fun () -> raise (My_error (1, "a"))) "Runtime.My_error"
+  && error_is_exception (fun () -> raise (My_error (1, "a"))) "My_error"
+  && not (error_is_exception (fun () -> raise (My_error (1, "a"))) "Error")
+  && not (error_is_exception (fun () -> raise Not_found) "Failure")
+)
+
+let%test "error payload" = Util.check_leaks (fun () ->
+  error_payload (fun () -> raise (My_error (1, "a"))) = Some (1, "a")
+  && error_payload (fun () -> ()) = None
+)
+
+let%test "error standard exceptions" = Util.check_leaks (fun () ->
+  error_is_not_found (fun () -> raise Not_found)
+  && not (error_is_not_found (fun () -> failwith "x"))
+  && (try apply1 (fun () -> failwith "reraise") (); false with Failure s -> s = "reraise")
+  && (try apply1 (fun () -> raise Not_found) (); false with Not_found -> true)
+)
+
+let%test "into exception" = Util.check_leaks (fun () ->
+  (try raise_test_error 0; false with Test_not_ready -> true)
+  && (try raise_test_error 1; false with Test_code (1, "code") -> true)
+  && (try raise_test_error 2; false with Test_message "message" -> true)
+)
+
 let%test "GC" =
   Random.init 0;
   let test f =
@@ -81,3 +127,151 @@ let%test "test panic" = Util.check_leaks (fun () ->
     | Failure s -> s = "panic" && !panic_detected
     | _ -> false)
 
+let%test "panic policy" = Util.check_leaks (fun () ->
+  Callback.register "rust_panic_hook" ignore;
+  let count = panic_hook_count () in
+  let failure = try test_panic () |> ignore; false with Failure s -> s = "rust panic: XXX" in
+  set_panic_exception true;
+  (* Exceptions raised by the hook are ignored when using PanicPolicy::Exception *)
+  Callback.register "rust_panic_hook" panic_hook;
+  let rust_panic f msg =
+    try f (); false with Rust_panic (m, bt) -> m = msg && String.length bt > 0
+  in
+  let ok =
+    rust_panic (fun () -> ignore (test_panic ())) "XXX"
+    && rust_panic native_func_panic "native_func"
+    && rust_panic bytecode_func_panic "bytecode_func"
+  in
+  set_panic_exception false;
+  Callback.register "rust_panic_hook" panic_hook;
+  failure && ok && panic_hook_count () = count + 3)
+
+let%test "release lock" = Util.check_leaks (fun () ->
+  let counter = ref 0 in
+  let stop = ref false in
+  let t = Thread.create (fun () ->
+    while not !stop do
+      incr counter;
+      Thread.yield ()
+    done) () in
+  let before = !counter in
+  let s = release_lock_sleep 0.5 "done" in
+  let after = !counter in
+  stop := true;
+  Thread.join t;
+  s = "done" && after > before && release_lock_sum 100 = 5050)
+
+let%test "release lock error" = Util.check_leaks (fun () ->
+  release_lock_parse "123" = 123
+  && (try ignore (release_lock_parse ""); false with Invalid_argument s -> s = "empty")
+  && (try ignore (release_lock_parse "abc"); false with Failure _ -> true))
+
+let%test "release lock panic" = Util.check_leaks (fun () ->
+  panic_detected := false;
+  try
+    release_lock_panic ();
+    false
+  with
+    | Failure s -> s = "panic" && !panic_detected
+    | _ -> false)
+
+let%test "without lock" = Util.check_leaks (fun () ->
+  let counter = ref 0 in
+  let stop = ref false in
+  let t = Thread.create (fun () ->
+    while not !stop do
+      incr counter;
+      Thread.yield ()
+    done) () in
+  let before = !counter in
+  let n = without_lock_sum [| 1; 2; 3 |] 0.5 in
+  let after = !counter in
+  stop := true;
+  Thread.join t;
+  n = 6 && after > before)
+
+let%test "without lock panic" = Util.check_leaks (fun () ->
+  panic_detected := false;
+  try
+    without_lock_panic ();
+    false
+  with
+    | Failure s -> s = "panic" && !panic_detected
+    | _ -> false)
+
+let () = Callback.register "thread_callback" (fun x -> x * 2)
+
+let%test "thread registration" = Util.check_leaks (fun () ->
+  thread_registration_sum 8 = 56)
+
+let () = Callback.register "thread_callback_alloc" (fun x ->
+  let l = List.init 1000 (fun _ -> x) in
+  Gc.minor ();
+  List.hd l * 2)
+
+let%test "thread registration nested lock" = Util.check_leaks (fun () ->
+  thread_registration_nested 8 = 56)
+
+let%test "gc stat" = Util.check_leaks (fun () ->
+  let before = Gc.quick_stat () in
+  let (minor_collections, minor_words) = gc_stat_after_minor () in
+  minor_collections > before.minor_collections && minor_words >= before.minor_words)
+
+let%test "gc stat live blocks" = Util.check_leaks (fun () ->
+  gc_stat_live_blocks () > 0)
+
+let%test "gc set" = Util.check_leaks (fun () ->
+  let before = (Gc.get ()).space_overhead in
+  let prev = gc_set_space_overhead (before + 20) in
+  let updated = (Gc.get ()).space_overhead = before + 20 in
+  let _ = gc_set_space_overhead prev in
+  prev = before && updated && (Gc.get ()).space_overhead = before)
+
+let%test "finalise" =
+  let before = finalise_sum () in
+  let () = for i = 1 to 10 do finalise_value (i, string_of_int i) done in
+  Gc.full_major ();
+  Gc.full_major ();
+  finalise_sum () - before = 55
+
+let%test "finalise last" =
+  let before = finalise_sum () in
+  let () = for i = 1 to 10 do finalise_last_value (ref i) done in
+  Gc.full_major ();
+  Gc.full_major ();
+  finalise_sum () - before = 10
+
+let%test "finalise immediate" =
+  try
+    finalise_value 1;
+    false
+  with Invalid_argument _ -> true
+
+let%test "memprof" =
+  if not (memprof_supported ()) then true
+  else begin
+    memprof_start 1.0;
+    let l = ref [] in
+    for i = 0 to 999 do l := ref i :: !l done;
+    Gc.full_major ();
+    let n = List.length !l in
+    l := [];
+    Gc.full_major ();
+    let (allocs, promoted, dealloc) = memprof_stop () in
+    n = 1000 && allocs >= 1000 && promoted > 0 && dealloc > 0
+  end
+
+let%test "memprof start twice" =
+  if not (memprof_supported ()) then true
+  else begin
+    memprof_start 0.0;
+    let twice = try memprof_start 0.0; false with Failure _ -> true in
+    ignore (memprof_stop ());
+    let stopped = try ignore (memprof_stop ()); false with Failure _ -> true in
+    twice && stopped
+  end
+
+let%test "runtime events" =
+  match runtime_events_phases () with
+  | None -> true
+  | Some (b, e) -> b > 0 && e > 0
@@ -78,6 +78,46 @@ pub fn test_panic() -> ocaml::Int {
     panic!("XXX")
 }
 
+static PANIC_HOOK_COUNT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+#[ocaml::func]
+#[ocaml::sig("bool -> unit")]
+pub fn set_panic_exception(enable: bool) {
+    if enable {
+        gc.set_panic_policy(ocaml::PanicPolicy::Exception);
+        gc.set_panic_hook(|_msg, _backtrace| {
+            PANIC_HOOK_COUNT.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        });
+    } else {
+        gc.set_panic_policy(ocaml::PanicPolicy::Failure);
+        gc.clear_panic_hook();
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int")]
+pub fn panic_hook_count() -> ocaml::Int {
+    PANIC_HOOK_COUNT.load(std::sync::atomic::Ordering::SeqCst) as ocaml::Int
+}
+
+#[ocaml::native_func]
+#[ocaml::sig("unit -> unit")]
+pub unsafe fn native_func_panic(unit: ocaml::Raw) -> ocaml::Value {
+    if ocaml::sys::is_long(unit.0) {
+        panic!("native_func")
+    }
+    ocaml::Value::unit()
+}
+
+#[ocaml::bytecode_func]
+#[ocaml::sig("unit -> unit")]
+pub unsafe fn bytecode_func_panic(unit: ocaml::Value) -> ocaml::Value {
+    if unit.is_long() {
+        panic!("bytecode_func")
+    }
+    unit
+}
+
 ocaml::import! {
     fn call_named(g: ocaml::Float) -> ocaml::Float;
 }
@@ -104,6 +144,58 @@ pub unsafe fn exn_to_string(exn: ocaml::Value) -> String {
     exn.exception_to_string().unwrap()
 }
 
+#[ocaml::func]
+#[ocaml::sig("(unit -> unit) -> string")]
+pub unsafe fn error_to_string(f: ocaml::Value) -> String {
+    match f.call1(gc, ocaml::Value::unit()) {
+        Ok(_) => String::new(),
+        Err(e) => e.to_string(),
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("(unit -> unit) -> string option")]
+pub unsafe fn error_backtrace(f: ocaml::Value) -> Option<String> {
+    let err = f.call1(gc, ocaml::Value::unit()).err()?;
+    let bt = err.backtrace()?;
+    if !bt.frames.first()?.is_raise {
+        return None;
+    }
+    Some(bt.to_string())
+}
+
+#[ocaml::func]
+#[ocaml::sig("(unit -> unit) -> string option")]
+pub unsafe fn error_exception_name(f: ocaml::Value) -> Option<String> {
+    f.call1(gc, ocaml::Value::unit()).err()?.exception_name()
+}
+
+#[ocaml::func]
+#[ocaml::sig("(unit -> unit) -> string -> bool")]
+pub unsafe fn error_is_exception(f: ocaml::Value, name: &str) -> bool {
+    match f.call1(gc, ocaml::Value::unit()) {
+        Ok(_) => false,
+        Err(e) => e.is_exception(name),
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("(unit -> unit) -> (int * string) option")]
+pub unsafe fn error_payload(f: ocaml::Value) -> Option<(ocaml::Int, String)> {
+    f.call1(gc, ocaml::Value::unit())
+        .err()?
+        .exception_payload(gc)
+}
+
+#[ocaml::func]
+#[ocaml::sig("(unit -> unit) -> bool")]
+pub unsafe fn error_is_not_found(f: ocaml::Value) -> bool {
+    match f.call1(gc, ocaml::Value::unit()) {
+        Ok(_) => false,
+        Err(e) => matches!(e.caml_error(), Some(ocaml::CamlError::NotFound)),
+    }
+}
+
 #[ocaml::func]
 #[ocaml::sig("unit -> unit")]
 pub unsafe fn gc_minor() {
@@ -127,3 +219,275 @@ pub unsafe fn gc_full_major() {
 pub unsafe fn gc_compact() {
     ocaml::runtime::gc_compact();
 }
+
+#[ocaml::func(release_lock)]
+#[ocaml::sig("int -> int")]
+pub fn release_lock_sum(n: ocaml::Int) -> ocaml::Int {
+    (0..=n).sum()
+}
+
+#[ocaml::func(release_lock)]
+#[ocaml::sig("float -> string -> string")]
+pub fn release_lock_sleep(secs: f64, msg: String) -> String {
+    std::thread::sleep(std::time::Duration::from_secs_f64(secs));
+    msg
+}
+
+#[ocaml::func(release_lock)]
+#[ocaml::sig("string -> int")]
+pub fn release_lock_parse(s: String) -> Result<ocaml::Int, ocaml::OwnedError> {
+    if s.is_empty() {
+        return Err(ocaml::OwnedError::InvalidArgument("empty".to_string()));
+    }
+    Ok(s.parse()?)
+}
+
+#[ocaml::func(release_lock)]
+#[ocaml::sig("unit -> unit")]
+pub fn release_lock_panic() {
+    panic!("release_lock")
+}
+
+#[ocaml::func]
+#[ocaml::sig("int array -> float -> int")]
+pub fn without_lock_sum(data: Vec<ocaml::Int>, secs: f64) -> ocaml::Int {
+    gc.without_lock(move || {
+        std::thread::sleep(std::time::Duration::from_secs_f64(secs));
+        data.iter().sum()
+    })
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> unit")]
+pub fn without_lock_panic() {
+    gc.without_lock(|| panic!("without_lock"))
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> int")]
+pub fn thread_registration_sum(n: ocaml::Int) -> Result<ocaml::Int, ocaml::Error> {
+    let results = gc.without_lock(move || {
+        let threads: Vec<_> = (0..n)
+            .map(|i| {
+                std::thread::spawn(move || -> Result<ocaml::Int, String> {
+                    let _thread = ocaml::ThreadRegistration::new().map_err(|e| format!("{e:?}"))?;
+                    ocaml::Runtime::with_lock(|_| unsafe {
+                        let f = ocaml::Value::named("thread_callback")
+                            .ok_or("thread_callback is not registered")?;
+                        ocaml::Runtime::with_lock(|rt| {
+                            f.call1(rt, i)
+                                .map(<ocaml::Int as ocaml::FromValue>::from_value)
+                                .map_err(|e| format!("{e:?}"))
+                        })
+                    })
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .collect::<Result<Vec<_>, _>>()
+    });
+
+    match results {
+        Ok(x) => Ok(x.into_iter().sum()),
+        Err(e) => Err(ocaml::Error::Error(e.into())),
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> int")]
+pub fn thread_registration_nested(n: ocaml::Int) -> Result<ocaml::Int, ocaml::Error> {
+    let results = gc.without_lock(move || {
+        let threads: Vec<_> = (0..n)
+            .map(|i| {
+                std::thread::spawn(move || -> Result<ocaml::Int, String> {
+                    let _thread = ocaml::ThreadRegistration::new().map_err(|e| format!("{e:?}"))?;
+                    ocaml::Runtime::with_lock(|rt| {
+                        // The inner call has to reacquire the lock released by `without_lock`
+                        rt.without_lock(|| {
+                            ocaml::Runtime::with_lock(|rt| unsafe {
+                                let f = ocaml::Value::named("thread_callback_alloc")
+                                    .ok_or("thread_callback_alloc is not registered")?;
+                                f.call1(rt, i)
+                                    .map(<ocaml::Int as ocaml::FromValue>::from_value)
+                                    .map_err(|e| format!("{e:?}"))
+                            })
+                        })
+                    })
+                })
+            })
+            .collect();
+        threads
+            .into_iter()
+            .map(|t| t.join().unwrap())
+            .collect::<Result<Vec<_>, _>>()
+    });
+
+    match results {
+        Ok(x) => Ok(x.into_iter().sum()),
+        Err(e) => Err(ocaml::Error::Error(e.into())),
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int * float")]
+pub fn gc_stat_after_minor() -> (ocaml::Int, f64) {
+    ocaml::runtime::gc_minor();
+    let stat = ocaml::runtime::gc_quick_stat();
+    (stat.minor_collections as ocaml::Int, stat.minor_words)
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int")]
+pub fn gc_stat_live_blocks() -> ocaml::Int {
+    ocaml::runtime::gc_stat().live_blocks as ocaml::Int
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> int")]
+pub fn gc_set_space_overhead(n: ocaml::Int) -> ocaml::Int {
+    let mut control = ocaml::runtime::gc_get();
+    let prev = control.space_overhead;
+    control.space_overhead = n as usize;
+    ocaml::runtime::gc_set(&control);
+    prev as ocaml::Int
+}
+
+static FINALISED: std::sync::atomic::AtomicIsize = std::sync::atomic::AtomicIsize::new(0);
+
+#[ocaml::func]
+#[ocaml::sig("'a -> unit")]
+pub fn finalise_value(v: Value) -> Result<(), ocaml::Error> {
+    v.finalise(gc, |v| {
+        let id = unsafe { v.field(0).int_val() };
+        FINALISED.fetch_add(id, std::sync::atomic::Ordering::SeqCst);
+    })
+}
+
+#[ocaml::func]
+#[ocaml::sig("'a -> unit")]
+pub fn finalise_last_value(v: Value) -> Result<(), ocaml::Error> {
+    v.finalise_last(gc, || {
+        FINALISED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    })
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int")]
+pub fn finalise_sum() -> ocaml::Int {
+    FINALISED.load(std::sync::atomic::Ordering::SeqCst)
+}
+
+static MEMPROF_ALLOC: std::sync::atomic::AtomicIsize = std::sync::atomic::AtomicIsize::new(0);
+static MEMPROF_PROMOTE: std::sync::atomic::AtomicIsize = std::sync::atomic::AtomicIsize::new(0);
+static MEMPROF_DEALLOC: std::sync::atomic::AtomicIsize = std::sync::atomic::AtomicIsize::new(0);
+
+#[ocaml::func]
+#[ocaml::sig("unit -> bool")]
+pub fn memprof_supported() -> bool {
+    ocaml::memprof::is_supported()
+}
+
+#[ocaml::func]
+#[ocaml::sig("float -> unit")]
+pub fn memprof_start(rate: f64) -> Result<(), ocaml::Error> {
+    use std::sync::atomic::Ordering;
+    MEMPROF_ALLOC.store(0, Ordering::SeqCst);
+    MEMPROF_PROMOTE.store(0, Ordering::SeqCst);
+    MEMPROF_DEALLOC.store(0, Ordering::SeqCst);
+
+    let tracker = ocaml::memprof::Tracker::new()
+        .alloc_minor(|_, info| {
+            MEMPROF_ALLOC.fetch_add(info.n_samples as isize, Ordering::SeqCst);
+            true
+        })
+        .promote(|_, _| {
+            MEMPROF_PROMOTE.fetch_add(1, Ordering::SeqCst);
+            true
+        })
+        .dealloc_major(|_, _| {
+            MEMPROF_DEALLOC.fetch_add(1, Ordering::SeqCst);
+        });
+    ocaml::memprof::start(gc, rate, 16, tracker)
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int * int * int")]
+pub fn memprof_stop() -> Result<(ocaml::Int, ocaml::Int, ocaml::Int), ocaml::Error> {
+    use std::sync::atomic::Ordering;
+    ocaml::memprof::stop(gc)?;
+    Ok((
+        MEMPROF_ALLOC.load(Ordering::SeqCst),
+        MEMPROF_PROMOTE.load(Ordering::SeqCst),
+        MEMPROF_DEALLOC.load(Ordering::SeqCst),
+    ))
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> (int * int) option")]
+pub fn runtime_events_phases() -> Result<Option<(ocaml::Int, ocaml::Int)>, ocaml::Error> {
+    if !ocaml::runtime_events::is_supported() {
+        return Ok(None);
+    }
+
+    ocaml::runtime_events::start(gc)?;
+    let mut cursor = ocaml::runtime_events::Cursor::current()?;
+    cursor.read_poll(0, |_| ())?;
+
+    ocaml::runtime::gc_minor();
+    let (mut begin, mut end) = (0, 0);
+    cursor.read_poll(0, |ev| match ev.kind {
+        ocaml::runtime_events::EventKind::PhaseBegin(_) => begin += 1,
+        ocaml::runtime_events::EventKind::PhaseEnd(_) => end += 1,
+        _ => (),
+    })?;
+    Ok(Some((begin, end)))
+}
+
+#[ocaml::func]
+#[ocaml::sig("(unit -> unit) -> (string * int) array")]
+pub fn runtime_events_user(
+    f: ocaml::Callback<(), ()>,
+) -> Result<Vec<(String, ocaml::Int)>, ocaml::Error> {
+    let mut cursor = ocaml::runtime_events::Cursor::current()?;
+    cursor.read_poll(0, |_| ())?;
+
+    f.call(gc, ())?;
+    let mut events = Vec::new();
+    cursor.read_poll_user(gc, 0, |ev| {
+        if let ocaml::runtime_events::EventKind::User {
+            name,
+            data: ocaml::runtime_events::UserData::Int(i),
+        } = ev.kind
+        {
+            events.push((name, i as ocaml::Int));
+        }
+    })?;
+    Ok(events)
+}
+
+#[derive(ocaml::IntoException)]
+pub enum TestError {
+    #[exception(name = "Test_not_ready")]
+    NotReady,
+
+    #[exception(name = "Test_code", sig = "int * string")]
+    Code(ocaml::Int, String),
+
+    #[exception(name = "Test_message", sig = "string")]
+    Message { msg: String },
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> unit")]
+pub fn raise_test_error(i: ocaml::Int) -> Result<(), ocaml::Error> {
+    let err = match i {
+        0 => TestError::NotReady,
+        1 => TestError::Code(i, "code".to_string()),
+        _ => TestError::Message {
+            msg: "message".to_string(),
+        },
+    };
+    Err(ocaml::IntoException::into_exception(err, gc))
+}
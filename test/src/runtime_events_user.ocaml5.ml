@@ -0,0 +1,12 @@
+open Rust
+
+type Runtime_events.User.tag += Ocamlrs_test
+
+let%test "runtime events user" =
+  let ev =
+    Runtime_events.User.register "ocamlrs.test" Ocamlrs_test
+      Runtime_events.Type.int
+  in
+  Runtime_events.start ();
+  let events = runtime_events_user (fun () -> Runtime_events.User.write ev 42) in
+  Array.mem ("ocamlrs.test", 42) events
@@ -2,11 +2,41 @@
 
 open! Bigarray
 
+external ocaml_rs_closure_call: 'a -> 'b -> 'c = "ocaml_rs_closure_call"
+let () = Callback.register "ocaml_rs_closure" ocaml_rs_closure_call
+let () = Callback.register "ocaml_rs_closure2" (fun env x0 x1 -> ocaml_rs_closure_call env (x0, x1))
+let () = Callback.register "ocaml_rs_closure3" (fun env x0 x1 x2 -> ocaml_rs_closure_call env (x0, x1, x2))
+let () = Callback.register "ocaml_rs_closure4" (fun env x0 x1 x2 x3 -> ocaml_rs_closure_call env (x0, x1, x2, x3))
+let () = Callback.register "ocaml_rs_closure5" (fun env x0 x1 x2 x3 x4 -> ocaml_rs_closure_call env (x0, x1, x2, x3, x4))
+let () = Callback.register "ocaml_rs_closure6" (fun env x0 x1 x2 x3 x4 x5 -> ocaml_rs_closure_call env (x0, x1, x2, x3, x4, x5))
+
+let () = Callback.register "ocaml_rs_seq_memo" (fun f -> let l = lazy (f ()) in fun () -> Lazy.force l)
+
+let () = Callback.register "ocaml_rs_input" input
+let () = Callback.register "ocaml_rs_output" output_substring
+let () = Callback.register "ocaml_rs_flush" flush
+
+exception Rust_panic of string * string
+let () = Callback.register_exception "Rust_panic" (Rust_panic ("", ""))
+
 (* file: callbacks.rs *)
 
 external apply1: ('a -> 'b) -> 'a -> 'b = "apply1"
 external apply3: ('a -> 'b) -> 'a -> 'b = "apply3"
 external apply_range: (int list -> 'a) -> int -> int -> 'a = "apply_range"
+external callback_register: (int -> string -> unit) -> unit = "callback_register"
+external callback_fire: int -> unit = "callback_fire"
+external callback_clear: unit -> unit = "callback_clear"
+external callback_curried: (int -> int -> int) -> int = "callback_curried"
+external callback_call2: (int -> int -> int) -> int = "callback_call2"
+external callback_call2_checked: (int -> int -> int) -> int = "callback_call2_checked"
+external callback_unit: (unit -> string) -> string = "callback_unit"
+external closure_add: unit -> (int -> int -> int) = "closure_add"
+external closure_compare_len: unit -> (string -> string -> int) = "closure_compare_len"
+external closure_counter: unit -> (unit -> int) = "closure_counter"
+external closure_checked_div: unit -> (int -> int) = "closure_checked_div"
+external closure_with_drop: unit -> (int -> int) = "closure_with_drop"
+external closures_dropped: unit -> int = "closures_dropped"
 
 (* file: conv.rs *)
 
@@ -14,6 +44,9 @@ type enum1 = Empty | First of int | Second of string array
 type struct1 = {a: int; b: float; mutable c: string option; d: string array option;}
 type all_float_struct = {float_a: float; float_b: float}
 type float_array_t = {fa: floatarray} [@@unboxed]
+type serde_shape = Circle of float | Point | Rect of float * float
+type serde_record = {label: string; values: int32 array; tags: (string * int64) list; parent: string option; shape: serde_shape}
+type serde_point = {px: float; py: float}
 external enum1_empty: unit -> enum1 = "enum1_empty"
 external enum1_first: int -> enum1 = "enum1_first"
 external enum1_make_second: string -> enum1 = "enum1_make_second"
@@ -41,11 +74,26 @@ external make_float_array_f32_012: unit -> float array = "make_float_array_f32_0
 external make_floatarray_f32_012: unit -> floatarray = "make_floatarray_f32_012"
 external make_float_array_f64_012: unit -> float array = "make_float_array_f64_012"
 external make_floatarray_f64_012: unit -> floatarray = "make_floatarray_f64_012"
+external enum1_is_empty_checked: enum1 -> bool = "enum1_is_empty_checked"
+external struct1_get_c_checked: struct1 -> string option = "struct1_get_c_checked"
+external all_float_struct_sum_checked: all_float_struct -> float = "all_float_struct_sum_checked"
+external add_length_checked: int -> string -> int = "add_length_checked"
+external serde_record_update: serde_record -> serde_record = "serde_record_update"
+external serde_point_swap: serde_point -> serde_point = "serde_point_swap"
+external serde_list_sum: int list -> int64 list = "serde_list_sum"
+external serde_record_error: 'a -> string = "serde_record_error"
+external serde_level: int -> (int64 option, string) result = "serde_level"
 
 (* file: custom.rs *)
 
 type testing
 type testing_callback
+type testing_serialize
+type testing_derive
+type testing_register
+type testing_mem
+type testing_counter
+type testing_http_status
 type file
 external testing_alloc: int64 -> testing = "testing_alloc"
 external testing_set_c: testing -> string -> unit = "testing_set_c"
@@ -53,12 +101,56 @@ external testing_set_a: testing -> float -> unit = "testing_set_a"
 external testing_get_values: testing -> (float * int64 * string) = "testing_get_values"
 external testing_callback_alloc: (int -> float) -> testing_callback = "testing_callback_alloc"
 external testing_callback_call: testing_callback -> int -> float = "testing_callback_call"
+external testing_serialize_alloc: string -> int64 array -> testing_serialize = "testing_serialize_alloc"
+external testing_serialize_get: testing_serialize -> (string * int64 array) = "testing_serialize_get"
+external testing_derive_alloc: int64 -> string -> testing_derive = "testing_derive_alloc"
+external testing_derive_name: testing_derive -> string = "testing_derive_name"
+external testing_register_marshalled: int64 -> string = "testing_register_marshalled"
+external testing_register_init: unit -> unit = "testing_register_init"
+external testing_register_key: testing_register -> int64 = "testing_register_key"
+external testing_mem_alloc: int -> testing_mem = "testing_mem_alloc"
+external testing_mem_len: testing_mem -> int = "testing_mem_len"
 external file_open: string -> file = "file_open"
 external file_read: file -> string = "file_read"
 external file_close: file -> unit = "file_close"
 
+module TestingCounter = struct
+  type t = testing_counter
+  external new_: int -> t = "testing_counter_new"
+  external add: t -> int -> unit = "testing_counter_add"
+  external get: t -> int = "testing_counter_get"
+  external add_many: t -> t -> int -> int -> int -> int -> unit = "testing_counter_add_many_bytecode" "testing_counter_add_many"
+end
+
+module TestingHTTPStatus = struct
+  type t = testing_http_status
+  external new_: int -> t = "testing_http_status_new"
+  external is_ok: t -> bool = "testing_http_status_is_ok"
+end
+
+(* file: domains.rs *)
+
+external domain_current_id: unit -> int = "domain_current_id"
+external domain_parallel: (unit -> 'a) array -> 'a array = "domain_parallel"
+external domain_boxroot_stress: int -> int = "domain_boxroot_stress"
+
+(* file: marshalling.rs *)
+
+external marshal_reencode: string -> string = "marshal_reencode"
+external marshal_describe: string -> string = "marshal_describe"
+external marshal_equal: string -> string -> bool = "marshal_equal"
+external marshal_build: unit -> string = "marshal_build"
+external marshal_value: 'a -> bool -> string = "marshal_value"
+external unmarshal_value: string -> 'a = "unmarshal_value"
+
 (* file: runtime.rs *)
 
+exception Test_not_ready
+let () = Callback.register_exception "Test_not_ready" (Obj.magic [%extension_constructor Test_not_ready] : exn)
+exception Test_code of int * string
+let () = Callback.register_exception "Test_code" (Obj.magic [%extension_constructor Test_code] : exn)
+exception Test_message of string
+let () = Callback.register_exception "Test_message" (Obj.magic [%extension_constructor Test_message] : exn)
 external more_than_five_params: float -> float -> float -> float -> float -> float -> float -> float = "more_than_five_params_bytecode" "more_than_five_params"
 external mutable_parameter_with_more_than_five_arguments: bool -> bool -> int64 -> int64 -> int64 option -> int32 option -> unit = "mutable_parameter_with_more_than_five_arguments_bytecode" "mutable_parameter_with_more_than_five_arguments"
 external raise_exc: float -> unit = "raise_exc"
@@ -66,14 +158,44 @@ external raise_failure: unit -> unit = "raise_failure"
 external hash_variant_abc: int -> [`Abc of int] = "hash_variant_abc"
 external hash_variant_def: float -> [`Def of float] = "hash_variant_def"
 external test_panic: unit -> int = "test_panic"
+external set_panic_exception: bool -> unit = "set_panic_exception"
+external panic_hook_count: unit -> int = "panic_hook_count"
+external native_func_panic: unit -> unit = "native_func_panic"
+external bytecode_func_panic: unit -> unit = "bytecode_func_panic"
 external test_call_named: float -> float = "test_call_named"
 external bench_func: unit -> unit = "bench_func"
 external bench_native_func: unit -> unit = "bench_native_func"
 external exn_to_string: exn -> string = "exn_to_string"
+external error_to_string: (unit -> unit) -> string = "error_to_string"
+external error_backtrace: (unit -> unit) -> string option = "error_backtrace"
+external error_exception_name: (unit -> unit) -> string option = "error_exception_name"
+external error_is_exception: (unit -> unit) -> string -> bool = "error_is_exception"
+external error_payload: (unit -> unit) -> (int * string) option = "error_payload"
+external error_is_not_found: (unit -> unit) -> bool = "error_is_not_found"
 external gc_minor: unit -> unit = "gc_minor"
 external gc_major: unit -> unit = "gc_major"
 external gc_full_major: unit -> unit = "gc_full_major"
 external gc_compact: unit -> unit = "gc_compact"
+external release_lock_sum: int -> int = "release_lock_sum"
+external release_lock_sleep: float -> string -> string = "release_lock_sleep"
+external release_lock_parse: string -> int = "release_lock_parse"
+external release_lock_panic: unit -> unit = "release_lock_panic"
+external without_lock_sum: int array -> float -> int = "without_lock_sum"
+external without_lock_panic: unit -> unit = "without_lock_panic"
+external thread_registration_sum: int -> int = "thread_registration_sum"
+external thread_registration_nested: int -> int = "thread_registration_nested"
+external gc_stat_after_minor: unit -> int * float = "gc_stat_after_minor"
+external gc_stat_live_blocks: unit -> int = "gc_stat_live_blocks"
+external gc_set_space_overhead: int -> int = "gc_set_space_overhead"
+external finalise_value: 'a -> unit = "finalise_value"
+external finalise_last_value: 'a -> unit = "finalise_last_value"
+external finalise_sum: unit -> int = "finalise_sum"
+external memprof_supported: unit -> bool = "memprof_supported"
+external memprof_start: float -> unit = "memprof_start"
+external memprof_stop: unit -> int * int * int = "memprof_stop"
+external runtime_events_phases: unit -> (int * int) option = "runtime_events_phases"
+external runtime_events_user: (unit -> unit) -> (string * int) array = "runtime_events_user"
+external raise_test_error: int -> unit = "raise_test_error"
 
 (* file: types.rs *)
 
@@ -87,3 +209,20 @@ external array1_of_string: string -> (int, int8_unsigned_elt, c_layout) Array1.t
 external array1_new: int -> init:int -> (int, int8_unsigned_elt, c_layout) Array1.t = "array1_new"
 external array1_from_rust_vec: unit -> (float, float32_elt, c_layout) Array1.t = "array1_from_rust_vec"
 external seq_sum: int Seq.t -> int = "seq_sum"
+external weak_create: int -> 'a Weak.t = "weak_create"
+external weak_set: 'a Weak.t -> int -> 'a option -> unit = "weak_set"
+external weak_get: 'a Weak.t -> int -> 'a option = "weak_get"
+external weak_check: 'a Weak.t -> int -> bool = "weak_check"
+external weak_length: 'a Weak.t -> int = "weak_length"
+external ephemeron_create: 'a -> 'b -> ('a, 'b) Ephemeron.K1.t = "ephemeron_create"
+external ephemeron_get_key: ('a, 'b) Ephemeron.K1.t -> 'a option = "ephemeron_get_key"
+external ephemeron_get_data: ('a, 'b) Ephemeron.K1.t -> 'b option = "ephemeron_get_data"
+external ephemeron_check_key: ('a, 'b) Ephemeron.K1.t -> bool = "ephemeron_check_key"
+external ephemeron_unset_data: ('a, 'b) Ephemeron.K1.t -> unit = "ephemeron_unset_data"
+external channel_count_lines: in_channel -> int = "channel_count_lines"
+external channel_read_exact: in_channel -> int -> string = "channel_read_exact"
+external channel_write_lines: out_channel -> int -> unit = "channel_write_lines"
+external seq_from_iter_range: int -> int Seq.t = "seq_from_iter_range"
+external seq_from_iter_strings: unit -> string Seq.t = "seq_from_iter_strings"
+external seq_from_iter_infinite: unit -> int Seq.t = "seq_from_iter_infinite"
+external seq_from_iter_stats: unit -> int * int = "seq_from_iter_stats"
@@ -101,3 +101,103 @@ let%test "seq sum" = Util.check_leaks (fun () ->
   let sum' = List.fold_left ( + ) 0 l in
   sum = sum'
 )
+
+let%test "weak" = Util.check_leaks (fun () ->
+  let w = weak_create 3 in
+  let kept = ref 2 in
+  weak_set w 0 (Some (ref 1));
+  weak_set w 1 (Some kept);
+  Util.gc ();
+  let ok =
+    weak_length w = 3
+    && not (weak_check w 0)
+    && weak_get w 0 = None
+    && weak_get w 1 = Some kept
+    && weak_get w 2 = None
+  in
+  weak_set w 1 None;
+  ok && not (weak_check w 1)
+)
+
+let%test "weak out of bounds" = Util.check_leaks (fun () ->
+  let w = weak_create 1 in
+  try
+    let _ = weak_get w 1 in
+    false
+  with Invalid_argument _ -> true
+)
+
+let%test "ephemeron" = Util.check_leaks (fun () ->
+  let key = ref 1 in
+  let e = ephemeron_create key "data" in
+  Util.gc ();
+  let alive = ephemeron_check_key e && ephemeron_get_data e = Some "data" && ephemeron_get_key e = Some key in
+  ephemeron_unset_data e;
+  alive && ephemeron_get_data e = None && !(Sys.opaque_identity key) = 1
+)
+
+let%test "ephemeron collected" = Util.check_leaks (fun () ->
+  let e = ephemeron_create (ref 1) (Bytes.make 16 'a') in
+  Util.gc ();
+  not (ephemeron_check_key e) && ephemeron_get_key e = None && ephemeron_get_data e = None
+)
+
+let%test "channels" = Util.check_leaks (fun () ->
+  let path = Filename.temp_file "ocaml-rs" ".txt" in
+  let oc = open_out path in
+  channel_write_lines oc 1000;
+  close_out oc;
+  let ic = open_in path in
+  let n = channel_count_lines ic in
+  close_in ic;
+  let ic = open_in path in
+  let first = channel_read_exact ic 7 in
+  let rest = input_line ic in
+  let second = input_line ic in
+  close_in ic;
+  Sys.remove path;
+  n = 1000 && first = "line 0\n" && rest = "line 1" && second = "line 2"
+)
+
+let%test "channel errors" = Util.check_leaks (fun () ->
+  let path = Filename.temp_file "ocaml-rs" ".txt" in
+  let oc = open_out path in
+  close_out oc;
+  let ic = open_in path in
+  close_in ic;
+  Sys.remove path;
+  let write_failed = try channel_write_lines oc 10; false with Failure _ -> true in
+  let read_failed = try ignore (channel_read_exact ic 7); false with Failure _ -> true in
+  write_failed && read_failed
+)
+
+let rec seq_take n s =
+  if n = 0 then []
+  else match s () with
+    | Seq.Nil -> []
+    | Seq.Cons (x, next) -> x :: seq_take (n - 1) next
+
+let%test "seq from iter" = Util.check_leaks (fun () ->
+  List.of_seq (seq_from_iter_range 5) = [0; 2; 4; 6; 8]
+  && List.of_seq (seq_from_iter_range 0) = []
+  && List.of_seq (seq_from_iter_strings ()) = ["a"; "b"; "c"])
+
+let%test "seq from iter persistent" = Util.check_leaks (fun () ->
+  let s = seq_from_iter_range 3 in
+  let first = List.of_seq s in
+  let second = List.of_seq s in
+  first = [0; 2; 4] && second = first && List.of_seq (Seq.take 2 s) = [0; 2])
+
+let%test "seq from iter lazy" =
+  let pulled, dropped = seq_from_iter_stats () in
+  let[@inline never] take () =
+    let s = seq_from_iter_infinite () in
+    let before, _ = seq_from_iter_stats () in
+    let l = seq_take 3 s in
+    before = pulled && l = [0; 1; 2]
+  in
+  let ok = take () in
+  Gc.full_major ();
+  Gc.full_major ();
+  let pulled', dropped' = seq_from_iter_stats () in
+  ok && pulled' = pulled + 3 && dropped' = dropped + 1
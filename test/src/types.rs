@@ -133,3 +133,145 @@ pub unsafe fn seq_sum(seq: ocaml::Seq<ocaml::Int>) -> Result<ocaml::Int, ocaml::
     }
     Ok(sum)
 }
+
+#[ocaml::func]
+#[ocaml::sig("int -> 'a Weak.t")]
+pub fn weak_create(n: ocaml::Int) -> ocaml::Weak<Value> {
+    ocaml::Weak::create(gc, n as usize)
+}
+
+#[ocaml::func]
+#[ocaml::sig("'a Weak.t -> int -> 'a option -> unit")]
+pub fn weak_set(
+    mut w: ocaml::Weak<Value>,
+    i: ocaml::Int,
+    x: Option<Value>,
+) -> Result<(), ocaml::Error> {
+    w.set(gc, i as usize, x.as_ref())
+}
+
+#[ocaml::func]
+#[ocaml::sig("'a Weak.t -> int -> 'a option")]
+pub fn weak_get(w: ocaml::Weak<Value>, i: ocaml::Int) -> Result<Option<Value>, ocaml::Error> {
+    w.get(i as usize)
+}
+
+#[ocaml::func]
+#[ocaml::sig("'a Weak.t -> int -> bool")]
+pub fn weak_check(w: ocaml::Weak<Value>, i: ocaml::Int) -> Result<bool, ocaml::Error> {
+    w.check(i as usize)
+}
+
+#[ocaml::func]
+#[ocaml::sig("'a Weak.t -> int")]
+pub fn weak_length(w: ocaml::Weak<Value>) -> ocaml::Int {
+    w.len() as ocaml::Int
+}
+
+#[ocaml::func]
+#[ocaml::sig("'a -> 'b -> ('a, 'b) Ephemeron.K1.t")]
+pub fn ephemeron_create(k: Value, v: Value) -> ocaml::Ephemeron<Value, Value> {
+    let mut e = ocaml::Ephemeron::create(gc);
+    e.set_key(gc, &k);
+    e.set_data(gc, &v);
+    e
+}
+
+#[ocaml::func]
+#[ocaml::sig("('a, 'b) Ephemeron.K1.t -> 'a option")]
+pub fn ephemeron_get_key(e: ocaml::Ephemeron<Value, Value>) -> Option<Value> {
+    e.get_key()
+}
+
+#[ocaml::func]
+#[ocaml::sig("('a, 'b) Ephemeron.K1.t -> 'b option")]
+pub fn ephemeron_get_data(e: ocaml::Ephemeron<Value, Value>) -> Option<Value> {
+    e.get_data()
+}
+
+#[ocaml::func]
+#[ocaml::sig("('a, 'b) Ephemeron.K1.t -> bool")]
+pub fn ephemeron_check_key(e: ocaml::Ephemeron<Value, Value>) -> bool {
+    e.check_key()
+}
+
+#[ocaml::func]
+#[ocaml::sig("('a, 'b) Ephemeron.K1.t -> unit")]
+pub fn ephemeron_unset_data(mut e: ocaml::Ephemeron<Value, Value>) {
+    e.unset_data()
+}
+
+#[ocaml::func]
+#[ocaml::sig("in_channel -> int")]
+pub fn channel_count_lines(ic: ocaml::InChannel) -> ocaml::Int {
+    use std::io::BufRead;
+    ic.lines().count() as ocaml::Int
+}
+
+#[ocaml::func]
+#[ocaml::sig("in_channel -> int -> string")]
+pub fn channel_read_exact(mut ic: ocaml::InChannel, n: ocaml::Int) -> Result<String, ocaml::Error> {
+    use std::io::Read;
+    let mut buf = vec![0; n as usize];
+    ic.read_exact(&mut buf)?;
+    Ok(String::from_utf8_lossy(&buf).into_owned())
+}
+
+#[ocaml::func]
+#[ocaml::sig("out_channel -> int -> unit")]
+pub fn channel_write_lines(mut oc: ocaml::OutChannel, n: ocaml::Int) -> Result<(), ocaml::Error> {
+    use std::io::Write;
+    for i in 0..n {
+        writeln!(oc, "line {i}")?;
+    }
+    oc.flush()?;
+    Ok(())
+}
+
+static SEQ_PULLED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+static SEQ_DROPPED: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+struct SeqIter(ocaml::Int);
+
+impl Iterator for SeqIter {
+    type Item = ocaml::Int;
+
+    fn next(&mut self) -> Option<ocaml::Int> {
+        SEQ_PULLED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        self.0 += 1;
+        Some(self.0 - 1)
+    }
+}
+
+impl Drop for SeqIter {
+    fn drop(&mut self) {
+        SEQ_DROPPED.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[ocaml::func]
+#[ocaml::sig("int -> int Seq.t")]
+pub fn seq_from_iter_range(n: ocaml::Int) -> Result<ocaml::Seq<ocaml::Int>, ocaml::Error> {
+    ocaml::Seq::from_iter(gc, (0..n).map(|x| x * 2))
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> string Seq.t")]
+pub fn seq_from_iter_strings() -> Result<ocaml::Seq<String>, ocaml::Error> {
+    ocaml::Seq::from_iter(gc, ["a", "b", "c"].into_iter().map(String::from))
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int Seq.t")]
+pub fn seq_from_iter_infinite() -> Result<ocaml::Seq<ocaml::Int>, ocaml::Error> {
+    ocaml::Seq::from_iter(gc, SeqIter(0))
+}
+
+#[ocaml::func]
+#[ocaml::sig("unit -> int * int")]
+pub fn seq_from_iter_stats() -> (ocaml::Int, ocaml::Int) {
+    (
+        SEQ_PULLED.load(std::sync::atomic::Ordering::SeqCst) as ocaml::Int,
+        SEQ_DROPPED.load(std::sync::atomic::Ordering::SeqCst) as ocaml::Int,
+    )
+}